@@ -1,6 +1,8 @@
 #ifndef _MOTHRA_C_H_
 #define _MOTHRA_C_H_
 
+#include <stdbool.h>
+
 #ifdef _WIN64
    #define EXPORT __declspec(dllexport)
    #define IMPORT __declspec(dllimport)
@@ -13,21 +15,105 @@
 extern "C" {
 #endif
 
+// Gossip validation results accepted by validate_gossip_message
+#define GOSSIP_ACCEPT 0
+#define GOSSIP_REJECT 1
+#define GOSSIP_IGNORE 2
+
+// Values of req_resp passed to receive_rpc. A stream end follows the response chunks of a
+// streaming method, and has no data
+#define RPC_REQUEST 0
+#define RPC_RESPONSE 1
+#define RPC_STREAM_END 2
+
+// Response codes of send_rpc_error_response and receive_rpc_error
+#define RPC_INVALID_REQUEST 2
+#define RPC_SERVER_ERROR 3
+#define RPC_UNKNOWN_ERROR 255
+
+// Kinds of failure passed to rpc_failed
+#define RPC_FAILED_TIMEOUT 0
+#define RPC_FAILED_UNSUPPORTED_PROTOCOL 1
+#define RPC_FAILED_DISCONNECTED 2
+#define RPC_FAILED_IO 3
+#define RPC_FAILED_OTHER 4
+
+// Reasons of disconnect_peer and receive_goodbye
+#define GOODBYE_CLIENT_SHUTDOWN 1
+#define GOODBYE_IRRELEVANT_NETWORK 2
+#define GOODBYE_FAULT 3
+#define GOODBYE_TOO_MANY_PEERS 129
+
 EXPORT void network_start(char**, int, char**, int);
 EXPORT void send_gossip(unsigned char*, int, unsigned char*, int);
-EXPORT void send_rpc_request(unsigned char*, int, unsigned char*, int, unsigned char*, int);
-EXPORT void send_rpc_response(unsigned char*, int, unsigned char*, int, unsigned char*, int);
+// The functions taking a peer id return false, or a request id of zero, if it is invalid
+EXPORT bool validate_gossip_message(unsigned char*, int, unsigned char*, int, int);
+// Blocks until the subscription is processed; do not call from within a handler
+EXPORT bool subscribe_gossip(unsigned char*, int);
+EXPORT bool unsubscribe_gossip(unsigned char*, int);
+EXPORT long long send_rpc_request(unsigned char*, int, unsigned char*, int, unsigned char*, int);
+EXPORT bool send_rpc_response(unsigned char*, int, unsigned char*, int, long long, unsigned char*, int);
+// Ends the response stream of a streaming method, after its chunks are sent with send_rpc_response
+EXPORT bool send_rpc_stream_end(unsigned char*, int, unsigned char*, int, long long);
+// Responds to a request with an error code and message, instead of a response
+EXPORT bool send_rpc_error_response(unsigned char*, int, long long, unsigned char, unsigned char*, int);
+// Sends a Goodbye with the reason to a peer, then disconnects from it
+EXPORT bool disconnect_peer(unsigned char*, int, long long);
+// Supplies our status for the status handshake; call after network_start and whenever it changes
+EXPORT void update_local_status(unsigned char*, int);
+// Bans an IP address or CIDR range with a reason for a number of seconds, or forever if not
+// positive. Bans are stored in the network directory. Returns false if the range is invalid
+EXPORT bool ban_ip_range(unsigned char*, int, unsigned char*, int, long long);
+// Blocks until the ban is removed; do not call from within a handler. Returns false if not banned
+EXPORT bool unban_ip_range(unsigned char*, int);
+// Calls the function with each banned range, its reason and its expiry in seconds since the UNIX
+// epoch, which is zero for permanent bans
+EXPORT void list_ip_bans(void (*ip_ban_ptr)(const unsigned char*, int, const unsigned char*, int, long long));
 
 EXPORT void register_handlers(
    void (*discovered_peer_ptr)(const unsigned char*, int), 
-   void (*receive_gossip_ptr)(const unsigned char*, int, unsigned char*, int), 
-   void (*receive_rpc_ptr)(const unsigned char*, int, int, const unsigned char*, int, unsigned char*, int)
+   void (*receive_gossip_ptr)(const unsigned char*, int, const unsigned char*, int, const unsigned char*, int, unsigned char*, int), 
+   void (*receive_rpc_ptr)(const unsigned char*, int, const unsigned char*, int, int, const unsigned char*, int, long long, unsigned char*, int)
+);
+
+// Optional; any handler may be NULL. Must be called before network_start
+EXPORT void register_peer_handlers(
+   void (*peer_disconnected_ptr)(const unsigned char*, int),
+   void (*peer_subscribed_ptr)(const unsigned char*, int, const unsigned char*, int),
+   void (*peer_unsubscribed_ptr)(const unsigned char*, int, const unsigned char*, int)
+);
+
+// Optional; may be NULL. Must be called before network_start
+EXPORT void register_rpc_error_handler(
+   void (*receive_rpc_error_ptr)(const unsigned char*, int, long long, int, const unsigned char*, int)
+);
+
+// Optional; may be NULL. Must be called before network_start
+EXPORT void register_rpc_failed_handler(
+   void (*rpc_failed_ptr)(const unsigned char*, int, const unsigned char*, int, long long, int, const unsigned char*, int)
+);
+
+// Optional; may be NULL. Must be called before network_start
+EXPORT void register_goodbye_handler(
+   void (*receive_goodbye_ptr)(const unsigned char*, int, long long)
+);
+
+// Optional; may be NULL, which accepts every status. Must be called before network_start
+EXPORT void register_status_handler(
+   bool (*check_status_ptr)(const unsigned char*, int, unsigned char*, int)
 );
        
 // Events functions called by Core
 EXPORT void discovered_peer(const unsigned char*, int);
-EXPORT void receive_gossip(const unsigned char*, int, unsigned char*, int);
-EXPORT void receive_rpc(const unsigned char*, int, int, const unsigned char*, int, unsigned char*, int);
+EXPORT void receive_gossip(const unsigned char*, int, const unsigned char*, int, const unsigned char*, int, unsigned char*, int);
+EXPORT void receive_rpc(const unsigned char*, int, const unsigned char*, int, int, const unsigned char*, int, long long, unsigned char*, int);
+EXPORT void peer_disconnected(const unsigned char*, int);
+EXPORT void peer_subscribed(const unsigned char*, int, const unsigned char*, int);
+EXPORT void peer_unsubscribed(const unsigned char*, int, const unsigned char*, int);
+EXPORT void receive_rpc_error(const unsigned char*, int, long long, int, const unsigned char*, int);
+EXPORT void rpc_failed(const unsigned char*, int, const unsigned char*, int, long long, int, const unsigned char*, int);
+EXPORT void receive_goodbye(const unsigned char*, int, long long);
+EXPORT bool check_status(const unsigned char*, int, unsigned char*, int);
 
 #ifdef __cplusplus
 }
@@ -21,6 +21,33 @@ namespace Bindings
     {
         // mothra.dll on Windows, libmothra.so on Linux, libmotha.dylib on OSX
         private const string DllName = "libmothra";
+
+        public const int GossipAccept = 0;
+        public const int GossipReject = 1;
+        public const int GossipIgnore = 2;
+
+        // Values of the request/response flag; a stream end follows the response chunks of a streaming method
+        public const int RpcRequest = 0;
+        public const int RpcResponse = 1;
+        public const int RpcStreamEnd = 2;
+
+        // Response codes of SendErrorResponse and ReceiveRpcError
+        public const int RpcInvalidRequest = 2;
+        public const int RpcServerError = 3;
+        public const int RpcUnknownError = 255;
+
+        // Kinds of failure passed to RpcFailed
+        public const int RpcFailedTimeout = 0;
+        public const int RpcFailedUnsupportedProtocol = 1;
+        public const int RpcFailedDisconnected = 2;
+        public const int RpcFailedIo = 3;
+        public const int RpcFailedOther = 4;
+
+        // Reasons of Disconnect and ReceiveGoodbye
+        public const long GoodbyeClientShutdown = 1;
+        public const long GoodbyeIrrelevantNetwork = 2;
+        public const long GoodbyeFault = 3;
+        public const long GoodbyeTooManyPeers = 129;
         
         [DllImport(DllName, EntryPoint = "network_start", CallingConvention = CallingConvention.Cdecl)]
         public static extern unsafe void Start([In, Out] string[] clientConstants, int numClientConstants, [In, Out] string[] args, int numArgs);
@@ -28,22 +55,108 @@ namespace Bindings
         [DllImport(DllName, EntryPoint = "send_gossip", CallingConvention = CallingConvention.Cdecl)]
         public static extern unsafe void SendGossip(byte* topicUtf8, int topicLength, byte* data, int dataLength);
 
+        [DllImport(DllName, EntryPoint = "subscribe_gossip", CallingConvention = CallingConvention.Cdecl)]
+        [return: MarshalAs(UnmanagedType.U1)]
+        public static extern unsafe bool Subscribe(byte* topicUtf8, int topicLength);
+
+        [DllImport(DllName, EntryPoint = "unsubscribe_gossip", CallingConvention = CallingConvention.Cdecl)]
+        [return: MarshalAs(UnmanagedType.U1)]
+        public static extern unsafe bool Unsubscribe(byte* topicUtf8, int topicLength);
+
+        // The methods taking a peer id return false, or a request id of zero, if it is invalid
+        [DllImport(DllName, EntryPoint = "validate_gossip_message", CallingConvention = CallingConvention.Cdecl)]
+        [return: MarshalAs(UnmanagedType.U1)]
+        public static extern unsafe bool ValidateGossip(byte* messageIdUtf8, int messageIdLength, byte* peerUtf8, int peerLength, int validationResult);
+
         [DllImport(DllName, EntryPoint = "send_rpc_request", CallingConvention = CallingConvention.Cdecl)]
-        public static extern unsafe void SendRequest(byte* methodUtf8, int methodLength, byte* peerUtf8, int peerLength, byte* data, int dataLength);
+        public static extern unsafe long SendRequest(byte* methodUtf8, int methodLength, byte* peerUtf8, int peerLength, byte* data, int dataLength);
 
         [DllImport(DllName, EntryPoint = "send_rpc_response", CallingConvention = CallingConvention.Cdecl)]
-        public static extern unsafe void SendResponse(byte* methodUtf8, int methodLength, byte* peerUtf8, int peerLength, byte* data, int dataLength);
+        [return: MarshalAs(UnmanagedType.U1)]
+        public static extern unsafe bool SendResponse(byte* methodUtf8, int methodLength, byte* peerUtf8, int peerLength, long requestId, byte* data, int dataLength);
+
+        [DllImport(DllName, EntryPoint = "send_rpc_stream_end", CallingConvention = CallingConvention.Cdecl)]
+        [return: MarshalAs(UnmanagedType.U1)]
+        public static extern unsafe bool SendStreamEnd(byte* methodUtf8, int methodLength, byte* peerUtf8, int peerLength, long requestId);
+
+        [DllImport(DllName, EntryPoint = "send_rpc_error_response", CallingConvention = CallingConvention.Cdecl)]
+        [return: MarshalAs(UnmanagedType.U1)]
+        public static extern unsafe bool SendErrorResponse(byte* peerUtf8, int peerLength, long requestId, byte code, byte* messageUtf8, int messageLength);
+
+        [DllImport(DllName, EntryPoint = "disconnect_peer", CallingConvention = CallingConvention.Cdecl)]
+        [return: MarshalAs(UnmanagedType.U1)]
+        public static extern unsafe bool Disconnect(byte* peerUtf8, int peerLength, long reason);
+
+        // Supplies our status for the status handshake; call after Start and whenever it changes
+        [DllImport(DllName, EntryPoint = "update_local_status", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void UpdateStatus(byte* status, int statusLength);
+
+        // Bans an IP address or CIDR range for a number of seconds, or forever if not positive
+        [DllImport(DllName, EntryPoint = "ban_ip_range", CallingConvention = CallingConvention.Cdecl)]
+        [return: MarshalAs(UnmanagedType.U1)]
+        public static extern unsafe bool BanIp(byte* rangeUtf8, int rangeLength, byte* reasonUtf8, int reasonLength, long duration);
+
+        // Blocks until the ban is removed; do not call from within a handler
+        [DllImport(DllName, EntryPoint = "unban_ip_range", CallingConvention = CallingConvention.Cdecl)]
+        [return: MarshalAs(UnmanagedType.U1)]
+        public static extern unsafe bool UnbanIp(byte* rangeUtf8, int rangeLength);
+
+        // Calls the handler with each ban; the expiry is in seconds since the UNIX epoch, or zero if permanent
+        [DllImport(DllName, EntryPoint = "list_ip_bans", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void ListIpBans(IpBan ipBan);
 
         [DllImport(DllName, EntryPoint = "register_handlers", CallingConvention = CallingConvention.Cdecl)]
         public static extern unsafe void RegisterHandlers(DiscoveredPeer discoveredPeer, ReceiveGossip receiveGossip, ReceiveRpc receiveRpc);
+
+        // Optional; any handler may be null. Must be called before Start
+        [DllImport(DllName, EntryPoint = "register_peer_handlers", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void RegisterPeerHandlers(PeerDisconnected? peerDisconnected, PeerTopic? peerSubscribed, PeerTopic? peerUnsubscribed);
+
+        // Optional; the handler may be null. Must be called before Start
+        [DllImport(DllName, EntryPoint = "register_rpc_error_handler", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void RegisterRpcErrorHandler(ReceiveRpcError? receiveRpcError);
+
+        // Optional; the handler may be null. Must be called before Start
+        [DllImport(DllName, EntryPoint = "register_rpc_failed_handler", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void RegisterRpcFailedHandler(RpcFailed? rpcFailed);
+
+        // Optional; the handler may be null. Must be called before Start
+        [DllImport(DllName, EntryPoint = "register_goodbye_handler", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void RegisterGoodbyeHandler(ReceiveGoodbye? receiveGoodbye);
+
+        // Optional; a null handler accepts every status. Must be called before Start
+        [DllImport(DllName, EntryPoint = "register_status_handler", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void RegisterStatusHandler(CheckStatus? checkStatus);
         
         [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
         public unsafe delegate void DiscoveredPeer(byte* peerUtf8, int peerLength);
         
         [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
-        public unsafe delegate void ReceiveGossip(byte* topicUtf8, int topicLength, byte* data, int dataLength);
+        public unsafe delegate void ReceiveGossip(byte* messageIdUtf8, int messageIdLength, byte* peerUtf8, int peerLength, byte* topicUtf8, int topicLength, byte* data, int dataLength);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        public unsafe delegate void ReceiveRpc(byte* methodUtf8, int methodLength, byte* versionUtf8, int versionLength, int requestResponseFlag, byte* peerUtf8, int peerLength, long requestId, byte* data, int dataLength);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        public unsafe delegate void PeerDisconnected(byte* peerUtf8, int peerLength);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        public unsafe delegate void PeerTopic(byte* peerUtf8, int peerLength, byte* topicUtf8, int topicLength);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        public unsafe delegate void ReceiveRpcError(byte* peerUtf8, int peerLength, long requestId, int code, byte* messageUtf8, int messageLength);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        public unsafe delegate void RpcFailed(byte* methodUtf8, int methodLength, byte* peerUtf8, int peerLength, long requestId, int kind, byte* errorUtf8, int errorLength);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        public unsafe delegate void ReceiveGoodbye(byte* peerUtf8, int peerLength, long reason);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        [return: MarshalAs(UnmanagedType.U1)]
+        public unsafe delegate bool CheckStatus(byte* peerUtf8, int peerLength, byte* status, int statusLength);
 
         [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
-        public unsafe delegate void ReceiveRpc(byte* methodUtf8, int methodLength, int requestResponseFlag, byte* peerUtf8, int peerLength, byte* data, int dataLength);
+        public unsafe delegate void IpBan(byte* rangeUtf8, int rangeLength, byte* reasonUtf8, int reasonLength, long expiry);
     }
 }
\ No newline at end of file
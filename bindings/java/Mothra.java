@@ -6,21 +6,97 @@ import java.util.function.Function;
 
 public class Mothra {
     public static final String MOTHRA = "mothra-jni";
+    public static final int GOSSIP_ACCEPT = 0;
+    public static final int GOSSIP_REJECT = 1;
+    public static final int GOSSIP_IGNORE = 2;
+    public static final int RPC_REQUEST = 0;
+    public static final int RPC_RESPONSE = 1;
+    public static final int RPC_STREAM_END = 2;
+    public static final int RPC_INVALID_REQUEST = 2;
+    public static final int RPC_SERVER_ERROR = 3;
+    public static final int RPC_UNKNOWN_ERROR = 255;
+    public static final int RPC_FAILED_TIMEOUT = 0;
+    public static final int RPC_FAILED_UNSUPPORTED_PROTOCOL = 1;
+    public static final int RPC_FAILED_DISCONNECTED = 2;
+    public static final int RPC_FAILED_IO = 3;
+    public static final int RPC_FAILED_OTHER = 4;
+    public static final long GOODBYE_CLIENT_SHUTDOWN = 1;
+    public static final long GOODBYE_IRRELEVANT_NETWORK = 2;
+    public static final long GOODBYE_FAULT = 3;
+    public static final long GOODBYE_TOO_MANY_PEERS = 129;
     public static Function<String, Boolean> DiscoveryMessage;
-    public static BiFunction<String, byte[], Boolean> ReceivedGossipMessage;
-    public static QuadFunction<String, Integer, String, byte[], Boolean> ReceivedRPCMessage;
+    public static QuadFunction<String, String, String, byte[], Boolean> ReceivedGossipMessage;
+    public static HexaFunction<String, String, Integer, String, Long, byte[], Boolean> ReceivedRPCMessage;
+    public static Function<String, Boolean> PeerDisconnectedMessage;
+    public static BiFunction<String, String, Boolean> PeerSubscribedMessage;
+    public static BiFunction<String, String, Boolean> PeerUnsubscribedMessage;
+    public static QuadFunction<String, Long, Integer, String, Boolean> ReceivedRPCErrorMessage;
+    public static PentaFunction<String, String, Long, Integer, String, Boolean> RPCFailedMessage;
+    public static BiFunction<String, Long, Boolean> ReceivedGoodbyeMessage;
+    public static BiFunction<String, byte[], Boolean> StatusCheck;
+    public static TriFunction<String, String, Long, Boolean> IpBanEntry;
     public static native void Init();
     public static native void Start(String[] client_constants, String[] args);
     public static native void SendGossip(byte[] topic, byte[] message);
-    public static native void SendRPC(byte[] method, int req_resp, byte[] peer, byte[] message);
+    public static native boolean ValidateGossip(byte[] message_id, byte[] peer, int validation_result);
+    public static native boolean Subscribe(byte[] topic);
+    public static native boolean Unsubscribe(byte[] topic);
+    public static native long SendRPC(byte[] method, int req_resp, byte[] peer, long request_id, byte[] message);
+    public static native boolean SendRPCError(byte[] peer, long request_id, int code, byte[] message);
+    public static native boolean Disconnect(byte[] peer, long reason);
+    public static native void UpdateStatus(byte[] status);
+    public static native boolean BanIp(byte[] range, byte[] reason, long duration);
+    public static native boolean UnbanIp(byte[] range);
+    public static native void ListIpBans();
     public static void DiscoveredPeer(byte[] peer) {
         DiscoveryMessage.apply(new String(peer));
     }
-    public static void ReceiveGossip(byte[] topic, byte[] message) {
-        ReceivedGossipMessage.apply(new String(topic), message);
+    public static void ReceiveGossip(byte[] message_id, byte[] peer, byte[] topic, byte[] message) {
+        ReceivedGossipMessage.apply(new String(message_id), new String(peer), new String(topic), message);
     }
-    public static void ReceiveRPC(byte[] method, int req_resp, byte[] peer, byte[] message) {
-        ReceivedRPCMessage.apply(new String(method), req_resp, new String(peer), message);
+    public static void ReceiveRPC(byte[] method, byte[] version, int req_resp, byte[] peer, long request_id, byte[] message) {
+        ReceivedRPCMessage.apply(new String(method), new String(version), req_resp, new String(peer), request_id, message);
+    }
+    public static void PeerDisconnected(byte[] peer) {
+        if (PeerDisconnectedMessage != null) {
+            PeerDisconnectedMessage.apply(new String(peer));
+        }
+    }
+    public static void PeerSubscribed(byte[] peer, byte[] topic) {
+        if (PeerSubscribedMessage != null) {
+            PeerSubscribedMessage.apply(new String(peer), new String(topic));
+        }
+    }
+    public static void PeerUnsubscribed(byte[] peer, byte[] topic) {
+        if (PeerUnsubscribedMessage != null) {
+            PeerUnsubscribedMessage.apply(new String(peer), new String(topic));
+        }
+    }
+    public static void ReceiveRPCError(byte[] peer, long request_id, int code, byte[] message) {
+        if (ReceivedRPCErrorMessage != null) {
+            ReceivedRPCErrorMessage.apply(new String(peer), request_id, code, new String(message));
+        }
+    }
+    public static void RPCFailed(byte[] method, byte[] peer, long request_id, int kind, byte[] error) {
+        if (RPCFailedMessage != null) {
+            RPCFailedMessage.apply(new String(method), new String(peer), request_id, kind, new String(error));
+        }
+    }
+    public static void ReceiveGoodbye(byte[] peer, long reason) {
+        if (ReceivedGoodbyeMessage != null) {
+            ReceivedGoodbyeMessage.apply(new String(peer), reason);
+        }
+    }
+    public static boolean CheckStatus(byte[] peer, byte[] status) {
+        if (StatusCheck != null) {
+            return StatusCheck.apply(new String(peer), status);
+        }
+        return true;
+    }
+    public static void IpBanListed(byte[] range, byte[] reason, long expiry) {
+        if (IpBanEntry != null) {
+            IpBanEntry.apply(new String(range), new String(reason), expiry);
+        }
     }
     static {
         try {
@@ -32,6 +108,16 @@ public class Mothra {
         }
     }
 
+    @FunctionalInterface
+    public interface TriFunction<A,B,C,R> {
+        R apply(A a, B b, C c);
+        default <V> TriFunction<A, B, C, V> andThen(
+                                    Function<? super R, ? extends V> after) {
+            Objects.requireNonNull(after);
+            return (A a, B b, C c) -> after.apply(apply(a, b, c));
+        }
+    }
+
     @FunctionalInterface
     public interface QuadFunction<A,B,C,D,R> {
         R apply(A a, B b, C c, D d);
@@ -41,4 +127,24 @@ public class Mothra {
             return (A a, B b, C c, D d) -> after.apply(apply(a, b, c, d));
         }
     }
+
+    @FunctionalInterface
+    public interface PentaFunction<A,B,C,D,E,R> {
+        R apply(A a, B b, C c, D d, E e);
+        default <V> PentaFunction<A, B, C, D, E, V> andThen(
+                                    Function<? super R, ? extends V> after) {
+            Objects.requireNonNull(after);
+            return (A a, B b, C c, D d, E e) -> after.apply(apply(a, b, c, d, e));
+        }
+    }
+
+    @FunctionalInterface
+    public interface HexaFunction<A,B,C,D,E,F,R> {
+        R apply(A a, B b, C c, D d, E e, F f);
+        default <V> HexaFunction<A, B, C, D, E, F, V> andThen(
+                                    Function<? super R, ? extends V> after) {
+            Objects.requireNonNull(after);
+            return (A a, B b, C c, D d, E e, F f) -> after.apply(apply(a, b, c, d, e, f));
+        }
+    }
 }
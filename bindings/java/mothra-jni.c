@@ -11,6 +11,11 @@ JNIEXPORT void JNICALL Java_p2p_mothra_Mothra_Init(JNIEnv* jenv, jclass jcls)
    jint rs = (*jenv)->GetJavaVM(jenv, &jvm);
    assert (rs == JNI_OK);
    register_handlers(discovered_peer_callback,receive_gossip_callback,receive_rpc_callback);
+   register_peer_handlers(peer_disconnected_callback,peer_subscribed_callback,peer_unsubscribed_callback);
+   register_rpc_error_handler(receive_rpc_error_callback);
+   register_rpc_failed_handler(rpc_failed_callback);
+   register_goodbye_handler(receive_goodbye_callback);
+   register_status_handler(check_status_callback);
 }
 
 JNIEXPORT void JNICALL Java_p2p_mothra_Mothra_Start(JNIEnv *jenv, jclass jcls, jobjectArray jclient_constants, jobjectArray jargs){
@@ -55,34 +60,177 @@ JNIEXPORT void JNICALL Java_p2p_mothra_Mothra_SendGossip(JNIEnv *jenv, jclass jc
     if (data) (*jenv)->ReleaseByteArrayElements(jenv, jdata, (jbyte *)data, 0);
 }
 
-JNIEXPORT void JNICALL Java_p2p_mothra_Mothra_SendRPC(JNIEnv *jenv, jclass jcls, jbyteArray jmethod, jint jreq_resp, jbyteArray jpeer, jbyteArray jdata){
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_Subscribe(JNIEnv *jenv, jclass jcls, jbyteArray jtopic){
+    int topic_length = (*jenv)->GetArrayLength(jenv, jtopic);
+    unsigned char *topic = (unsigned char *) 0 ;
+    jboolean isCopy = JNI_TRUE;
+    jboolean result = JNI_FALSE;
+    if (jtopic) {
+        topic = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jtopic,&isCopy);
+        if (!topic) return JNI_FALSE;
+    }
+    result = subscribe_gossip(topic,topic_length) ? JNI_TRUE : JNI_FALSE;
+    if (topic) (*jenv)->ReleaseByteArrayElements(jenv, jtopic, (jbyte *)topic, 0);
+    return result;
+}
+
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_Unsubscribe(JNIEnv *jenv, jclass jcls, jbyteArray jtopic){
+    int topic_length = (*jenv)->GetArrayLength(jenv, jtopic);
+    unsigned char *topic = (unsigned char *) 0 ;
+    jboolean isCopy = JNI_TRUE;
+    jboolean result = JNI_FALSE;
+    if (jtopic) {
+        topic = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jtopic,&isCopy);
+        if (!topic) return JNI_FALSE;
+    }
+    result = unsubscribe_gossip(topic,topic_length) ? JNI_TRUE : JNI_FALSE;
+    if (topic) (*jenv)->ReleaseByteArrayElements(jenv, jtopic, (jbyte *)topic, 0);
+    return result;
+}
+
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_ValidateGossip(JNIEnv *jenv, jclass jcls, jbyteArray jmessage_id, jbyteArray jpeer, jint jvalidation_result){
+    int message_id_length = (*jenv)->GetArrayLength(jenv, jmessage_id);
+    int peer_length = (*jenv)->GetArrayLength(jenv, jpeer);
+    unsigned char *message_id = (unsigned char *) 0 ;
+    unsigned char *peer = (unsigned char *) 0 ;
+    jboolean result = JNI_FALSE;
+    jboolean isCopy = JNI_TRUE;
+    if (jmessage_id) {
+        message_id = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jmessage_id,&isCopy);
+        if (!message_id) goto release;
+    }
+    if (jpeer) {
+        peer = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jpeer,&isCopy);
+        if (!peer) goto release;
+    }
+    result = validate_gossip_message(message_id,message_id_length,peer,peer_length,jvalidation_result) ? JNI_TRUE : JNI_FALSE;
+release:
+    if (message_id) (*jenv)->ReleaseByteArrayElements(jenv, jmessage_id, (jbyte *)message_id, 0);
+    if (peer) (*jenv)->ReleaseByteArrayElements(jenv, jpeer, (jbyte *)peer, 0);
+    return result;
+}
+
+JNIEXPORT jlong JNICALL Java_p2p_mothra_Mothra_SendRPC(JNIEnv *jenv, jclass jcls, jbyteArray jmethod, jint jreq_resp, jbyteArray jpeer, jlong jrequest_id, jbyteArray jdata){
     int data_length = (*jenv)->GetArrayLength(jenv, jdata);
     int method_length = (*jenv)->GetArrayLength(jenv, jmethod);
     int peer_length = (*jenv)->GetArrayLength(jenv, jpeer);
     unsigned char *data = (unsigned char *) 0 ;
     unsigned char *method = (unsigned char *) 0 ;
     unsigned char *peer = (unsigned char *) 0 ;
+    jlong request_id = jrequest_id;
     jboolean isCopy = JNI_TRUE;
     if (jdata) {
         data = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jdata,&isCopy);
-        if (!data) return ;
+        if (!data) { request_id = 0; goto release; }
     }
     if (jpeer) {
         peer = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jpeer,&isCopy);
-        if (!peer) return ;
+        if (!peer) { request_id = 0; goto release; }
     }
     if (jmethod) {
         method = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jmethod,&isCopy);
-        if (!method) return ;
+        if (!method) { request_id = 0; goto release; }
     }
     if (jreq_resp == 0){
-        send_rpc_request(method,method_length,peer,peer_length,data,data_length);
+        request_id = send_rpc_request(method,method_length,peer,peer_length,data,data_length);
     } else if (jreq_resp == 1){
-        send_rpc_response(method,method_length,peer,peer_length,data,data_length);
+        if (!send_rpc_response(method,method_length,peer,peer_length,request_id,data,data_length)) request_id = 0;
+    } else if (jreq_resp == 2){
+        if (!send_rpc_stream_end(method,method_length,peer,peer_length,request_id)) request_id = 0;
     }
+release:
     if (data) (*jenv)->ReleaseByteArrayElements(jenv, jdata, (jbyte *)data, 0);
     if (peer) (*jenv)->ReleaseByteArrayElements(jenv, jpeer, (jbyte *)peer, 0);
     if (method) (*jenv)->ReleaseByteArrayElements(jenv, jmethod, (jbyte *)method, 0);
+    return request_id;
+}
+
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_SendRPCError(JNIEnv *jenv, jclass jcls, jbyteArray jpeer, jlong jrequest_id, jint jcode, jbyteArray jmessage){
+    int peer_length = (*jenv)->GetArrayLength(jenv, jpeer);
+    int message_length = (*jenv)->GetArrayLength(jenv, jmessage);
+    unsigned char *peer = (unsigned char *) 0 ;
+    unsigned char *message = (unsigned char *) 0 ;
+    jboolean result = JNI_FALSE;
+    jboolean isCopy = JNI_TRUE;
+    if (jpeer) {
+        peer = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jpeer,&isCopy);
+        if (!peer) goto release;
+    }
+    if (jmessage) {
+        message = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jmessage,&isCopy);
+        if (!message) goto release;
+    }
+    result = send_rpc_error_response(peer,peer_length,jrequest_id,(unsigned char)jcode,message,message_length) ? JNI_TRUE : JNI_FALSE;
+release:
+    if (peer) (*jenv)->ReleaseByteArrayElements(jenv, jpeer, (jbyte *)peer, 0);
+    if (message) (*jenv)->ReleaseByteArrayElements(jenv, jmessage, (jbyte *)message, 0);
+    return result;
+}
+
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_Disconnect(JNIEnv *jenv, jclass jcls, jbyteArray jpeer, jlong jreason){
+    int peer_length = (*jenv)->GetArrayLength(jenv, jpeer);
+    unsigned char *peer = (unsigned char *) 0 ;
+    jboolean result;
+    jboolean isCopy = JNI_TRUE;
+    if (jpeer) {
+        peer = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jpeer,&isCopy);
+        if (!peer) return JNI_FALSE;
+    }
+    result = disconnect_peer(peer,peer_length,jreason) ? JNI_TRUE : JNI_FALSE;
+    if (peer) (*jenv)->ReleaseByteArrayElements(jenv, jpeer, (jbyte *)peer, 0);
+    return result;
+}
+
+JNIEXPORT void JNICALL Java_p2p_mothra_Mothra_UpdateStatus(JNIEnv *jenv, jclass jcls, jbyteArray jstatus){
+    int status_length = (*jenv)->GetArrayLength(jenv, jstatus);
+    unsigned char *status = (unsigned char *) 0 ;
+    jboolean isCopy = JNI_TRUE;
+    if (jstatus) {
+        status = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jstatus,&isCopy);
+        if (!status) return;
+    }
+    update_local_status(status,status_length);
+    if (status) (*jenv)->ReleaseByteArrayElements(jenv, jstatus, (jbyte *)status, 0);
+}
+
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_BanIp(JNIEnv *jenv, jclass jcls, jbyteArray jrange, jbyteArray jreason, jlong jduration){
+    int range_length = (*jenv)->GetArrayLength(jenv, jrange);
+    int reason_length = (*jenv)->GetArrayLength(jenv, jreason);
+    unsigned char *range = (unsigned char *) 0 ;
+    unsigned char *reason = (unsigned char *) 0 ;
+    jboolean isCopy = JNI_TRUE;
+    jboolean result = JNI_FALSE;
+    if (jrange) {
+        range = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jrange,&isCopy);
+        if (!range) goto release;
+    }
+    if (jreason) {
+        reason = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jreason,&isCopy);
+        if (!reason) goto release;
+    }
+    result = ban_ip_range(range,range_length,reason,reason_length,jduration) ? JNI_TRUE : JNI_FALSE;
+release:
+    if (range) (*jenv)->ReleaseByteArrayElements(jenv, jrange, (jbyte *)range, 0);
+    if (reason) (*jenv)->ReleaseByteArrayElements(jenv, jreason, (jbyte *)reason, 0);
+    return result;
+}
+
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_UnbanIp(JNIEnv *jenv, jclass jcls, jbyteArray jrange){
+    int range_length = (*jenv)->GetArrayLength(jenv, jrange);
+    unsigned char *range = (unsigned char *) 0 ;
+    jboolean isCopy = JNI_TRUE;
+    jboolean result = JNI_FALSE;
+    if (jrange) {
+        range = (unsigned char *)(*jenv)->GetByteArrayElements(jenv,jrange,&isCopy);
+        if (!range) return JNI_FALSE;
+    }
+    result = unban_ip_range(range,range_length) ? JNI_TRUE : JNI_FALSE;
+    if (range) (*jenv)->ReleaseByteArrayElements(jenv, jrange, (jbyte *)range, 0);
+    return result;
+}
+
+JNIEXPORT void JNICALL Java_p2p_mothra_Mothra_ListIpBans(JNIEnv *jenv, jclass jcls){
+    list_ip_bans(ip_ban_callback);
 }
 
 void discovered_peer_callback(const unsigned char* peer, int peer_length) {
@@ -112,13 +260,15 @@ void discovered_peer_callback(const unsigned char* peer, int peer_length) {
     }
 }
 
-void receive_gossip_callback(const unsigned char* topic, int topic_length, unsigned char* data, int data_length) {
+void receive_gossip_callback(const unsigned char* message_id, int message_id_length, const unsigned char* peer, int peer_length, const unsigned char* topic, int topic_length, unsigned char* data, int data_length) {
     JNIEnv *jenv;
     jint rs = (*jvm)->AttachCurrentThread(jvm, (void**)&jenv, NULL);
     assert (rs == JNI_OK);
     if(jenv != NULL) {
         jclass mothra_class;
         jmethodID receivegossip_method;
+        jbyteArray jmessage_id;
+        jbyteArray jpeer;
         jbyteArray jtopic;
         jbyteArray jdata;
         mothra_class = (*jenv)->FindClass(jenv, "p2p/mothra/Mothra");
@@ -126,23 +276,27 @@ void receive_gossip_callback(const unsigned char* topic, int topic_length, unsig
             detach(jenv);
         }
         //Put the native unsigned chars in the java byte array
+        jmessage_id = (*jenv)->NewByteArray(jenv, message_id_length);
+        jpeer = (*jenv)->NewByteArray(jenv, peer_length);
         jtopic = (*jenv)->NewByteArray(jenv, topic_length);
         jdata = (*jenv)->NewByteArray(jenv, data_length);
+        (*jenv)->SetByteArrayRegion(jenv, jmessage_id, 0, message_id_length, (jbyte *)message_id);
+        (*jenv)->SetByteArrayRegion(jenv, jpeer, 0, peer_length, (jbyte *)peer);
         (*jenv)->SetByteArrayRegion(jenv, jdata, 0, data_length, (jbyte *)data);
         (*jenv)->SetByteArrayRegion(jenv, jtopic, 0, topic_length, (jbyte *)topic);
-        if(!jdata || !jtopic){
+        if(!jdata || !jtopic || !jpeer || !jmessage_id){
             detach(jenv);
         }
-        receivegossip_method = (*jenv)->GetStaticMethodID(jenv, mothra_class, "ReceiveGossip", "([B[B)V");
+        receivegossip_method = (*jenv)->GetStaticMethodID(jenv, mothra_class, "ReceiveGossip", "([B[B[B[B)V");
         if(!receivegossip_method){
-            printf("JNI Error: GetStaticMethodID was unable to find method: ReceiveGossip with signature: ([B[B)V\n");
+            printf("JNI Error: GetStaticMethodID was unable to find method: ReceiveGossip with signature: ([B[B[B[B)V\n");
             detach(jenv);
         }
-        (*jenv)->CallStaticVoidMethod(jenv, mothra_class, receivegossip_method, jtopic, jdata);
+        (*jenv)->CallStaticVoidMethod(jenv, mothra_class, receivegossip_method, jmessage_id, jpeer, jtopic, jdata);
     }
 }
 
-void receive_rpc_callback(const unsigned char* method, int method_length, int req_resp, const unsigned char* peer, int peer_length, unsigned char* data, int data_length) {
+void receive_rpc_callback(const unsigned char* method, int method_length, const unsigned char* version, int version_length, int req_resp, const unsigned char* peer, int peer_length, long long request_id, unsigned char* data, int data_length) {
     JNIEnv *jenv;
     jint rs = (*jvm)->AttachCurrentThread(jvm, (void**)&jenv, NULL);
     assert (rs == JNI_OK);
@@ -150,8 +304,10 @@ void receive_rpc_callback(const unsigned char* method, int method_length, int re
         jclass mothra_class;
         jmethodID receiverpc_method;
         jbyteArray jmethod;
+        jbyteArray jversion;
         jint jreq_resp;
         jbyteArray jpeer;
+        jlong jrequest_id;
         jbyteArray jdata;
         mothra_class = (*jenv)->FindClass(jenv, "p2p/mothra/Mothra");
         if(!mothra_class){
@@ -159,21 +315,252 @@ void receive_rpc_callback(const unsigned char* method, int method_length, int re
         }
         //Put the native unsigned chars in the java byte array
         jmethod = (*jenv)->NewByteArray(jenv, method_length);
+        jversion = (*jenv)->NewByteArray(jenv, version_length);
         jreq_resp = req_resp;
         jpeer = (*jenv)->NewByteArray(jenv, peer_length);
+        jrequest_id = request_id;
         jdata = (*jenv)->NewByteArray(jenv, data_length);
         (*jenv)->SetByteArrayRegion(jenv, jmethod, 0, method_length, (jbyte *)method);
+        (*jenv)->SetByteArrayRegion(jenv, jversion, 0, version_length, (jbyte *)version);
         (*jenv)->SetByteArrayRegion(jenv, jpeer, 0, peer_length, (jbyte *)peer);
         (*jenv)->SetByteArrayRegion(jenv, jdata, 0, data_length, (jbyte *)data);
-        if(!jdata || !jpeer|| !jmethod){
+        if(!jdata || !jpeer|| !jmethod || !jversion){
             detach(jenv);
         }
-        receiverpc_method = (*jenv)->GetStaticMethodID(jenv, mothra_class, "ReceiveRPC", "([BI[B[B)V");
+        receiverpc_method = (*jenv)->GetStaticMethodID(jenv, mothra_class, "ReceiveRPC", "([B[BI[BJ[B)V");
         if(!receiverpc_method){
-            printf("JNI Error: GetStaticMethodID was unable to find method: ReceiveRPC with signature: ([BI[B[B)V\n");
+            printf("JNI Error: GetStaticMethodID was unable to find method: ReceiveRPC with signature: ([B[BI[BJ[B)V\n");
+            detach(jenv);
+        }
+        (*jenv)->CallStaticVoidMethod(jenv, mothra_class, receiverpc_method, jmethod, jversion, jreq_resp, jpeer, jrequest_id, jdata);
+    }
+}
+
+void peer_disconnected_callback(const unsigned char* peer, int peer_length) {
+    JNIEnv *jenv;
+    jint rs = (*jvm)->AttachCurrentThread(jvm, (void**)&jenv, NULL);
+    assert (rs == JNI_OK);
+    if(jenv != NULL) {
+        jclass mothra_class;
+        jmethodID peerdisconnected_method;
+        jbyteArray jpeer;
+        mothra_class = (*jenv)->FindClass(jenv, "p2p/mothra/Mothra");
+        if(!mothra_class){
+            detach(jenv);
+        }
+        //Put the native unsigned chars in the java byte array
+        jpeer = (*jenv)->NewByteArray(jenv, peer_length);
+        (*jenv)->SetByteArrayRegion(jenv, jpeer, 0, peer_length, (jbyte *)peer);
+        if(!jpeer){
+            detach(jenv);
+        }
+        peerdisconnected_method = (*jenv)->GetStaticMethodID(jenv, mothra_class, "PeerDisconnected", "([B)V");
+        if(!peerdisconnected_method){
+            printf("JNI Error: GetStaticMethodID was unable to find method: PeerDisconnected with signature: ([B)V\n");
+            detach(jenv);
+        }
+        (*jenv)->CallStaticVoidMethod(jenv, mothra_class, peerdisconnected_method, jpeer);
+    }
+}
+
+void peer_subscribed_callback(const unsigned char* peer, int peer_length, const unsigned char* topic, int topic_length) {
+    peer_topic_callback("PeerSubscribed", peer, peer_length, topic, topic_length);
+}
+
+void peer_unsubscribed_callback(const unsigned char* peer, int peer_length, const unsigned char* topic, int topic_length) {
+    peer_topic_callback("PeerUnsubscribed", peer, peer_length, topic, topic_length);
+}
+
+void receive_rpc_error_callback(const unsigned char* peer, int peer_length, long long request_id, int code, const unsigned char* message, int message_length) {
+    JNIEnv *jenv;
+    jint rs = (*jvm)->AttachCurrentThread(jvm, (void**)&jenv, NULL);
+    assert (rs == JNI_OK);
+    if(jenv != NULL) {
+        jclass mothra_class;
+        jmethodID receiverpcerror_method;
+        jbyteArray jpeer;
+        jbyteArray jmessage;
+        jlong jrequest_id = request_id;
+        jint jcode = code;
+        mothra_class = (*jenv)->FindClass(jenv, "p2p/mothra/Mothra");
+        if(!mothra_class){
+            detach(jenv);
+        }
+        //Put the native unsigned chars in the java byte array
+        jpeer = (*jenv)->NewByteArray(jenv, peer_length);
+        jmessage = (*jenv)->NewByteArray(jenv, message_length);
+        (*jenv)->SetByteArrayRegion(jenv, jpeer, 0, peer_length, (jbyte *)peer);
+        (*jenv)->SetByteArrayRegion(jenv, jmessage, 0, message_length, (jbyte *)message);
+        if(!jpeer || !jmessage){
+            detach(jenv);
+        }
+        receiverpcerror_method = (*jenv)->GetStaticMethodID(jenv, mothra_class, "ReceiveRPCError", "([BJI[B)V");
+        if(!receiverpcerror_method){
+            printf("JNI Error: GetStaticMethodID was unable to find method: ReceiveRPCError with signature: ([BJI[B)V\n");
+            detach(jenv);
+        }
+        (*jenv)->CallStaticVoidMethod(jenv, mothra_class, receiverpcerror_method, jpeer, jrequest_id, jcode, jmessage);
+    }
+}
+
+void rpc_failed_callback(const unsigned char* method, int method_length, const unsigned char* peer, int peer_length, long long request_id, int kind, const unsigned char* error, int error_length) {
+    JNIEnv *jenv;
+    jint rs = (*jvm)->AttachCurrentThread(jvm, (void**)&jenv, NULL);
+    assert (rs == JNI_OK);
+    if(jenv != NULL) {
+        jclass mothra_class;
+        jmethodID rpcfailed_method;
+        jbyteArray jmethod;
+        jbyteArray jpeer;
+        jbyteArray jerror;
+        jlong jrequest_id = request_id;
+        jint jkind = kind;
+        mothra_class = (*jenv)->FindClass(jenv, "p2p/mothra/Mothra");
+        if(!mothra_class){
+            detach(jenv);
+        }
+        //Put the native unsigned chars in the java byte array
+        jmethod = (*jenv)->NewByteArray(jenv, method_length);
+        jpeer = (*jenv)->NewByteArray(jenv, peer_length);
+        jerror = (*jenv)->NewByteArray(jenv, error_length);
+        (*jenv)->SetByteArrayRegion(jenv, jmethod, 0, method_length, (jbyte *)method);
+        (*jenv)->SetByteArrayRegion(jenv, jpeer, 0, peer_length, (jbyte *)peer);
+        (*jenv)->SetByteArrayRegion(jenv, jerror, 0, error_length, (jbyte *)error);
+        if(!jmethod || !jpeer || !jerror){
+            detach(jenv);
+        }
+        rpcfailed_method = (*jenv)->GetStaticMethodID(jenv, mothra_class, "RPCFailed", "([B[BJI[B)V");
+        if(!rpcfailed_method){
+            printf("JNI Error: GetStaticMethodID was unable to find method: RPCFailed with signature: ([B[BJI[B)V\n");
+            detach(jenv);
+        }
+        (*jenv)->CallStaticVoidMethod(jenv, mothra_class, rpcfailed_method, jmethod, jpeer, jrequest_id, jkind, jerror);
+    }
+}
+
+void receive_goodbye_callback(const unsigned char* peer, int peer_length, long long reason) {
+    JNIEnv *jenv;
+    jint rs = (*jvm)->AttachCurrentThread(jvm, (void**)&jenv, NULL);
+    assert (rs == JNI_OK);
+    if(jenv != NULL) {
+        jclass mothra_class;
+        jmethodID goodbye_method;
+        jbyteArray jpeer;
+        jlong jreason = reason;
+        mothra_class = (*jenv)->FindClass(jenv, "p2p/mothra/Mothra");
+        if(!mothra_class){
+            detach(jenv);
+        }
+        //Put the native unsigned chars in the java byte array
+        jpeer = (*jenv)->NewByteArray(jenv, peer_length);
+        (*jenv)->SetByteArrayRegion(jenv, jpeer, 0, peer_length, (jbyte *)peer);
+        if(!jpeer){
+            detach(jenv);
+        }
+        goodbye_method = (*jenv)->GetStaticMethodID(jenv, mothra_class, "ReceiveGoodbye", "([BJ)V");
+        if(!goodbye_method){
+            printf("JNI Error: GetStaticMethodID was unable to find method: ReceiveGoodbye with signature: ([BJ)V\n");
+            detach(jenv);
+        }
+        (*jenv)->CallStaticVoidMethod(jenv, mothra_class, goodbye_method, jpeer, jreason);
+    }
+}
+
+bool check_status_callback(const unsigned char* peer, int peer_length, unsigned char* status, int status_length) {
+    JNIEnv *jenv;
+    jint rs = (*jvm)->AttachCurrentThread(jvm, (void**)&jenv, NULL);
+    assert (rs == JNI_OK);
+    if(jenv != NULL) {
+        jclass mothra_class;
+        jmethodID status_method;
+        jbyteArray jpeer;
+        jbyteArray jstatus;
+        mothra_class = (*jenv)->FindClass(jenv, "p2p/mothra/Mothra");
+        if(!mothra_class){
+            detach(jenv);
+        }
+        //Put the native unsigned chars in the java byte arrays
+        jpeer = (*jenv)->NewByteArray(jenv, peer_length);
+        (*jenv)->SetByteArrayRegion(jenv, jpeer, 0, peer_length, (jbyte *)peer);
+        if(!jpeer){
+            detach(jenv);
+        }
+        jstatus = (*jenv)->NewByteArray(jenv, status_length);
+        (*jenv)->SetByteArrayRegion(jenv, jstatus, 0, status_length, (jbyte *)status);
+        if(!jstatus){
+            detach(jenv);
+        }
+        status_method = (*jenv)->GetStaticMethodID(jenv, mothra_class, "CheckStatus", "([B[B)Z");
+        if(!status_method){
+            printf("JNI Error: GetStaticMethodID was unable to find method: CheckStatus with signature: ([B[B)Z\n");
+            detach(jenv);
+        }
+        return (*jenv)->CallStaticBooleanMethod(jenv, mothra_class, status_method, jpeer, jstatus);
+    }
+    return true;
+}
+
+void ip_ban_callback(const unsigned char* range, int range_length, const unsigned char* reason, int reason_length, long long expiry) {
+    JNIEnv *jenv;
+    jint rs = (*jvm)->AttachCurrentThread(jvm, (void**)&jenv, NULL);
+    assert (rs == JNI_OK);
+    if(jenv != NULL) {
+        jclass mothra_class;
+        jmethodID ipban_method;
+        jbyteArray jrange;
+        jbyteArray jreason;
+        jlong jexpiry = expiry;
+        mothra_class = (*jenv)->FindClass(jenv, "p2p/mothra/Mothra");
+        if(!mothra_class){
+            detach(jenv);
+        }
+        //Put the native unsigned chars in the java byte arrays
+        jrange = (*jenv)->NewByteArray(jenv, range_length);
+        (*jenv)->SetByteArrayRegion(jenv, jrange, 0, range_length, (jbyte *)range);
+        if(!jrange){
+            detach(jenv);
+        }
+        jreason = (*jenv)->NewByteArray(jenv, reason_length);
+        (*jenv)->SetByteArrayRegion(jenv, jreason, 0, reason_length, (jbyte *)reason);
+        if(!jreason){
+            detach(jenv);
+        }
+        ipban_method = (*jenv)->GetStaticMethodID(jenv, mothra_class, "IpBanListed", "([B[BJ)V");
+        if(!ipban_method){
+            printf("JNI Error: GetStaticMethodID was unable to find method: IpBanListed with signature: ([B[BJ)V\n");
+            detach(jenv);
+        }
+        (*jenv)->CallStaticVoidMethod(jenv, mothra_class, ipban_method, jrange, jreason, jexpiry);
+    }
+}
+
+static void peer_topic_callback(const char* method_name, const unsigned char* peer, int peer_length, const unsigned char* topic, int topic_length) {
+    JNIEnv *jenv;
+    jint rs = (*jvm)->AttachCurrentThread(jvm, (void**)&jenv, NULL);
+    assert (rs == JNI_OK);
+    if(jenv != NULL) {
+        jclass mothra_class;
+        jmethodID peertopic_method;
+        jbyteArray jpeer;
+        jbyteArray jtopic;
+        mothra_class = (*jenv)->FindClass(jenv, "p2p/mothra/Mothra");
+        if(!mothra_class){
+            detach(jenv);
+        }
+        //Put the native unsigned chars in the java byte array
+        jpeer = (*jenv)->NewByteArray(jenv, peer_length);
+        jtopic = (*jenv)->NewByteArray(jenv, topic_length);
+        (*jenv)->SetByteArrayRegion(jenv, jpeer, 0, peer_length, (jbyte *)peer);
+        (*jenv)->SetByteArrayRegion(jenv, jtopic, 0, topic_length, (jbyte *)topic);
+        if(!jpeer || !jtopic){
+            detach(jenv);
+        }
+        peertopic_method = (*jenv)->GetStaticMethodID(jenv, mothra_class, method_name, "([B[B)V");
+        if(!peertopic_method){
+            printf("JNI Error: GetStaticMethodID was unable to find method: %s with signature: ([B[B)V\n", method_name);
             detach(jenv);
         }
-        (*jenv)->CallStaticVoidMethod(jenv, mothra_class, receiverpc_method, jmethod, jreq_resp, jpeer, jdata);
+        (*jenv)->CallStaticVoidMethod(jenv, mothra_class, peertopic_method, jpeer, jtopic);
     }
 }
 
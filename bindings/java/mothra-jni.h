@@ -8,12 +8,30 @@ extern "C" {
 JNIEXPORT void JNICALL Java_p2p_mothra_Mothra_Init(JNIEnv*, jclass);
 JNIEXPORT void JNICALL Java_p2p_mothra_Mothra_Start(JNIEnv*, jclass, jobjectArray, jobjectArray);
 JNIEXPORT void JNICALL Java_p2p_mothra_Mothra_SendGossip(JNIEnv*, jclass, jbyteArray, jbyteArray);
-JNIEXPORT void JNICALL Java_p2p_mothra_Mothra_SendRPC (JNIEnv*, jclass, jbyteArray, jint, jbyteArray, jbyteArray);
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_ValidateGossip(JNIEnv*, jclass, jbyteArray, jbyteArray, jint);
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_Subscribe(JNIEnv*, jclass, jbyteArray);
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_Unsubscribe(JNIEnv*, jclass, jbyteArray);
+JNIEXPORT jlong JNICALL Java_p2p_mothra_Mothra_SendRPC (JNIEnv*, jclass, jbyteArray, jint, jbyteArray, jlong, jbyteArray);
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_SendRPCError (JNIEnv*, jclass, jbyteArray, jlong, jint, jbyteArray);
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_Disconnect (JNIEnv*, jclass, jbyteArray, jlong);
+JNIEXPORT void JNICALL Java_p2p_mothra_Mothra_UpdateStatus (JNIEnv*, jclass, jbyteArray);
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_BanIp (JNIEnv*, jclass, jbyteArray, jbyteArray, jlong);
+JNIEXPORT jboolean JNICALL Java_p2p_mothra_Mothra_UnbanIp (JNIEnv*, jclass, jbyteArray);
+JNIEXPORT void JNICALL Java_p2p_mothra_Mothra_ListIpBans (JNIEnv*, jclass);
 
 void discovered_peer_callback(const unsigned char*, int);
-void receive_gossip_callback(const unsigned char*, int, unsigned char*, int);
-void receive_rpc_callback(const unsigned char*, int, int, const unsigned char*, int, unsigned char*, int);
+void receive_gossip_callback(const unsigned char*, int, const unsigned char*, int, const unsigned char*, int, unsigned char*, int);
+void receive_rpc_callback(const unsigned char*, int, const unsigned char*, int, int, const unsigned char*, int, long long, unsigned char*, int);
+void peer_disconnected_callback(const unsigned char*, int);
+void peer_subscribed_callback(const unsigned char*, int, const unsigned char*, int);
+void peer_unsubscribed_callback(const unsigned char*, int, const unsigned char*, int);
+void receive_rpc_error_callback(const unsigned char*, int, long long, int, const unsigned char*, int);
+void rpc_failed_callback(const unsigned char*, int, const unsigned char*, int, long long, int, const unsigned char*, int);
+void receive_goodbye_callback(const unsigned char*, int, long long);
+bool check_status_callback(const unsigned char*, int, unsigned char*, int);
+void ip_ban_callback(const unsigned char*, int, const unsigned char*, int, long long);
 
+static void peer_topic_callback(const char*, const unsigned char*, int, const unsigned char*, int);
 static char** copy_array(JNIEnv*, jobjectArray, int);
 static void detach(JNIEnv*);
 #ifdef __cplusplus
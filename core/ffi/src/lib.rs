@@ -1,9 +1,16 @@
 use cast::i16;
-use mothra::{cli_app, gossip, rpc_request, rpc_response, Mothra, NetworkGlobals, NetworkMessage};
+use mothra::{
+    ban_ip, cli_app, disconnect, gossip, ip_bans, rpc_error_response, rpc_request, rpc_response,
+    rpc_stream_end, subscribe, unban_ip, unsubscribe, update_status, validate_gossip,
+    GoodbyeReason, Mothra, MothraHandler, NetworkGlobals, NetworkMessage, RequestId, RpcErrorKind,
+    ValidationResult,
+};
 use std::ffi::CStr;
 use std::os::raw::{c_char, c_uchar};
 use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
 use std::{process, slice, str};
+use tokio::prelude::Future;
 use tokio::sync::{mpsc, oneshot};
 use tokio_compat::runtime::Runtime;
 
@@ -19,6 +26,10 @@ static mut CONTEXT: Vec<Context> = Vec::new();
 
 type DiscoveredPeerType = unsafe extern "C" fn(peer: *const c_uchar, peer_length: i16);
 type ReceiveGossipType = unsafe extern "C" fn(
+    message_id: *const c_uchar,
+    message_id_length: i16,
+    peer: *const c_uchar,
+    peer_length: i16,
     topic: *const c_uchar,
     topic_length: i16,
     data: *mut c_uchar,
@@ -27,44 +38,243 @@ type ReceiveGossipType = unsafe extern "C" fn(
 type ReceiveRpcType = unsafe extern "C" fn(
     method: *const c_uchar,
     method_length: i16,
+    version: *const c_uchar,
+    version_length: i16,
     req_resp: i16,
     peer: *const c_uchar,
     peer_length: i16,
+    request_id: i64,
     data: *mut c_uchar,
     data_length: i16,
 );
+type PeerDisconnectedType = unsafe extern "C" fn(peer: *const c_uchar, peer_length: i16);
+type PeerTopicType = unsafe extern "C" fn(
+    peer: *const c_uchar,
+    peer_length: i16,
+    topic: *const c_uchar,
+    topic_length: i16,
+);
+type ReceiveRpcErrorType = unsafe extern "C" fn(
+    peer: *const c_uchar,
+    peer_length: i16,
+    request_id: i64,
+    code: i16,
+    message: *const c_uchar,
+    message_length: i16,
+);
+type RpcFailedType = unsafe extern "C" fn(
+    method: *const c_uchar,
+    method_length: i16,
+    peer: *const c_uchar,
+    peer_length: i16,
+    request_id: i64,
+    kind: i16,
+    error: *const c_uchar,
+    error_length: i16,
+);
+type ReceiveGoodbyeType = unsafe extern "C" fn(peer: *const c_uchar, peer_length: i16, reason: i64);
+type CheckStatusType = unsafe extern "C" fn(
+    peer: *const c_uchar,
+    peer_length: i16,
+    status: *mut c_uchar,
+    status_length: i16,
+) -> bool;
+type IpBanType = unsafe extern "C" fn(
+    range: *const c_uchar,
+    range_length: i16,
+    reason: *const c_uchar,
+    reason_length: i16,
+    expiry: i64,
+);
 static mut DISCOVERED_PEER_PTR: Option<DiscoveredPeerType> = None;
 static mut RECEIVE_GOSSIP_PTR: Option<ReceiveGossipType> = None;
 static mut RECEIVE_RPC_PTR: Option<ReceiveRpcType> = None;
+static mut PEER_DISCONNECTED_PTR: Option<PeerDisconnectedType> = None;
+static mut PEER_SUBSCRIBED_PTR: Option<PeerTopicType> = None;
+static mut PEER_UNSUBSCRIBED_PTR: Option<PeerTopicType> = None;
+static mut RECEIVE_RPC_ERROR_PTR: Option<ReceiveRpcErrorType> = None;
+static mut RPC_FAILED_PTR: Option<RpcFailedType> = None;
+static mut RECEIVE_GOODBYE_PTR: Option<ReceiveGoodbyeType> = None;
+static mut CHECK_STATUS_PTR: Option<CheckStatusType> = None;
 
-fn discovered_peer(peer: String) {
-    let peer_length = i16(peer.len()).unwrap();
-    unsafe { DISCOVERED_PEER_PTR.unwrap()(peer.as_ptr(), peer_length) };
+/// Forwards network events to the C handlers that were registered when the network started.
+struct FfiHandler {
+    discovered_peer: Option<DiscoveredPeerType>,
+    receive_gossip: Option<ReceiveGossipType>,
+    receive_rpc: Option<ReceiveRpcType>,
+    peer_disconnected: Option<PeerDisconnectedType>,
+    peer_subscribed: Option<PeerTopicType>,
+    peer_unsubscribed: Option<PeerTopicType>,
+    receive_rpc_error: Option<ReceiveRpcErrorType>,
+    rpc_failed: Option<RpcFailedType>,
+    receive_goodbye: Option<ReceiveGoodbyeType>,
+    check_status: Option<CheckStatusType>,
 }
 
-fn receive_gossip(topic: String, mut data: Vec<u8>) {
-    let topic_length = i16(topic.len()).unwrap();
-    let data_length = i16(data.len()).unwrap();
-    unsafe {
-        RECEIVE_GOSSIP_PTR.unwrap()(topic.as_ptr(), topic_length, data.as_mut_ptr(), data_length)
-    };
+/// Calls a handler that takes a peer and a topic.
+fn call_peer_topic(handler: Option<PeerTopicType>, peer: String, topic: String) {
+    if let Some(handler) = handler {
+        let peer_length = i16(peer.len()).unwrap();
+        let topic_length = i16(topic.len()).unwrap();
+        unsafe { handler(peer.as_ptr(), peer_length, topic.as_ptr(), topic_length) };
+    }
 }
 
-fn receive_rpc(method: String, req_resp: u8, peer: String, mut data: Vec<u8>) {
-    let method_length = i16(method.len()).unwrap();
-    let peer_length = i16(peer.len()).unwrap();
-    let data_length = i16(data.len()).unwrap();
-    unsafe {
-        RECEIVE_RPC_PTR.unwrap()(
-            method.as_ptr(),
-            method_length,
-            i16(req_resp),
-            peer.as_ptr(),
-            peer_length,
-            data.as_mut_ptr(),
-            data_length,
-        )
-    };
+impl MothraHandler for FfiHandler {
+    fn discovered_peer(&mut self, peer: String) {
+        if let Some(discovered_peer) = self.discovered_peer {
+            let peer_length = i16(peer.len()).unwrap();
+            unsafe { discovered_peer(peer.as_ptr(), peer_length) };
+        }
+    }
+
+    fn peer_disconnected(&mut self, peer: String) {
+        if let Some(peer_disconnected) = self.peer_disconnected {
+            let peer_length = i16(peer.len()).unwrap();
+            unsafe { peer_disconnected(peer.as_ptr(), peer_length) };
+        }
+    }
+
+    fn peer_subscribed(&mut self, peer: String, topic: String) {
+        call_peer_topic(self.peer_subscribed, peer, topic);
+    }
+
+    fn peer_unsubscribed(&mut self, peer: String, topic: String) {
+        call_peer_topic(self.peer_unsubscribed, peer, topic);
+    }
+
+    fn receive_gossip(
+        &mut self,
+        message_id: String,
+        peer: String,
+        topic: String,
+        mut data: Vec<u8>,
+    ) {
+        if let Some(receive_gossip) = self.receive_gossip {
+            let message_id_length = i16(message_id.len()).unwrap();
+            let peer_length = i16(peer.len()).unwrap();
+            let topic_length = i16(topic.len()).unwrap();
+            let data_length = i16(data.len()).unwrap();
+            unsafe {
+                receive_gossip(
+                    message_id.as_ptr(),
+                    message_id_length,
+                    peer.as_ptr(),
+                    peer_length,
+                    topic.as_ptr(),
+                    topic_length,
+                    data.as_mut_ptr(),
+                    data_length,
+                )
+            };
+        }
+    }
+
+    fn receive_rpc(
+        &mut self,
+        method: String,
+        version: String,
+        req_resp: u8,
+        peer: String,
+        request_id: RequestId,
+        mut data: Vec<u8>,
+    ) {
+        if let Some(receive_rpc) = self.receive_rpc {
+            let method_length = i16(method.len()).unwrap();
+            let version_length = i16(version.len()).unwrap();
+            let peer_length = i16(peer.len()).unwrap();
+            let data_length = i16(data.len()).unwrap();
+            unsafe {
+                receive_rpc(
+                    method.as_ptr(),
+                    method_length,
+                    version.as_ptr(),
+                    version_length,
+                    i16(req_resp),
+                    peer.as_ptr(),
+                    peer_length,
+                    request_id as i64,
+                    data.as_mut_ptr(),
+                    data_length,
+                )
+            };
+        }
+    }
+
+    fn receive_rpc_error(
+        &mut self,
+        peer: String,
+        request_id: RequestId,
+        code: u8,
+        message: String,
+    ) {
+        if let Some(receive_rpc_error) = self.receive_rpc_error {
+            let peer_length = i16(peer.len()).unwrap();
+            let message_length = i16(message.len()).unwrap();
+            unsafe {
+                receive_rpc_error(
+                    peer.as_ptr(),
+                    peer_length,
+                    request_id as i64,
+                    i16(code),
+                    message.as_ptr(),
+                    message_length,
+                )
+            };
+        }
+    }
+
+    fn rpc_failed(
+        &mut self,
+        method: String,
+        peer: String,
+        request_id: RequestId,
+        kind: RpcErrorKind,
+        error: String,
+    ) {
+        if let Some(rpc_failed) = self.rpc_failed {
+            let method_length = i16(method.len()).unwrap();
+            let peer_length = i16(peer.len()).unwrap();
+            let error_length = i16(error.len()).unwrap();
+            unsafe {
+                rpc_failed(
+                    method.as_ptr(),
+                    method_length,
+                    peer.as_ptr(),
+                    peer_length,
+                    request_id as i64,
+                    kind as i16,
+                    error.as_ptr(),
+                    error_length,
+                )
+            };
+        }
+    }
+
+    fn receive_goodbye(&mut self, peer: String, reason: GoodbyeReason) {
+        if let Some(receive_goodbye) = self.receive_goodbye {
+            let peer_length = i16(peer.len()).unwrap();
+            unsafe { receive_goodbye(peer.as_ptr(), peer_length, u64::from(reason) as i64) };
+        }
+    }
+
+    fn check_status(&mut self, peer: String, mut status: Vec<u8>) -> bool {
+        match self.check_status {
+            Some(check_status) => {
+                let peer_length = i16(peer.len()).unwrap();
+                let status_length = i16(status.len()).unwrap();
+                unsafe {
+                    check_status(
+                        peer.as_ptr(),
+                        peer_length,
+                        status.as_mut_ptr(),
+                        status_length,
+                    )
+                }
+            }
+            None => true,
+        }
+    }
 }
 
 #[no_mangle]
@@ -78,6 +288,50 @@ pub unsafe extern "C" fn register_handlers(
     RECEIVE_RPC_PTR = Some(receive_rpc);
 }
 
+/// Registers the handlers for peer membership events. Any of them may be null. Must be called
+/// before `network_start`.
+#[no_mangle]
+pub unsafe extern "C" fn register_peer_handlers(
+    peer_disconnected: Option<PeerDisconnectedType>,
+    peer_subscribed: Option<PeerTopicType>,
+    peer_unsubscribed: Option<PeerTopicType>,
+) {
+    PEER_DISCONNECTED_PTR = peer_disconnected;
+    PEER_SUBSCRIBED_PTR = peer_subscribed;
+    PEER_UNSUBSCRIBED_PTR = peer_unsubscribed;
+}
+
+/// Registers the handler for error responses to RPC requests. It may be null. Must be called
+/// before `network_start`.
+#[no_mangle]
+pub unsafe extern "C" fn register_rpc_error_handler(
+    receive_rpc_error: Option<ReceiveRpcErrorType>,
+) {
+    RECEIVE_RPC_ERROR_PTR = receive_rpc_error;
+}
+
+/// Registers the handler for RPC requests that failed before they were answered. It may be null.
+/// Must be called before `network_start`.
+#[no_mangle]
+pub unsafe extern "C" fn register_rpc_failed_handler(rpc_failed: Option<RpcFailedType>) {
+    RPC_FAILED_PTR = rpc_failed;
+}
+
+/// Registers the handler for Goodbye requests received from peers. It may be null. Must be called
+/// before `network_start`.
+#[no_mangle]
+pub unsafe extern "C" fn register_goodbye_handler(receive_goodbye: Option<ReceiveGoodbyeType>) {
+    RECEIVE_GOODBYE_PTR = receive_goodbye;
+}
+
+/// Registers the predicate that decides whether the status of a peer is compatible with ours,
+/// when the status handshake is enabled. It may be null, in which case every status is accepted.
+/// Must be called before `network_start`.
+#[no_mangle]
+pub unsafe extern "C" fn register_status_handler(check_status: Option<CheckStatusType>) {
+    CHECK_STATUS_PTR = check_status;
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn network_start(
     client_constants: *mut *mut c_char,
@@ -140,14 +394,21 @@ pub unsafe extern "C" fn network_start(
         });
 
     let config = Mothra::get_config(client_name, client_version, protocol_version, &matches);
-    let (network_globals, network_send, network_exit, log) = Mothra::new(
-        config,
-        &runtime.executor(),
-        discovered_peer,
-        receive_gossip,
-        receive_rpc,
-    )
-    .unwrap();
+    // the handlers registered at this point are used for the lifetime of the network
+    let handler = FfiHandler {
+        discovered_peer: DISCOVERED_PEER_PTR,
+        receive_gossip: RECEIVE_GOSSIP_PTR,
+        receive_rpc: RECEIVE_RPC_PTR,
+        peer_disconnected: PEER_DISCONNECTED_PTR,
+        peer_subscribed: PEER_SUBSCRIBED_PTR,
+        peer_unsubscribed: PEER_UNSUBSCRIBED_PTR,
+        receive_rpc_error: RECEIVE_RPC_ERROR_PTR,
+        rpc_failed: RPC_FAILED_PTR,
+        receive_goodbye: RECEIVE_GOODBYE_PTR,
+        check_status: CHECK_STATUS_PTR,
+    };
+    let (network_globals, network_send, network_exit, log) =
+        Mothra::new(config, &runtime.executor(), handler).unwrap();
     CONTEXT.push(Context {
         runtime,
         network_globals,
@@ -172,6 +433,57 @@ pub unsafe extern "C" fn send_gossip(
     );
 }
 
+/// Blocks until the network service has processed the subscription. This must not be called
+/// from within a registered handler.
+#[no_mangle]
+pub unsafe extern "C" fn subscribe_gossip(topic: *mut c_uchar, topic_length: usize) -> bool {
+    subscribe(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(topic, topic_length)).into(),
+        CONTEXT[0].log.clone(),
+    )
+    .wait()
+    .unwrap_or(false)
+}
+
+/// Blocks until the network service has processed the unsubscription. This must not be called
+/// from within a registered handler.
+#[no_mangle]
+pub unsafe extern "C" fn unsubscribe_gossip(topic: *mut c_uchar, topic_length: usize) -> bool {
+    unsubscribe(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(topic, topic_length)).into(),
+        CONTEXT[0].log.clone(),
+    )
+    .wait()
+    .unwrap_or(false)
+}
+
+/// Returns `false` if the peer id is invalid.
+#[no_mangle]
+pub unsafe extern "C" fn validate_gossip_message(
+    message_id: *mut c_uchar,
+    message_id_length: usize,
+    peer: *mut c_uchar,
+    peer_length: usize,
+    validation_result: i16,
+) -> bool {
+    let validation_result = match validation_result {
+        0 => ValidationResult::Accept,
+        1 => ValidationResult::Reject,
+        _ => ValidationResult::Ignore,
+    };
+    validate_gossip(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(message_id, message_id_length)).into(),
+        str::from_utf8_unchecked(slice::from_raw_parts(peer, peer_length)).into(),
+        validation_result,
+        CONTEXT[0].log.clone(),
+    )
+    .is_ok()
+}
+
+/// Returns the id of the request, or zero if the peer id is invalid.
 #[no_mangle]
 pub unsafe extern "C" fn send_rpc_request(
     method: *mut c_uchar,
@@ -180,30 +492,163 @@ pub unsafe extern "C" fn send_rpc_request(
     peer_length: usize,
     data: *mut c_uchar,
     data_length: usize,
-) {
+) -> i64 {
     rpc_request(
         CONTEXT[0].network_send.clone(),
         str::from_utf8_unchecked(slice::from_raw_parts(method, method_length)).into(),
         str::from_utf8_unchecked(slice::from_raw_parts(peer, peer_length)).into(),
         slice::from_raw_parts_mut(data, data_length).to_vec(),
         CONTEXT[0].log.clone(),
-    );
+    )
+    .map(|request_id| request_id as i64)
+    .unwrap_or(0)
 }
 
+/// Returns `false` if the peer id is invalid.
 #[no_mangle]
 pub unsafe extern "C" fn send_rpc_response(
     method: *mut c_uchar,
     method_length: usize,
     peer: *mut c_uchar,
     peer_length: usize,
+    request_id: i64,
     data: *mut c_uchar,
     data_length: usize,
-) {
+) -> bool {
     rpc_response(
         CONTEXT[0].network_send.clone(),
         str::from_utf8_unchecked(slice::from_raw_parts(method, method_length)).into(),
         str::from_utf8_unchecked(slice::from_raw_parts(peer, peer_length)).into(),
+        request_id as RequestId,
         slice::from_raw_parts_mut(data, data_length).to_vec(),
         CONTEXT[0].log.clone(),
+    )
+    .is_ok()
+}
+
+/// Returns `false` if the peer id is invalid.
+#[no_mangle]
+pub unsafe extern "C" fn send_rpc_error_response(
+    peer: *mut c_uchar,
+    peer_length: usize,
+    request_id: i64,
+    code: u8,
+    message: *mut c_uchar,
+    message_length: usize,
+) -> bool {
+    rpc_error_response(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(peer, peer_length)).into(),
+        request_id as RequestId,
+        code,
+        str::from_utf8_unchecked(slice::from_raw_parts(message, message_length)).into(),
+        CONTEXT[0].log.clone(),
+    )
+    .is_ok()
+}
+
+/// Returns `false` if the peer id is invalid.
+#[no_mangle]
+pub unsafe extern "C" fn send_rpc_stream_end(
+    method: *mut c_uchar,
+    method_length: usize,
+    peer: *mut c_uchar,
+    peer_length: usize,
+    request_id: i64,
+) -> bool {
+    rpc_stream_end(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(method, method_length)).into(),
+        str::from_utf8_unchecked(slice::from_raw_parts(peer, peer_length)).into(),
+        request_id as RequestId,
+        CONTEXT[0].log.clone(),
+    )
+    .is_ok()
+}
+
+/// Returns `false` if the peer id is invalid.
+#[no_mangle]
+pub unsafe extern "C" fn disconnect_peer(
+    peer: *mut c_uchar,
+    peer_length: usize,
+    reason: i64,
+) -> bool {
+    disconnect(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(peer, peer_length)).into(),
+        GoodbyeReason::from(reason as u64),
+        CONTEXT[0].log.clone(),
+    )
+    .is_ok()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn update_local_status(status: *mut c_uchar, status_length: usize) {
+    update_status(
+        CONTEXT[0].network_send.clone(),
+        slice::from_raw_parts_mut(status, status_length).to_vec(),
+        CONTEXT[0].log.clone(),
     );
 }
+
+/// Bans an IP address, or a range in CIDR notation, for `duration` seconds, or forever if
+/// `duration` is not positive. Returns `false` if the range is invalid.
+#[no_mangle]
+pub unsafe extern "C" fn ban_ip_range(
+    range: *mut c_uchar,
+    range_length: usize,
+    reason: *mut c_uchar,
+    reason_length: usize,
+    duration: i64,
+) -> bool {
+    let duration = if duration > 0 {
+        Some(Duration::from_secs(duration as u64))
+    } else {
+        None
+    };
+    ban_ip(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(range, range_length)).into(),
+        str::from_utf8_unchecked(slice::from_raw_parts(reason, reason_length)).into(),
+        duration,
+        CONTEXT[0].log.clone(),
+    )
+    .is_ok()
+}
+
+/// Blocks until the network service has removed the ban. Returns `false` if the range is invalid
+/// or was not banned. This must not be called from within a registered handler.
+#[no_mangle]
+pub unsafe extern "C" fn unban_ip_range(range: *mut c_uchar, range_length: usize) -> bool {
+    unban_ip(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(range, range_length)).into(),
+        CONTEXT[0].log.clone(),
+    )
+    .ok()
+    .and_then(|result| result.wait().ok())
+    .unwrap_or(false)
+}
+
+/// Calls `ip_ban` with each banned IP address or range, its reason and its expiry in seconds
+/// since the UNIX epoch, which is zero for permanent bans.
+#[no_mangle]
+pub unsafe extern "C" fn list_ip_bans(ip_ban: IpBanType) {
+    for ban in ip_bans(&CONTEXT[0].network_globals) {
+        let range = ban.range.to_string();
+        let range_length = i16(range.len()).unwrap();
+        let reason_length = i16(ban.reason.len()).unwrap();
+        let expiry = ban
+            .expiry
+            .and_then(|expiry| expiry.duration_since(UNIX_EPOCH).ok())
+            .map(|expiry| expiry.as_secs() as i64)
+            .unwrap_or(0);
+        ip_ban(
+            range.as_ptr(),
+            range_length,
+            ban.reason.as_ptr(),
+            reason_length,
+            expiry,
+        );
+    }
+}
@@ -0,0 +1,467 @@
+//! Bans of IP addresses and CIDR ranges. Unlike bans of peer ids, which a peer can replace for
+//! free, these apply to every peer connecting from or discovered at a banned address. The list is
+//! stored in the network directory so that bans survive restarts.
+//!
+//! The list is stored in `bans.dat`, one ban per line, as the range, the expiry and the reason,
+//! separated by spaces:
+//!
+//! ```text
+//! 10.0.0.0/8 1700000000 spamming gossip
+//! 2001:db8::1 0 manual ban
+//! ```
+//!
+//! The expiry is in seconds since the UNIX epoch, or zero for a permanent ban. The reason is the
+//! rest of the line, and may be empty or contain spaces.
+use crate::multiaddr::Protocol;
+use crate::persistence::{read_lines, write_atomically};
+use crate::Multiaddr;
+use slog::warn;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The file in the network directory where the ban list is stored.
+const BAN_LIST_FILENAME: &str = "bans.dat";
+
+/// A single IP address, or a range of addresses in CIDR notation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpRange {
+    /// The first address of the range. Bits past the prefix are always zero.
+    address: IpAddr,
+    /// The number of leading bits an address must share with `address` to be in the range.
+    prefix_len: u8,
+}
+
+impl IpRange {
+    /// Returns `true` if the address is in this range. IPv4 ranges never contain IPv6 addresses,
+    /// and vice versa.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.address, ip) {
+            (IpAddr::V4(range), IpAddr::V4(ip)) => {
+                let mut ip = ip.octets();
+                mask(&mut ip, self.prefix_len);
+                range.octets() == ip
+            }
+            (IpAddr::V6(range), IpAddr::V6(ip)) => {
+                let mut ip = ip.octets();
+                mask(&mut ip, self.prefix_len);
+                range.octets() == ip
+            }
+            _ => false,
+        }
+    }
+
+    /// The length of a prefix covering a single address.
+    fn max_prefix_len(address: &IpAddr) -> u8 {
+        match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+}
+
+impl From<IpAddr> for IpRange {
+    fn from(address: IpAddr) -> Self {
+        IpRange {
+            address,
+            prefix_len: IpRange::max_prefix_len(&address),
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    /// Parses an address such as `10.0.0.1`, or a range such as `10.0.0.0/8`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(2, '/');
+        let address = parts
+            .next()
+            .unwrap_or_default()
+            .parse::<IpAddr>()
+            .map_err(|_| format!("Invalid IP address: {}", s))?;
+        let max_prefix_len = IpRange::max_prefix_len(&address);
+        let prefix_len = match parts.next() {
+            Some(prefix_len) => prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_prefix_len)
+                .ok_or_else(|| format!("Invalid CIDR prefix length: {}", s))?,
+            None => max_prefix_len,
+        };
+
+        // clear the bits past the prefix, so that equal ranges compare equal
+        let address = match address {
+            IpAddr::V4(address) => {
+                let mut octets = address.octets();
+                mask(&mut octets, prefix_len);
+                IpAddr::V4(Ipv4Addr::from(octets))
+            }
+            IpAddr::V6(address) => {
+                let mut octets = address.octets();
+                mask(&mut octets, prefix_len);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+        };
+        Ok(IpRange {
+            address,
+            prefix_len,
+        })
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.prefix_len == IpRange::max_prefix_len(&self.address) {
+            write!(f, "{}", self.address)
+        } else {
+            write!(f, "{}/{}", self.address, self.prefix_len)
+        }
+    }
+}
+
+/// Clears the bits of an address past the first `prefix_len`.
+fn mask(octets: &mut [u8], prefix_len: u8) {
+    for (i, octet) in octets.iter_mut().enumerate() {
+        let bits_kept = (prefix_len as usize).saturating_sub(i * 8).min(8);
+        *octet &= !(0xffu16 >> bits_kept) as u8;
+    }
+}
+
+/// A banned address or range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IpBan {
+    pub range: IpRange,
+    /// Why the range was banned.
+    pub reason: String,
+    /// When the ban expires, or `None` if it is permanent.
+    pub expiry: Option<SystemTime>,
+}
+
+impl IpBan {
+    /// Creates a ban that lasts for `duration`, or forever if `duration` is `None`.
+    pub fn new(range: IpRange, reason: String, duration: Option<Duration>) -> Self {
+        IpBan {
+            range,
+            reason,
+            expiry: duration.map(|duration| SystemTime::now() + duration),
+        }
+    }
+
+    /// Returns `true` if the ban has expired.
+    pub fn is_expired(&self) -> bool {
+        self.expiry
+            .map(|expiry| expiry <= SystemTime::now())
+            .unwrap_or(false)
+    }
+
+    /// Formats the ban as a line of the ban list file: the range, the expiry in seconds since the
+    /// UNIX epoch (zero if permanent) and the reason.
+    fn to_line(&self) -> String {
+        let expiry = self
+            .expiry
+            .and_then(|expiry| expiry.duration_since(UNIX_EPOCH).ok())
+            .map(|expiry| expiry.as_secs())
+            .unwrap_or(0);
+        let reason = self.reason.replace(|c| c == '\n' || c == '\r', " ");
+        format!("{} {} {}", self.range, expiry, reason)
+    }
+
+    /// Parses a line written by `to_line`.
+    fn from_line(line: &str) -> Result<Self, String> {
+        let mut parts = line.splitn(3, ' ');
+        let range = parts.next().unwrap_or_default().parse::<IpRange>()?;
+        let expiry = parts
+            .next()
+            .and_then(|expiry| expiry.parse::<u64>().ok())
+            .ok_or_else(|| format!("Invalid ban expiry: {}", line))?;
+        let reason = parts.next().unwrap_or_default().to_string();
+        Ok(IpBan {
+            range,
+            reason,
+            expiry: match expiry {
+                0 => None,
+                expiry => Some(UNIX_EPOCH + Duration::from_secs(expiry)),
+            },
+        })
+    }
+}
+
+/// The banned addresses and ranges.
+#[derive(Debug, Default)]
+pub struct BanList {
+    bans: Vec<IpBan>,
+}
+
+impl BanList {
+    /// Loads the ban list stored by `persist`. Returns an empty list if there is no stored list
+    /// or it could not be read. Bans that fail to parse or have expired are skipped.
+    pub fn load(dir: &Path, log: &slog::Logger) -> Self {
+        let bans = read_lines(dir, BAN_LIST_FILENAME, log)
+            .iter()
+            .filter_map(|line| match IpBan::from_line(line) {
+                Ok(ban) => Some(ban),
+                Err(e) => {
+                    warn!(log, "Ban from ban list file could not be parsed"; "error" => e);
+                    None
+                }
+            })
+            .filter(|ban| !ban.is_expired())
+            .collect();
+        BanList { bans }
+    }
+
+    /// Stores the ban list in the network directory, one ban per line. Any previously stored
+    /// list is replaced.
+    pub fn persist(&self, dir: &Path) -> std::io::Result<()> {
+        let lines = self.bans.iter().map(IpBan::to_line).collect::<Vec<_>>();
+        write_atomically(dir, BAN_LIST_FILENAME, &lines)
+    }
+
+    /// Adds a ban, replacing any existing ban of the same range.
+    pub fn add(&mut self, ban: IpBan) {
+        self.bans.retain(|existing| existing.range != ban.range);
+        self.bans.push(ban);
+    }
+
+    /// Removes the ban of a range. Returns `false` if the range was not banned.
+    pub fn remove(&mut self, range: &IpRange) -> bool {
+        let len = self.bans.len();
+        self.bans.retain(|ban| ban.range != *range);
+        self.bans.len() != len
+    }
+
+    /// Removes the bans that have expired. Returns `true` if any were removed.
+    pub fn remove_expired(&mut self) -> bool {
+        let len = self.bans.len();
+        self.bans.retain(|ban| !ban.is_expired());
+        self.bans.len() != len
+    }
+
+    /// The bans that have not expired.
+    pub fn bans(&self) -> Vec<IpBan> {
+        self.bans
+            .iter()
+            .filter(|ban| !ban.is_expired())
+            .cloned()
+            .collect()
+    }
+
+    /// Returns `true` if the address is in a range that is banned.
+    pub fn is_banned(&self, ip: &IpAddr) -> bool {
+        self.bans
+            .iter()
+            .any(|ban| ban.range.contains(ip) && !ban.is_expired())
+    }
+
+    /// Returns `true` if the IP address of the multiaddr is banned. Multiaddrs without an IP
+    /// address, such as DNS addresses, are never banned.
+    pub fn is_banned_multiaddr(&self, multiaddr: &Multiaddr) -> bool {
+        multiaddr_ip(multiaddr)
+            .map(|ip| self.is_banned(&ip))
+            .unwrap_or(false)
+    }
+}
+
+/// The IP address of a multiaddr, if it has one.
+pub fn multiaddr_ip(multiaddr: &Multiaddr) -> Option<IpAddr> {
+    multiaddr.iter().find_map(|protocol| match protocol {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::o;
+    use std::fs;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn range(s: &str) -> IpRange {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_ip_range_from_str() {
+        assert_eq!(range("10.0.0.1").to_string(), "10.0.0.1");
+        assert_eq!(range("10.0.0.1/32").to_string(), "10.0.0.1");
+        assert_eq!(range("10.1.2.3/8").to_string(), "10.0.0.0/8");
+        assert_eq!(range("10.1.2.3/8"), range("10.0.0.0/8"));
+        assert_eq!(range("2001:db8::1/32").to_string(), "2001:db8::/32");
+        assert_eq!(range(" ::1 ").to_string(), "::1");
+
+        assert!("".parse::<IpRange>().is_err());
+        assert!("10.0.0".parse::<IpRange>().is_err());
+        assert!("10.0.0.1/".parse::<IpRange>().is_err());
+        assert!("10.0.0.1/33".parse::<IpRange>().is_err());
+        assert!("::1/129".parse::<IpRange>().is_err());
+        assert!("10.0.0.1/x".parse::<IpRange>().is_err());
+    }
+
+    #[test]
+    fn test_ip_range_contains() {
+        let single = range("10.0.0.1");
+        assert!(single.contains(&ip("10.0.0.1")));
+        assert!(!single.contains(&ip("10.0.0.2")));
+
+        let cidr = range("192.168.0.0/16");
+        assert!(cidr.contains(&ip("192.168.0.0")));
+        assert!(cidr.contains(&ip("192.168.255.255")));
+        assert!(!cidr.contains(&ip("192.169.0.0")));
+
+        let all = range("0.0.0.0/0");
+        assert!(all.contains(&ip("255.255.255.255")));
+
+        let v6 = range("2001:db8::/32");
+        assert!(v6.contains(&ip("2001:db8:ffff::1")));
+        assert!(!v6.contains(&ip("2001:db9::1")));
+
+        // ranges never match addresses of the other family
+        assert!(!all.contains(&ip("::1")));
+        assert!(!range("::/0").contains(&ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn test_mask_non_octet_prefix() {
+        let mut octets = [0xff; 4];
+        mask(&mut octets, 0);
+        assert_eq!(octets, [0, 0, 0, 0]);
+
+        let mut octets = [0xff; 4];
+        mask(&mut octets, 12);
+        assert_eq!(octets, [0xff, 0xf0, 0, 0]);
+
+        let mut octets = [0xff; 4];
+        mask(&mut octets, 31);
+        assert_eq!(octets, [0xff, 0xff, 0xff, 0xfe]);
+
+        let mut octets = [0xff; 4];
+        mask(&mut octets, 32);
+        assert_eq!(octets, [0xff; 4]);
+
+        let cidr = range("10.16.0.0/12");
+        assert!(cidr.contains(&ip("10.31.255.255")));
+        assert!(!cidr.contains(&ip("10.32.0.0")));
+        assert!(!cidr.contains(&ip("10.15.255.255")));
+    }
+
+    #[test]
+    fn test_ban_line_round_trip() {
+        let permanent = IpBan::new(range("10.0.0.0/8"), "manual ban".into(), None);
+        let line = permanent.to_line();
+        assert_eq!(line, "10.0.0.0/8 0 manual ban");
+        assert_eq!(IpBan::from_line(&line).unwrap(), permanent);
+
+        let temporary = IpBan {
+            range: range("2001:db8::1"),
+            reason: "".into(),
+            expiry: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+        };
+        let line = temporary.to_line();
+        assert_eq!(line, "2001:db8::1 1700000000 ");
+        assert_eq!(IpBan::from_line(&line).unwrap(), temporary);
+
+        // line breaks would split the ban across lines
+        let multiline = IpBan::new(range("10.0.0.1"), "a\nb".into(), None);
+        assert_eq!(multiline.to_line(), "10.0.0.1 0 a b");
+
+        assert!(IpBan::from_line("10.0.0.1").is_err());
+        assert!(IpBan::from_line("10.0.0.1 never").is_err());
+        assert!(IpBan::from_line("nonsense 0 reason").is_err());
+    }
+
+    #[test]
+    fn test_ban_expiry() {
+        let permanent = IpBan::new(range("10.0.0.1"), "".into(), None);
+        assert!(!permanent.is_expired());
+
+        let active = IpBan::new(
+            range("10.0.0.2"),
+            "".into(),
+            Some(Duration::from_secs(3600)),
+        );
+        assert!(!active.is_expired());
+
+        let expired = IpBan {
+            range: range("10.0.0.3"),
+            reason: "".into(),
+            expiry: Some(UNIX_EPOCH + Duration::from_secs(1)),
+        };
+        assert!(expired.is_expired());
+
+        let mut ban_list = BanList::default();
+        ban_list.add(permanent);
+        ban_list.add(active);
+        ban_list.add(expired);
+        assert!(ban_list.is_banned(&ip("10.0.0.1")));
+        assert!(ban_list.is_banned(&ip("10.0.0.2")));
+        assert!(!ban_list.is_banned(&ip("10.0.0.3")));
+        assert_eq!(ban_list.bans().len(), 2);
+        assert!(ban_list.remove_expired());
+        assert!(!ban_list.remove_expired());
+    }
+
+    #[test]
+    fn test_ban_list_add_remove() {
+        let mut ban_list = BanList::default();
+        ban_list.add(IpBan::new(range("10.0.0.0/8"), "first".into(), None));
+        ban_list.add(IpBan::new(range("10.1.0.0/8"), "second".into(), None));
+        let bans = ban_list.bans();
+        assert_eq!(bans.len(), 1);
+        assert_eq!(bans[0].reason, "second");
+
+        let multiaddr: Multiaddr = "/ip4/10.2.3.4/tcp/9000".parse().unwrap();
+        assert!(ban_list.is_banned_multiaddr(&multiaddr));
+        let dns: Multiaddr = "/dns4/example.com/tcp/9000".parse().unwrap();
+        assert!(!ban_list.is_banned_multiaddr(&dns));
+
+        assert!(ban_list.remove(&range("10.0.0.0/8")));
+        assert!(!ban_list.remove(&range("10.0.0.0/8")));
+        assert!(!ban_list.is_banned_multiaddr(&multiaddr));
+    }
+
+    #[test]
+    fn test_ban_list_persist_load() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let dir = std::env::temp_dir().join(format!("mothra-ban-list-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        // a missing file loads as an empty list
+        assert!(BanList::load(&dir, &log).bans().is_empty());
+
+        let mut ban_list = BanList::default();
+        let permanent = IpBan::new(range("10.0.0.0/8"), "manual ban".into(), None);
+        let temporary = IpBan {
+            range: range("2001:db8::/32"),
+            reason: "too many peers".into(),
+            // whole seconds, as the file does not store any finer precision
+            expiry: Some(UNIX_EPOCH + Duration::from_secs(4_000_000_000)),
+        };
+        let expired = IpBan {
+            range: range("192.168.0.1"),
+            reason: "".into(),
+            expiry: Some(UNIX_EPOCH + Duration::from_secs(1)),
+        };
+        ban_list.add(permanent.clone());
+        ban_list.add(temporary.clone());
+        ban_list.add(expired);
+        ban_list.persist(&dir).unwrap();
+
+        // expired bans and lines that fail to parse are skipped
+        let mut contents = fs::read_to_string(dir.join(BAN_LIST_FILENAME)).unwrap();
+        contents.push_str("\nnonsense\n\n");
+        fs::write(dir.join(BAN_LIST_FILENAME), contents).unwrap();
+
+        let loaded = BanList::load(&dir, &log);
+        assert_eq!(loaded.bans(), vec![permanent, temporary]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
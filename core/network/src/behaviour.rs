@@ -1,8 +1,13 @@
 use crate::discovery::Discovery;
-use crate::rpc::{RPCEvent, RPCMessage, RPC};
+use crate::rpc::{
+    next_request_id, MetaData, Ping, RPCError, RPCErrorResponse, RPCEvent, RPCMessage, RPCRequest,
+    RPCResponse, RequestId, RPC,
+};
 use crate::{
-    error, Enr, EnrForkId, GossipTopic, NetworkConfig, NetworkGlobals, SubnetId, TopicHash,
+    error, Enr, EnrForkId, ForkDigest, GossipTopic, NetworkConfig, NetworkGlobals, SubnetId,
+    TopicHash,
 };
+use fnv::FnvHashMap;
 use futures::prelude::*;
 use libp2p::{
     core::identity::Keypair,
@@ -16,6 +21,7 @@ use libp2p::{
 use lru::LruCache;
 use slog::{crit, debug, o, warn};
 use std::sync::Arc;
+use std::time::Instant;
 
 const MAX_IDENTIFY_ADDRESSES: usize = 20;
 
@@ -48,6 +54,13 @@ pub struct Behaviour<TSubstream: AsyncRead + AsyncWrite> {
     /// Keeps track of the current EnrForkId for upgrading gossipsub topics.
     #[behaviour(ignore)]
     enr_fork_id: EnrForkId,
+    /// Our outstanding ping and metadata requests, with the time they were sent. Their responses
+    /// are handled here rather than passed to the application.
+    #[behaviour(ignore)]
+    internal_requests: FnvHashMap<RequestId, Instant>,
+    /// Our outstanding metadata requests, by peer, so that only one is sent to a peer at a time.
+    #[behaviour(ignore)]
+    meta_data_requests: FnvHashMap<PeerId, RequestId>,
     /// Logger for behaviour actions.
     #[behaviour(ignore)]
     log: slog::Logger,
@@ -71,7 +84,7 @@ impl<TSubstream: AsyncRead + AsyncWrite> Behaviour<TSubstream> {
         );
 
         Ok(Behaviour {
-            rpc: RPC::new(log),
+            rpc: RPC::new(config, log),
             gossipsub: Gossipsub::new(local_peer_id, config.gs_config.clone()),
             discovery: Discovery::new(
                 local_key,
@@ -85,6 +98,8 @@ impl<TSubstream: AsyncRead + AsyncWrite> Behaviour<TSubstream> {
             seen_gossip_messages: LruCache::new(100_000),
             network_globals,
             enr_fork_id,
+            internal_requests: FnvHashMap::default(),
+            meta_data_requests: FnvHashMap::default(),
             log: behaviour_log,
         })
     }
@@ -96,6 +111,11 @@ impl<TSubstream: AsyncRead + AsyncWrite> Behaviour<TSubstream> {
     pub fn gs(&self) -> &Gossipsub<TSubstream> {
         &self.gossipsub
     }
+
+    /// The digest of the current fork, which prefixes the names of our gossipsub topics.
+    pub fn fork_digest(&self) -> ForkDigest {
+        self.enr_fork_id.fork_digest
+    }
 }
 
 /// Implements the combined behaviour for the libp2p service.
@@ -146,6 +166,144 @@ impl<TSubstream: AsyncRead + AsyncWrite> Behaviour<TSubstream> {
         self.rpc.send_rpc(peer_id, rpc_event);
     }
 
+    /// Pings all connected peers, to check that they are alive and whether their metadata has
+    /// changed.
+    pub fn ping_peers(&mut self) {
+        let peers = self
+            .network_globals
+            .connected_peer_set
+            .read()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        for peer_id in peers {
+            self.ping(peer_id);
+        }
+    }
+
+    /// Sends a ping carrying our metadata sequence number.
+    fn ping(&mut self, peer_id: PeerId) {
+        let request_id = next_request_id();
+        self.internal_requests.insert(request_id, Instant::now());
+        let ping = Ping {
+            data: self.discovery.meta_data().seq_number,
+        };
+        self.rpc.send_rpc(
+            peer_id,
+            RPCEvent::Request(request_id, RPCRequest::Ping(ping)),
+        );
+    }
+
+    /// Requests the metadata of a peer if we have not seen sequence number `seq_number` of it, and
+    /// no request for it is in flight already.
+    fn check_meta_data(&mut self, peer_id: PeerId, seq_number: u64) {
+        if self.meta_data_requests.contains_key(&peer_id) {
+            return;
+        }
+        let known_seq = self
+            .network_globals
+            .connected_peer_set
+            .read()
+            .get(&peer_id)
+            .and_then(|peer_info| peer_info.meta_data_seq);
+        if known_seq != Some(seq_number) {
+            let request_id = next_request_id();
+            self.internal_requests.insert(request_id, Instant::now());
+            self.meta_data_requests.insert(peer_id.clone(), request_id);
+            self.rpc
+                .send_rpc(peer_id, RPCEvent::Request(request_id, RPCRequest::MetaData));
+        }
+    }
+
+    /// Removes a ping or metadata request that was answered or failed. Returns the time it was
+    /// sent, or `None` if `request_id` is not one of our internal requests.
+    fn take_internal_request(
+        &mut self,
+        peer_id: &PeerId,
+        request_id: RequestId,
+    ) -> Option<Instant> {
+        if self.meta_data_requests.get(peer_id) == Some(&request_id) {
+            self.meta_data_requests.remove(peer_id);
+        }
+        self.internal_requests.remove(&request_id)
+    }
+
+    /// Records the metadata of a peer.
+    fn meta_data_received(&mut self, peer_id: &PeerId, meta_data: MetaData) {
+        debug!(self.log, "Received peer metadata"; "peer_id" => format!("{}", peer_id), "seq_number" => meta_data.seq_number);
+        if let Some(peer_info) = self
+            .network_globals
+            .connected_peer_set
+            .write()
+            .get_mut(peer_id)
+        {
+            peer_info.meta_data_seq = Some(meta_data.seq_number);
+            peer_info.enr_bitfield = Some(meta_data.attnets);
+        }
+    }
+
+    /// Records the round-trip time of a ping that was sent at `sent`.
+    fn pong_received(&mut self, peer_id: &PeerId, sent: Instant) {
+        let now = Instant::now();
+        if let Some(peer_info) = self
+            .network_globals
+            .connected_peer_set
+            .write()
+            .get_mut(peer_id)
+        {
+            peer_info.ping_latency = Some(now.duration_since(sent));
+            peer_info.last_seen = Some(now);
+        }
+    }
+
+    /// Handles the ping and metadata requests and responses. Returns the event if it is meant
+    /// for the application.
+    fn handle_internal_rpc(&mut self, peer_id: PeerId, rpc_event: RPCEvent) -> Option<RPCEvent> {
+        match rpc_event {
+            RPCEvent::Request(id, RPCRequest::Ping(ping)) => {
+                let pong = Ping {
+                    data: self.discovery.meta_data().seq_number,
+                };
+                self.rpc.send_rpc(
+                    peer_id.clone(),
+                    RPCEvent::Response(id, RPCErrorResponse::Success(RPCResponse::Pong(pong))),
+                );
+                self.check_meta_data(peer_id, ping.data);
+                None
+            }
+            RPCEvent::Request(id, RPCRequest::MetaData) => {
+                let meta_data = self.discovery.meta_data();
+                self.rpc.send_rpc(
+                    peer_id,
+                    RPCEvent::Response(
+                        id,
+                        RPCErrorResponse::Success(RPCResponse::MetaData(meta_data)),
+                    ),
+                );
+                None
+            }
+            RPCEvent::Response(id, response) => match self.take_internal_request(&peer_id, id) {
+                Some(sent) => {
+                    match response {
+                        RPCErrorResponse::Success(RPCResponse::Pong(ping)) => {
+                            self.pong_received(&peer_id, sent);
+                            self.check_meta_data(peer_id, ping.data);
+                        }
+                        RPCErrorResponse::Success(RPCResponse::MetaData(meta_data)) => {
+                            self.meta_data_received(&peer_id, meta_data);
+                        }
+                        response => {
+                            debug!(self.log, "Unexpected response to a ping or metadata request"; "peer_id" => format!("{}", peer_id), "response" => format!("{:?}", response));
+                        }
+                    }
+                    None
+                }
+                None => Some(RPCEvent::Response(id, response)),
+            },
+            rpc_event => Some(rpc_event),
+        }
+    }
+
     /* Discovery / Peer management functions */
 
     /// Notify discovery that the peer has been banned.
@@ -173,8 +331,9 @@ impl<TSubstream: AsyncRead + AsyncWrite> Behaviour<TSubstream> {
     /// The `value` is `true` if a subnet is being added and false otherwise.
     //TODO: revisit bc update_enr_bitfield requires ssz
     pub fn update_enr_subnet(&mut self, subnet_id: SubnetId, value: bool) {
-        if let Err(e) = self.discovery.update_enr_bitfield(subnet_id, value) {
-            crit!(self.log, "Could not update ENR bitfield"; "error" => e);
+        match self.discovery.update_enr_bitfield(subnet_id, value) {
+            Ok(()) => self.local_enr_updated(),
+            Err(e) => crit!(self.log, "Could not update ENR bitfield"; "error" => e),
         }
     }
 
@@ -183,10 +342,11 @@ impl<TSubstream: AsyncRead + AsyncWrite> Behaviour<TSubstream> {
         self.discovery.peers_request(subnet_id);
     }
 
-    /// Updates the local ENR's "eth2" field with the latest EnrForkId.
-    //TODO: fix the fact that the fork digest isnt updated
+    /// Updates the local ENR's "eth2" field with the latest EnrForkId, and moves our gossipsub
+    /// subscriptions to the topics of the new fork digest.
     pub fn update_fork_version(&mut self, enr_fork_id: EnrForkId) {
         self.discovery.update_eth2_enr(enr_fork_id.clone());
+        self.local_enr_updated();
 
         // unsubscribe from all gossip topics and re-subscribe to their new fork counterparts
         let subscribed_topics = self
@@ -203,15 +363,20 @@ impl<TSubstream: AsyncRead + AsyncWrite> Behaviour<TSubstream> {
         }
 
         // re-subscribe modifying the fork version
-        for topic in subscribed_topics {
-            // *topic.digest() = enr_fork_id.fork_digest;
-            //TODO: fix this
+        for mut topic in subscribed_topics {
+            *topic.digest() = enr_fork_id.fork_digest;
             self.subscribe(topic);
         }
 
         // update the local reference
         self.enr_fork_id = enr_fork_id;
     }
+
+    /// Informs the service that the local ENR has changed.
+    fn local_enr_updated(&mut self) {
+        let enr = self.discovery.local_enr().clone();
+        self.events.push(BehaviourEvent::LocalEnrUpdated(enr));
+    }
 }
 
 // Implement the NetworkBehaviourEventProcess trait so that we can derive NetworkBehaviour for Behaviour
@@ -238,7 +403,10 @@ impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<GossipsubE
                 self.events
                     .push(BehaviourEvent::PeerSubscribed(peer_id, topic));
             }
-            GossipsubEvent::Unsubscribed { .. } => {}
+            GossipsubEvent::Unsubscribed { peer_id, topic } => {
+                self.events
+                    .push(BehaviourEvent::PeerUnsubscribed(peer_id, topic));
+            }
         }
     }
 }
@@ -249,13 +417,40 @@ impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<RPCMessage
     fn inject_event(&mut self, event: RPCMessage) {
         match event {
             RPCMessage::PeerDialed(peer_id) => {
+                self.ping(peer_id.clone());
                 self.events.push(BehaviourEvent::PeerDialed(peer_id))
             }
+            RPCMessage::PeerConnectedIncoming(peer_id) => self
+                .events
+                .push(BehaviourEvent::PeerConnectedIncoming(peer_id)),
             RPCMessage::PeerDisconnected(peer_id) => {
+                self.meta_data_requests.remove(&peer_id);
                 self.events.push(BehaviourEvent::PeerDisconnected(peer_id))
             }
+            RPCMessage::PeerRateLimited(peer_id) => {
+                self.events.push(BehaviourEvent::PeerRateLimited(peer_id))
+            }
             RPCMessage::RPC(peer_id, rpc_event) => {
-                self.events.push(BehaviourEvent::RPC(peer_id, rpc_event))
+                if let Some(rpc_event) = self.handle_internal_rpc(peer_id.clone(), rpc_event) {
+                    self.events.push(BehaviourEvent::RPC(peer_id, rpc_event))
+                }
+            }
+            RPCMessage::RequestFailed {
+                peer_id,
+                request_id,
+                method,
+                error,
+            } => {
+                if self.take_internal_request(&peer_id, request_id).is_some() {
+                    debug!(self.log, "Ping or metadata request failed"; "peer_id" => format!("{}", peer_id), "method" => method, "error" => format!("{}", error));
+                    return;
+                }
+                self.events.push(BehaviourEvent::RPCFailed {
+                    peer_id,
+                    request_id,
+                    method,
+                    error,
+                })
             }
         }
     }
@@ -308,8 +503,11 @@ impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<IdentifyEv
 impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<Discv5Event>
     for Behaviour<TSubstream>
 {
-    fn inject_event(&mut self, _event: Discv5Event) {
-        // discv5 has no events to inject
+    fn inject_event(&mut self, event: Discv5Event) {
+        // discovery only forwards updates of our external socket
+        if let Discv5Event::SocketUpdated(_) = event {
+            self.local_enr_updated();
+        }
     }
 }
 
@@ -317,10 +515,21 @@ impl<TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess<Discv5Even
 pub enum BehaviourEvent {
     /// A received RPC event and the peer that it was received from.
     RPC(PeerId, RPCEvent),
+    /// An outbound RPC request failed before it was answered.
+    RPCFailed {
+        peer_id: PeerId,
+        request_id: RequestId,
+        method: String,
+        error: RPCError,
+    },
     /// We have completed an initial connection to a new peer.
     PeerDialed(PeerId),
+    /// A peer has dialed us.
+    PeerConnectedIncoming(PeerId),
     /// A peer has disconnected.
     PeerDisconnected(PeerId),
+    /// A peer repeatedly exceeded the rate limits of its inbound RPC requests.
+    PeerRateLimited(PeerId),
     /// A gossipsub message has been received.
     GossipMessage {
         /// The gossipsub message id. Used when propagating blocks after validation.
@@ -334,4 +543,8 @@ pub enum BehaviourEvent {
     },
     /// Subscribed to peer for given topic
     PeerSubscribed(PeerId, TopicHash),
+    /// A peer has unsubscribed from the given topic.
+    PeerUnsubscribed(PeerId, TopicHash),
+    /// The local ENR has been updated.
+    LocalEnrUpdated(Enr),
 }
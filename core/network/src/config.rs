@@ -1,5 +1,6 @@
 extern crate target_info;
-use crate::{error, Enr, DEFAULT_CLIENT_NAME};
+use crate::rpc::RPCMethod;
+use crate::{error, Enr, ForkDigest, ScheduledFork, DEFAULT_CLIENT_NAME};
 use libp2p::discv5::{Discv5Config, Discv5ConfigBuilder};
 use libp2p::gossipsub::{GossipsubConfig, GossipsubConfigBuilder, GossipsubMessage, MessageId};
 use libp2p::Multiaddr;
@@ -43,9 +44,13 @@ pub struct Config {
     /// The tcp port to broadcast to peers in order to reach back for libp2p services.
     pub enr_tcp_port: Option<u16>,
 
-    /// Target number of connected peers.
+    /// Target number of connected peers. Peers over this are pruned periodically.
     pub max_peers: usize,
 
+    /// The fraction of `max_peers` kept for peers we dial. Inbound connections are refused once
+    /// they fill the rest, and outbound peers are not pruned below this.
+    pub min_outbound_peers_ratio: f64,
+
     /// A secp256k1 secret key, as bytes in ASCII-encoded hex.
     ///
     /// With or without `0x` prefix.
@@ -69,10 +74,45 @@ pub struct Config {
     /// List of extra topics to initially subscribe to as strings.
     pub topics: Vec<String>,
 
-    /// Introduces randomization in network propagation of messages. This should only be set for
-    /// testing purposes and will likely be removed in future versions.
-    // TODO: Remove this functionality for mainnet
-    pub propagation_percentage: Option<u8>,
+    /// The RPC methods this node supports. Each method is advertised as its own protocol id.
+    pub rpc_methods: Vec<RPCMethod>,
+
+    /// The maximum number of outbound RPC substreams being negotiated at once with a peer. Further
+    /// requests are queued.
+    pub rpc_max_dial_negotiated: u32,
+
+    /// The time (in milliseconds) after which a connection without RPC activity is closed.
+    pub rpc_inactive_timeout: u64,
+
+    /// The maximum number of inbound RPC requests of a peer awaiting responses. Further requests
+    /// are answered with an error.
+    pub rpc_max_inbound_substreams: usize,
+
+    /// The number of inbound RPC requests over the limits after which the score of a peer is
+    /// lowered.
+    pub rpc_max_rate_limit_offences: u32,
+
+    /// Peers whose score falls to or below this are disconnected. Scores range from -100 to 100.
+    pub peer_score_disconnect_threshold: f64,
+
+    /// Peers whose score falls to or below this are banned for `peer_ban_duration`.
+    pub peer_score_ban_threshold: f64,
+
+    /// The time (in seconds) over which the score of a peer decays to half its value.
+    pub peer_score_halflife: u64,
+
+    /// The time (in seconds) a peer whose score fell to the ban threshold is banned for.
+    pub peer_ban_duration: u64,
+
+    /// Exchange statuses with every new peer, and only accept the peers whose status is
+    /// compatible with ours.
+    pub status_handshake: bool,
+
+    /// The digest of the initial fork, used until the first fork of `fork_schedule` activates.
+    pub fork_digest: ForkDigest,
+
+    /// Future forks and their activation times.
+    pub fork_schedule: Vec<ScheduledFork>,
 }
 
 impl Default for Config {
@@ -132,13 +172,25 @@ impl Default for Config {
             enr_udp_port: None,
             enr_tcp_port: None,
             max_peers: 10,
+            min_outbound_peers_ratio: 0.2,
             secret_key_hex: None,
             gs_config,
             discv5_config,
             boot_nodes: vec![],
             libp2p_nodes: vec![],
             topics: vec![],
-            propagation_percentage: None,
+            rpc_methods: vec![RPCMethod::new("hello", "1")],
+            rpc_max_dial_negotiated: 8,
+            rpc_inactive_timeout: 30_000,
+            rpc_max_inbound_substreams: 32,
+            rpc_max_rate_limit_offences: 10,
+            peer_score_disconnect_threshold: -20.0,
+            peer_score_ban_threshold: -50.0,
+            peer_score_halflife: 600,
+            peer_ban_duration: 3600,
+            status_handshake: false,
+            fork_digest: ForkDigest::default(),
+            fork_schedule: vec![],
         }
     }
 }
@@ -91,10 +91,7 @@ fn build_enr(
     builder.tcp(tcp_port);
 
     // set the `eth2` field on our ENR
-
-    // builder.add_value(ETH2_ENR_KEY.into(), enr_fork_id.as_ssz_bytes());
-    // TODO: fix this
-    builder.add_value(ETH2_ENR_KEY.into(), enr_fork_id);
+    builder.add_value(ETH2_ENR_KEY.into(), enr_fork_id.as_ssz_bytes());
 
     // set the "attnets" field on our ENR
     // TODO: fix this
@@ -1,8 +1,11 @@
 ///! This manages the discovery and management of peers.
 mod enr_helpers;
 
+use crate::ban_list::multiaddr_ip;
+use crate::rpc::MetaData;
 use crate::{
-    error, Enr, EnrBitfield, EnrForkId, NetworkConfig, NetworkGlobals, PeerInfo, SubnetId,
+    error, ConnectionDirection, Enr, EnrBitfield, EnrForkId, ForkDigest, NetworkConfig,
+    NetworkGlobals, PeerInfo, SubnetId,
 };
 use enr_helpers::{BITFIELD_ENR_KEY, ETH2_ENR_KEY};
 use futures::prelude::*;
@@ -14,7 +17,7 @@ use libp2p::swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters, Pr
 use slog::{crit, debug, info, trace, warn};
 use ssz::{Decode, Encode};
 use std::collections::HashSet;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::path::Path;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -57,6 +60,10 @@ pub struct Discovery<TSubstream> {
     /// The discovery behaviour used to discover new peers.
     discovery: Discv5<TSubstream>,
 
+    /// An updated external address of the local node, to be reported to the swarm on the next
+    /// poll.
+    observed_address: Option<Multiaddr>,
+
     /// A collection of network constants that can be read from other threads.
     network_globals: Arc<NetworkGlobals>,
 
@@ -121,6 +128,7 @@ impl<TSubstream> Discovery<TSubstream> {
             past_discovery_delay: INITIAL_SEARCH_DELAY,
             tcp_port: config.libp2p_port,
             discovery,
+            observed_address: None,
             network_globals,
             log,
             enr_dir,
@@ -132,6 +140,20 @@ impl<TSubstream> Discovery<TSubstream> {
         self.discovery.local_enr()
     }
 
+    /// Returns the metadata we advertise to our peers. The sequence number of our ENR increases
+    /// whenever our subnets change, so it doubles as the metadata sequence number.
+    pub fn meta_data(&self) -> MetaData {
+        let local_enr = self.discovery.local_enr();
+        let attnets = local_enr
+            .get(BITFIELD_ENR_KEY)
+            .and_then(|bitfield_bytes| EnrBitfield::from_ssz_bytes(bitfield_bytes).ok())
+            .unwrap_or_else(EnrBitfield::new);
+        MetaData {
+            seq_number: local_enr.seq(),
+            attnets,
+        }
+    }
+
     /// Manually search for peers. This restarts the discovery round, sparking multiple rapid
     /// queries.
     pub fn discover_peers(&mut self) {
@@ -161,6 +183,22 @@ impl<TSubstream> Discovery<TSubstream> {
         self.banned_peers.remove(peer_id);
     }
 
+    /// Returns `true` if the ENR of a discovered peer has a banned IP address.
+    fn ip_banned(&mut self, peer_id: &PeerId) -> bool {
+        let enr = match self.discovery.enr_of_peer(peer_id) {
+            Some(enr) => enr,
+            None => return false,
+        };
+        let ban_list = self.network_globals.ban_list.read();
+        enr.ip()
+            .map(|ip| ban_list.is_banned(&IpAddr::V4(ip)))
+            .unwrap_or(false)
+            || enr
+                .ip6()
+                .map(|ip| ban_list.is_banned(&IpAddr::V6(ip)))
+                .unwrap_or(false)
+    }
+
     /// Returns an iterator over all enr entries in the DHT.
     pub fn enr_entries(&mut self) -> impl Iterator<Item = &Enr> {
         self.discovery.enr_entries()
@@ -208,6 +246,9 @@ impl<TSubstream> Discovery<TSubstream> {
             .discovery
             .enr_insert(BITFIELD_ENR_KEY, current_bitfield.as_ssz_bytes());
 
+        // update the network globals
+        *self.network_globals.local_enr.write() = Some(self.discovery.local_enr().clone());
+
         Ok(())
     }
 
@@ -217,7 +258,7 @@ impl<TSubstream> Discovery<TSubstream> {
 
         let _ = self
             .discovery
-            .enr_insert(ETH2_ENR_KEY, enr_fork_id)
+            .enr_insert(ETH2_ENR_KEY, enr_fork_id.as_ssz_bytes())
             .map_err(|e| {
                 warn!(
                     self.log,
@@ -225,6 +266,9 @@ impl<TSubstream> Discovery<TSubstream> {
                     "error" => format!("{:?}", e)
                 )
             });
+
+        // update the network globals
+        *self.network_globals.local_enr.write() = Some(self.discovery.local_enr().clone());
     }
 
     /// A request to find peers on a given subnet.
@@ -308,9 +352,9 @@ impl<TSubstream> Discovery<TSubstream> {
         // pick a random NodeId
         let random_node = NodeId::random();
 
-        let enr_fork_id = self.enr_fork_id();
+        let fork_digest = self.fork_digest();
         // predicate for finding nodes with a matching fork
-        let eth2_fork_predicate = move |enr: &Enr| enr.get(ETH2_ENR_KEY) == Some(&enr_fork_id);
+        let eth2_fork_predicate = move |enr: &Enr| enr_fork_digest(enr) == Some(fork_digest);
         let predicate = move |enr: &Enr| eth2_fork_predicate(enr) && enr_predicate(enr);
 
         // general predicate
@@ -318,19 +362,23 @@ impl<TSubstream> Discovery<TSubstream> {
             .find_enr_predicate(random_node, predicate, num_nodes);
     }
 
-    /// Returns our current `eth2` field as SSZ bytes, associated with the local ENR. We only search for peers
-    /// that have this field.
-    fn enr_fork_id(&self) -> EnrForkId {
-        self.local_enr()
-            .get(ETH2_ENR_KEY)
-            .cloned()
-            .unwrap_or_else(|| {
-                crit!(self.log, "Local ENR has no eth2 field");
-                Vec::new()
-            })
+    /// Returns the fork digest of the `eth2` field of the local ENR. We only search for peers
+    /// with the same fork digest.
+    fn fork_digest(&self) -> ForkDigest {
+        enr_fork_digest(self.local_enr()).unwrap_or_else(|| {
+            crit!(self.log, "Local ENR has no valid eth2 field");
+            ForkDigest::default()
+        })
     }
 }
 
+/// Returns the fork digest of the `eth2` field of an ENR, if it has a valid one.
+fn enr_fork_digest(enr: &Enr) -> Option<ForkDigest> {
+    enr.get(ETH2_ENR_KEY)
+        .and_then(|bytes| EnrForkId::from_ssz_bytes(bytes).ok())
+        .map(|enr_fork_id| enr_fork_id.fork_digest)
+}
+
 // Redirect all behaviour events to underlying discovery behaviour.
 impl<TSubstream> NetworkBehaviour for Discovery<TSubstream>
 where
@@ -348,28 +396,33 @@ where
         self.discovery.addresses_of_peer(peer_id)
     }
 
-    fn inject_connected(&mut self, peer_id: PeerId, _endpoint: ConnectedPoint) {
+    fn inject_connected(&mut self, peer_id: PeerId, endpoint: ConnectedPoint) {
         // Find ENR info about a peer if possible.
+        // The peer is tracked even without a bitfield, which is then taken from its metadata.
         let mut peer_info = PeerInfo::new();
+        let (connection_direction, address) = match endpoint {
+            ConnectedPoint::Dialer { address } => (ConnectionDirection::Outgoing, address),
+            ConnectedPoint::Listener { send_back_addr, .. } => {
+                (ConnectionDirection::Incoming, send_back_addr)
+            }
+        };
+        peer_info.connection_direction = Some(connection_direction);
+        peer_info.ip_address = multiaddr_ip(&address);
         if let Some(enr) = self.discovery.enr_of_peer(&peer_id) {
-            let bitfield = match enr.get(BITFIELD_ENR_KEY) {
+            match enr.get(BITFIELD_ENR_KEY) {
                 Some(bitfield_bytes) => match EnrBitfield::from_ssz_bytes(bitfield_bytes) {
-                    Ok(bitfield) => bitfield,
+                    Ok(bitfield) => peer_info.enr_bitfield = Some(bitfield),
                     Err(e) => {
                         warn!(self.log, "Peer had invalid ENR bitfield"; 
                             "peer_id" => format!("{}", peer_id),
                             "error" => format!("{:?}", e));
-                        return;
                     }
                 },
                 None => {
                     warn!(self.log, "Peer has no ENR bitfield"; 
                     "peer_id" => format!("{}", peer_id));
-                    return;
                 }
-            };
-
-            peer_info.enr_bitfield = Some(bitfield);
+            }
         }
 
         self.network_globals
@@ -411,6 +464,11 @@ where
             Self::OutEvent,
         >,
     > {
+        // report an updated external address before polling discovery again
+        if let Some(address) = self.observed_address.take() {
+            return Async::Ready(NetworkBehaviourAction::ReportObservedAddr { address });
+        }
+
         // search for peers if it is time
         loop {
             match self.peer_discovery_delay.poll() {
@@ -439,7 +497,7 @@ where
                             // peers that get discovered during a query but are not contactable or
                             // don't match a predicate can end up here. For debugging purposes we
                             // log these to see if we are unnecessarily dropping discovered peers
-                            if enr.get(ETH2_ENR_KEY) == Some(&self.enr_fork_id()) {
+                            if enr_fork_digest(&enr) == Some(self.fork_digest()) {
                                 trace!(self.log, "Peer found in process of query"; "peer_id" => format!("{}", enr.peer_id()), "tcp_socket" => enr.tcp_socket());
                             } else {
                                 // this is temporary warning for debugging the DHT
@@ -452,10 +510,13 @@ where
                             address.push(Protocol::Tcp(self.tcp_port));
                             let enr = self.discovery.local_enr();
                             enr_helpers::save_enr_to_disk(Path::new(&self.enr_dir), enr, &self.log);
+                            *self.network_globals.local_enr.write() = Some(enr.clone());
 
-                            return Async::Ready(NetworkBehaviourAction::ReportObservedAddr {
-                                address,
-                            });
+                            // inform the behaviour of the new local ENR, then report the address
+                            self.observed_address = Some(address);
+                            return Async::Ready(NetworkBehaviourAction::GenerateEvent(
+                                Discv5Event::SocketUpdated(socket),
+                            ));
                         }
                         Discv5Event::FindNodeResult { closer_peers, .. } => {
                             // TODO: Modify once ENR predicate search is available
@@ -485,6 +546,7 @@ where
                                         .get(&peer_id)
                                         .is_none()
                                     && !self.banned_peers.contains(&peer_id)
+                                    && !self.ip_banned(&peer_id)
                                 {
                                     debug!(self.log, "Peer discovered"; "peer_id"=> format!("{:?}", peer_id));
                                     return Async::Ready(NetworkBehaviourAction::DialPeer {
@@ -1,18 +1,29 @@
+mod ban_list;
 pub mod behaviour;
 mod config;
 mod discovery;
+mod peer_manager;
+mod persistence;
 pub mod rpc;
 mod service;
 pub mod types;
 
 pub use crate::types::{
-    error, Enr, EnrBitfield, EnrForkId, GossipTopic, NetworkGlobals, PeerInfo, SubnetId,
+    error, parse_fork_digest, ConnectionDirection, Enr, EnrBitfield, EnrForkId, ForkDigest,
+    GossipTopic, NetworkGlobals, PeerInfo, ScheduledFork, SubnetId, FAR_FUTURE_TIME,
 };
+pub use ban_list::{BanList, IpBan, IpRange};
 pub use config::unused_port;
 pub use config::Config as NetworkConfig;
 pub use libp2p::gossipsub::{MessageId, Topic, TopicHash};
 pub use libp2p::{multiaddr, Multiaddr, PeerId, Swarm};
-pub use rpc::{RPCErrorResponse, RPCEvent, RPCRequest, RPCResponse};
+pub use peer_manager::{PeerAction, ScoreState};
+pub use persistence::{read_lines, write_atomically};
+pub use rpc::{
+    next_request_id, parse_methods, validate_methods, ErrorMessage, GoodbyeReason, MetaData, Ping,
+    RPCError, RPCErrorResponse, RPCEvent, RPCLimits, RPCMethod, RPCRequest, RPCResponse, RequestId,
+    STATUS_METHOD,
+};
 pub use service::{Libp2pEvent, Service};
 
 pub const DEFAULT_CLIENT_NAME: &str = "mothra";
@@ -0,0 +1,433 @@
+//! Keeps a reputation score for each peer. The score is lowered when a peer misbehaves and raised
+//! when it behaves well, and decays back to zero over time. Peers whose score falls below the
+//! configured thresholds are disconnected or banned by the service.
+//!
+//! The peer manager also enforces the peer limits: inbound peers over their limit are refused,
+//! and the lowest scoring peers are pruned while we are connected to more than `max_peers`.
+use crate::{NetworkConfig, NetworkGlobals, PeerId};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The highest score a peer can reach.
+const MAX_SCORE: f64 = 100.0;
+/// The lowest score a peer can reach.
+const MIN_SCORE: f64 = -100.0;
+/// The scores of disconnected peers are forgotten once they have decayed closer than this to zero.
+const SCORE_PRUNE_THRESHOLD: f64 = 0.1;
+
+/// An action of a peer that affects its score.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PeerAction {
+    /// A serious fault, such as sending invalid gossip or exceeding the RPC rate limits.
+    LowToleranceError,
+    /// A fault that may be accidental, such as letting a request time out or sending a malformed
+    /// request.
+    MidToleranceError,
+    /// A fault that is expected now and then, such as responding with an error.
+    HighToleranceError,
+    /// Good behaviour, such as a valid response or gossip message.
+    Good,
+}
+
+impl PeerAction {
+    /// The change to the score of a peer.
+    fn score_change(self) -> f64 {
+        match self {
+            PeerAction::LowToleranceError => -10.0,
+            PeerAction::MidToleranceError => -5.0,
+            PeerAction::HighToleranceError => -1.0,
+            PeerAction::Good => 1.0,
+        }
+    }
+}
+
+/// What should be done with a peer after its score changed.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum ScoreState {
+    /// The peer may stay connected.
+    Healthy,
+    /// The peer should be disconnected.
+    Disconnect,
+    /// The peer should be banned.
+    Ban,
+}
+
+/// The score of a peer and when it last decayed.
+#[derive(Debug, Clone)]
+struct PeerScore {
+    score: f64,
+    last_updated: Instant,
+}
+
+/// Tracks the scores of our peers.
+pub struct PeerManager {
+    /// The scores of the peers that have affected their score, including disconnected peers whose
+    /// score has not decayed yet.
+    scores: HashMap<PeerId, PeerScore>,
+    /// Peers whose score falls below this are disconnected.
+    disconnect_threshold: f64,
+    /// Peers whose score falls below this are banned.
+    ban_threshold: f64,
+    /// The time over which a score decays to half its value.
+    halflife: Duration,
+    /// The target number of connected peers.
+    max_peers: usize,
+    /// The number of outbound peers that are never pruned.
+    min_outbound_peers: usize,
+    /// The connected peers, whose `PeerInfo` mirrors their score.
+    network_globals: Arc<NetworkGlobals>,
+}
+
+impl PeerManager {
+    pub fn new(config: &NetworkConfig, network_globals: Arc<NetworkGlobals>) -> Self {
+        PeerManager {
+            scores: HashMap::new(),
+            disconnect_threshold: config.peer_score_disconnect_threshold,
+            ban_threshold: config.peer_score_ban_threshold,
+            halflife: Duration::from_secs(config.peer_score_halflife),
+            max_peers: config.max_peers,
+            min_outbound_peers: (config.max_peers as f64 * config.min_outbound_peers_ratio).ceil()
+                as usize,
+            network_globals,
+        }
+    }
+
+    /// The current score of a peer. Peers start with a score of zero.
+    pub fn score(&self, peer_id: &PeerId) -> f64 {
+        self.scores
+            .get(peer_id)
+            .map(|score| score.score)
+            .unwrap_or_default()
+    }
+
+    /// Updates the score of a peer after an action. Returns the new state of the peer if it is
+    /// worse than before the action, so that the peer is only disconnected or banned once.
+    pub fn report_peer(&mut self, peer_id: &PeerId, action: PeerAction) -> Option<ScoreState> {
+        let halflife = self.halflife;
+        let now = Instant::now();
+        let peer_score = self.scores.entry(peer_id.clone()).or_insert(PeerScore {
+            score: 0.0,
+            last_updated: now,
+        });
+        decay(peer_score, halflife, now);
+        let old_score = peer_score.score;
+        peer_score.score = (old_score + action.score_change())
+            .max(MIN_SCORE)
+            .min(MAX_SCORE);
+        let new_score = peer_score.score;
+        self.update_peer_info(peer_id, new_score);
+
+        let old_state = self.state(old_score);
+        let new_state = self.state(new_score);
+        if new_state > old_state {
+            Some(new_state)
+        } else {
+            None
+        }
+    }
+
+    /// Decays all scores, and forgets the disconnected peers whose score has decayed to zero.
+    pub fn heartbeat(&mut self) {
+        let halflife = self.halflife;
+        let now = Instant::now();
+        let connected_peers = self.network_globals.connected_peer_set.read();
+        self.scores.retain(|peer_id, peer_score| {
+            decay(peer_score, halflife, now);
+            connected_peers.contains_key(peer_id) || peer_score.score.abs() > SCORE_PRUNE_THRESHOLD
+        });
+        drop(connected_peers);
+
+        let scores: Vec<(PeerId, f64)> = self
+            .scores
+            .iter()
+            .map(|(peer_id, peer_score)| (peer_id.clone(), peer_score.score))
+            .collect();
+        for (peer_id, score) in scores {
+            self.update_peer_info(&peer_id, score);
+        }
+    }
+
+    /// Returns `true` if more peers dialed us than the slots not kept for outbound peers allow.
+    pub fn inbound_limit_exceeded(&self) -> bool {
+        let max_inbound_peers = self.max_peers.saturating_sub(self.min_outbound_peers);
+        self.network_globals.connected_inbound_peers() > max_inbound_peers
+    }
+
+    /// The peers to disconnect to bring us back to `max_peers`, lowest score first. Inbound peers
+    /// go before outbound peers of the same score, and outbound peers are kept while there are
+    /// no more of them than the minimum.
+    pub fn peers_to_prune(&self) -> Vec<PeerId> {
+        let connected_peers = self.network_globals.connected_peer_set.read();
+        let excess = connected_peers.len().saturating_sub(self.max_peers);
+        if excess == 0 {
+            return Vec::new();
+        }
+
+        let mut outbound_peers = connected_peers
+            .values()
+            .filter(|peer_info| peer_info.is_outgoing())
+            .count();
+        let mut candidates: Vec<(&PeerId, bool)> = connected_peers
+            .iter()
+            .map(|(peer_id, peer_info)| (peer_id, peer_info.is_outgoing()))
+            .collect();
+        candidates.sort_by(|(a, a_outgoing), (b, b_outgoing)| {
+            self.score(a)
+                .partial_cmp(&self.score(b))
+                .unwrap_or(Ordering::Equal)
+                .then(a_outgoing.cmp(b_outgoing))
+        });
+
+        let mut to_prune = Vec::with_capacity(excess);
+        for (peer_id, outgoing) in candidates {
+            if to_prune.len() == excess {
+                break;
+            }
+            if outgoing {
+                if outbound_peers <= self.min_outbound_peers {
+                    continue;
+                }
+                outbound_peers -= 1;
+            }
+            to_prune.push(peer_id.clone());
+        }
+        to_prune
+    }
+
+    /// The state of a peer with the given score.
+    fn state(&self, score: f64) -> ScoreState {
+        if score <= self.ban_threshold {
+            ScoreState::Ban
+        } else if score <= self.disconnect_threshold {
+            ScoreState::Disconnect
+        } else {
+            ScoreState::Healthy
+        }
+    }
+
+    /// Mirrors the score of a connected peer in its `PeerInfo`.
+    fn update_peer_info(&self, peer_id: &PeerId, score: f64) {
+        if let Some(peer_info) = self
+            .network_globals
+            .connected_peer_set
+            .write()
+            .get_mut(peer_id)
+        {
+            peer_info.score = score;
+        }
+    }
+}
+
+/// Decays a score exponentially towards zero, halving it every `halflife`.
+fn decay(peer_score: &mut PeerScore, halflife: Duration, now: Instant) {
+    let elapsed = now.duration_since(peer_score.last_updated);
+    peer_score.last_updated = now;
+    if halflife.as_secs_f64() > 0.0 {
+        peer_score.score *= 0.5f64.powf(elapsed.as_secs_f64() / halflife.as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BanList, ConnectionDirection, PeerInfo};
+    use ConnectionDirection::{Incoming, Outgoing};
+
+    #[test]
+    fn test_report_peer_transitions() {
+        let network_globals = NetworkGlobals::new(PeerId::random(), 9000, 9000, BanList::default());
+        let mut peer_manager =
+            PeerManager::new(&NetworkConfig::default(), Arc::new(network_globals));
+        let peer_id = PeerId::random();
+
+        // the default thresholds are -20 to disconnect and -50 to ban
+        let states = peer_manager.report_peer(&peer_id, PeerAction::Good);
+        assert_eq!(states, None);
+        let states = (0..7)
+            .map(|_| peer_manager.report_peer(&peer_id, PeerAction::LowToleranceError))
+            .collect::<Vec<_>>();
+        // the peer is only disconnected, and banned, once
+        assert_eq!(
+            states,
+            vec![
+                None,
+                None,
+                Some(ScoreState::Disconnect),
+                None,
+                None,
+                Some(ScoreState::Ban),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_score_bounds() {
+        let network_globals = NetworkGlobals::new(PeerId::random(), 9000, 9000, BanList::default());
+        let mut peer_manager =
+            PeerManager::new(&NetworkConfig::default(), Arc::new(network_globals));
+        let good = PeerId::random();
+        let bad = PeerId::random();
+        for _ in 0..200 {
+            peer_manager.report_peer(&good, PeerAction::Good);
+            peer_manager.report_peer(&bad, PeerAction::LowToleranceError);
+        }
+        assert!(peer_manager.score(&good) <= MAX_SCORE);
+        assert!(peer_manager.score(&good) > MAX_SCORE - 1.0);
+        assert!(peer_manager.score(&bad) >= MIN_SCORE);
+        assert!(peer_manager.score(&bad) < MIN_SCORE + 1.0);
+        assert_eq!(peer_manager.score(&PeerId::random()), 0.0);
+    }
+
+    #[test]
+    fn test_report_peer_mirrors_peer_info() {
+        let network_globals = Arc::new(NetworkGlobals::new(
+            PeerId::random(),
+            9000,
+            9000,
+            BanList::default(),
+        ));
+        let mut peer_manager = PeerManager::new(&NetworkConfig::default(), network_globals.clone());
+        let peer_id = PeerId::random();
+        network_globals
+            .connected_peer_set
+            .write()
+            .insert(peer_id.clone(), PeerInfo::default());
+
+        peer_manager.report_peer(&peer_id, PeerAction::MidToleranceError);
+        let score = network_globals.connected_peer_set.read()[&peer_id].score;
+        assert_eq!(score, peer_manager.score(&peer_id));
+        assert!(score < 0.0);
+    }
+
+    #[test]
+    fn test_decay() {
+        let now = Instant::now();
+        let halflife = Duration::from_secs(600);
+        let mut peer_score = PeerScore {
+            score: -40.0,
+            last_updated: now,
+        };
+
+        decay(&mut peer_score, halflife, now);
+        assert_eq!(peer_score.score, -40.0);
+
+        decay(&mut peer_score, halflife, now + halflife);
+        assert!((peer_score.score + 20.0).abs() < 1e-9);
+        assert_eq!(peer_score.last_updated, now + halflife);
+
+        decay(&mut peer_score, halflife, now + halflife * 3);
+        assert!((peer_score.score + 5.0).abs() < 1e-9);
+
+        // a halflife of zero disables decay
+        let mut peer_score = PeerScore {
+            score: 10.0,
+            last_updated: now,
+        };
+        decay(&mut peer_score, Duration::from_secs(0), now + halflife);
+        assert_eq!(peer_score.score, 10.0);
+    }
+
+    #[test]
+    fn test_prune_lowest_score_inbound_first() {
+        let network_globals = Arc::new(NetworkGlobals::new(
+            PeerId::random(),
+            9000,
+            9000,
+            BanList::default(),
+        ));
+        let config = NetworkConfig {
+            max_peers: 2,
+            min_outbound_peers_ratio: 0.5,
+            ..NetworkConfig::default()
+        };
+        let mut peer_manager = PeerManager::new(&config, network_globals.clone());
+        let mut peers = Vec::new();
+        for &direction in &[Outgoing, Outgoing, Incoming, Incoming] {
+            let peer_id = PeerId::random();
+            let peer_info = PeerInfo {
+                connection_direction: Some(direction),
+                ..PeerInfo::default()
+            };
+            network_globals
+                .connected_peer_set
+                .write()
+                .insert(peer_id.clone(), peer_info);
+            peers.push(peer_id);
+
+            // nothing is pruned within the peer limit
+            if peers.len() <= 2 {
+                assert!(peer_manager.peers_to_prune().is_empty());
+            }
+        }
+        peer_manager.report_peer(&peers[0], PeerAction::MidToleranceError);
+        peer_manager.report_peer(&peers[3], PeerAction::Good);
+
+        // the inbound peer goes before the outbound peer of the same score
+        assert_eq!(
+            peer_manager.peers_to_prune(),
+            vec![peers[0].clone(), peers[2].clone()]
+        );
+    }
+
+    #[test]
+    fn test_prune_keeps_min_outbound_peers() {
+        let network_globals = Arc::new(NetworkGlobals::new(
+            PeerId::random(),
+            9000,
+            9000,
+            BanList::default(),
+        ));
+        let config = NetworkConfig {
+            max_peers: 1,
+            min_outbound_peers_ratio: 1.0,
+            ..NetworkConfig::default()
+        };
+        let mut peer_manager = PeerManager::new(&config, network_globals.clone());
+        let (outbound, inbound) = (PeerId::random(), PeerId::random());
+        for (peer_id, direction) in &[(&outbound, Outgoing), (&inbound, Incoming)] {
+            let peer_info = PeerInfo {
+                connection_direction: Some(*direction),
+                ..PeerInfo::default()
+            };
+            network_globals
+                .connected_peer_set
+                .write()
+                .insert((*peer_id).clone(), peer_info);
+        }
+        peer_manager.report_peer(&outbound, PeerAction::LowToleranceError);
+
+        // the only outbound peer is kept, even though its score is the lowest
+        assert_eq!(peer_manager.peers_to_prune(), vec![inbound]);
+    }
+
+    #[test]
+    fn test_inbound_limit() {
+        let network_globals = Arc::new(NetworkGlobals::new(
+            PeerId::random(),
+            9000,
+            9000,
+            BanList::default(),
+        ));
+        let config = NetworkConfig {
+            max_peers: 2,
+            min_outbound_peers_ratio: 0.5,
+            ..NetworkConfig::default()
+        };
+        let peer_manager = PeerManager::new(&config, network_globals.clone());
+        // one of the two slots is kept for outbound peers
+        for &(direction, exceeded) in &[(Incoming, false), (Outgoing, false), (Incoming, true)] {
+            let peer_info = PeerInfo {
+                connection_direction: Some(direction),
+                ..PeerInfo::default()
+            };
+            network_globals
+                .connected_peer_set
+                .write()
+                .insert(PeerId::random(), peer_info);
+            assert_eq!(peer_manager.inbound_limit_exceeded(), exceeded);
+        }
+    }
+}
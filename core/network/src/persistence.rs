@@ -0,0 +1,32 @@
+//! Reads and writes the files stored in the network directory, such as the ban list and the DHT.
+//! Each file holds one entry per line.
+use slog::debug;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::Path;
+
+/// Reads the non-empty lines of a file in `dir`, with surrounding whitespace trimmed. Returns no
+/// lines if the file does not exist or could not be read.
+pub fn read_lines(dir: &Path, filename: &str, log: &slog::Logger) -> Vec<String> {
+    let file = dir.join(filename);
+    let mut contents = String::new();
+    if let Err(e) = File::open(&file).and_then(|mut f| f.read_to_string(&mut contents)) {
+        debug!(log, "Could not read file"; "file" => format!("{:?}", file), "error" => format!("{}", e));
+        return Vec::new();
+    }
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Replaces a file in `dir` with the given lines, creating the directory if needed.
+pub fn write_atomically(dir: &Path, filename: &str, lines: &[String]) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    // write to a temporary file first, so that a crash never leaves a truncated file behind
+    let tmp_file = dir.join(format!("{}.tmp", filename));
+    File::create(&tmp_file).and_then(|mut f| f.write_all(lines.join("\n").as_bytes()))?;
+    fs::rename(tmp_file, dir.join(filename))
+}
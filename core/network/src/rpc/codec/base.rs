@@ -62,9 +62,11 @@ where
     type Error = <TCodec as Encoder>::Error;
 
     fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        dst.clear();
-        dst.reserve(1);
-        dst.put_u8(item.as_u8());
+        // responses of a stream are appended to any chunks that have not been flushed yet
+        if let Some(response_code) = item.as_u8() {
+            dst.reserve(1);
+            dst.put_u8(response_code);
+        }
         self.inner.encode(item, dst)
     }
 }
@@ -105,8 +107,10 @@ where
             if let Some(resp_code) = self.response_code {
                 resp_code
             } else {
-                // buffer should not be empty
-                debug_assert!(!src.is_empty());
+                // wait for the response code of the next chunk
+                if src.is_empty() {
+                    return Ok(None);
+                }
 
                 let resp_byte = src.split_to(1);
                 let mut resp_code_byte = [0; 1];
@@ -118,7 +122,7 @@ where
             }
         };
 
-        if RPCErrorResponse::is_response(response_code) {
+        let response = if RPCErrorResponse::is_response(response_code) {
             // decode an actual response
             self.inner
                 .decode(src)
@@ -128,6 +132,143 @@ where
             self.inner
                 .decode_error(src)
                 .map(|r| r.map(|resp| RPCErrorResponse::from_error(response_code, resp)))
+        };
+
+        // a stream may contain several chunks, each with its own response code
+        if let Ok(Some(_)) = response {
+            self.response_code = None;
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::codec::serenity::{SerenityInboundCodec, SerenityOutboundCodec};
+    use crate::rpc::codec::ssz_snappy::{SSZSnappyInboundCodec, SSZSnappyOutboundCodec};
+    use crate::rpc::protocol::{ProtocolId, RPCError};
+
+    const MAX_PACKET_SIZE: usize = 1024;
+
+    fn message(bytes: &[u8]) -> RPCErrorResponse {
+        RPCErrorResponse::Success(RPCResponse::Message(
+            "blocks".into(),
+            "1".into(),
+            bytes.to_vec(),
+        ))
+    }
+
+    fn error(bytes: &[u8]) -> ErrorMessage {
+        ErrorMessage {
+            error_message: bytes.to_vec(),
+        }
+    }
+
+    /// Encodes a stream of responses, and decodes it again a byte at a time.
+    fn round_trip<TIn, TOut>(
+        mut inbound: BaseInboundCodec<TIn>,
+        mut outbound: BaseOutboundCodec<TOut>,
+        responses: Vec<RPCErrorResponse>,
+    ) -> Vec<RPCErrorResponse>
+    where
+        TIn: Decoder + Encoder<Item = RPCErrorResponse, Error = RPCError>,
+        TOut:
+            OutboundCodec<ErrorType = ErrorMessage> + Decoder<Item = RPCResponse, Error = RPCError>,
+    {
+        let mut encoded = BytesMut::new();
+        for response in responses {
+            inbound.encode(response, &mut encoded).unwrap();
+        }
+
+        let mut decoded = Vec::new();
+        let mut src = BytesMut::new();
+        for byte in encoded.iter() {
+            src.extend_from_slice(&[*byte]);
+            while let Some(response) = outbound.decode(&mut src).unwrap() {
+                decoded.push(response);
+            }
+        }
+        assert!(src.is_empty());
+        decoded
+    }
+
+    fn check_responses(decoded: Vec<RPCErrorResponse>) {
+        assert_eq!(decoded.len(), 5);
+        match &decoded[0] {
+            RPCErrorResponse::Success(RPCResponse::Message(method, version, bytes)) => {
+                assert_eq!((method.as_str(), version.as_str()), ("blocks", "1"));
+                assert_eq!(bytes, &vec![1, 2, 3]);
+            }
+            response => panic!("Unexpected response: {:?}", response),
+        }
+        match &decoded[1] {
+            RPCErrorResponse::Success(RPCResponse::Message(_, _, bytes)) => {
+                assert_eq!(bytes, &vec![4])
+            }
+            response => panic!("Unexpected response: {:?}", response),
+        }
+        match &decoded[2] {
+            RPCErrorResponse::InvalidRequest(err) => assert_eq!(err.as_string(), "invalid"),
+            response => panic!("Unexpected response: {:?}", response),
+        }
+        match &decoded[3] {
+            RPCErrorResponse::ServerError(err) => assert_eq!(err.as_string(), "server"),
+            response => panic!("Unexpected response: {:?}", response),
+        }
+        match &decoded[4] {
+            RPCErrorResponse::Unknown(err) => assert_eq!(err.as_string(), "unknown"),
+            response => panic!("Unexpected response: {:?}", response),
+        }
+    }
+
+    fn responses() -> Vec<RPCErrorResponse> {
+        vec![
+            message(&[1, 2, 3]),
+            message(&[4]),
+            // the end of a stream is not encoded
+            RPCErrorResponse::StreamTermination("blocks".into()),
+            RPCErrorResponse::InvalidRequest(error(b"invalid")),
+            RPCErrorResponse::ServerError(error(b"server")),
+            RPCErrorResponse::Unknown(error(b"unknown")),
+        ]
+    }
+
+    #[test]
+    fn test_serenity_multiple_chunks() {
+        let protocol = ProtocolId::new("blocks", "1", "ssz");
+        let decoded = round_trip(
+            BaseInboundCodec::new(SerenityInboundCodec::new(protocol.clone(), MAX_PACKET_SIZE)),
+            BaseOutboundCodec::new(SerenityOutboundCodec::new(protocol, MAX_PACKET_SIZE)),
+            responses(),
+        );
+        check_responses(decoded);
+    }
+
+    #[test]
+    fn test_ssz_snappy_multiple_chunks() {
+        let protocol = ProtocolId::new("blocks", "1", "ssz_snappy");
+        let decoded = round_trip(
+            BaseInboundCodec::new(SSZSnappyInboundCodec::new(
+                protocol.clone(),
+                MAX_PACKET_SIZE,
+            )),
+            BaseOutboundCodec::new(SSZSnappyOutboundCodec::new(protocol, MAX_PACKET_SIZE)),
+            responses(),
+        );
+        check_responses(decoded);
+    }
+
+    #[test]
+    fn test_unknown_response_code() {
+        let protocol = ProtocolId::new("blocks", "1", "ssz");
+        let mut outbound =
+            BaseOutboundCodec::new(SerenityOutboundCodec::new(protocol, MAX_PACKET_SIZE));
+        let mut src = BytesMut::from(&[7, 2, b'h', b'i'][..]);
+        match outbound.decode(&mut src).unwrap() {
+            Some(RPCErrorResponse::Unknown(err)) => assert_eq!(err.as_string(), "hi"),
+            response => panic!("Unexpected response: {:?}", response),
         }
+        assert!(outbound.decode(&mut src).unwrap().is_none());
     }
 }
@@ -36,19 +36,16 @@ impl Encoder for SerenityInboundCodec {
 
     fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
         let bytes = match item {
-            RPCErrorResponse::Success(resp) => match resp {
-                RPCResponse::Message(res) => Bytes::from(res),
-            },
+            RPCErrorResponse::Success(resp) => Bytes::from(resp.into_bytes()),
             RPCErrorResponse::InvalidRequest(err) => Bytes::from(err.as_string()),
             RPCErrorResponse::ServerError(err) => Bytes::from(err.as_string()),
             RPCErrorResponse::Unknown(err) => Bytes::from(err.as_string()),
+            // the end of a stream is signalled by closing it
+            RPCErrorResponse::StreamTermination(_) => return Ok(()),
         };
 
-        if !bytes.is_empty() {
-            // length-prefix and return
-            return self.inner.encode(bytes, dst).map_err(RPCError::from);
-        }
-        Ok(())
+        // length-prefix and return
+        self.inner.encode(bytes, dst).map_err(RPCError::from)
     }
 }
 
@@ -59,13 +56,7 @@ impl Decoder for SerenityInboundCodec {
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
         match self.inner.decode(src).map_err(RPCError::from) {
-            Ok(Some(packet)) => match self.protocol.message_name.as_str() {
-                "hello" => match self.protocol.version.as_str() {
-                    "1" => Ok(Some(RPCRequest::Message(packet.to_vec()))),
-                    _ => Err(RPCError::InvalidProtocol("Unknown Message version")),
-                },
-                _ => Err(RPCError::InvalidProtocol("Unknown message name.")),
-            },
+            Ok(Some(packet)) => RPCRequest::from_bytes(&self.protocol, packet.to_vec()).map(Some),
             Ok(None) => Ok(None),
             Err(e) => Err(e),
         }
@@ -100,9 +91,7 @@ impl Encoder for SerenityOutboundCodec {
     type Error = RPCError;
 
     fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let bytes = match item {
-            RPCRequest::Message(req) => Bytes::from(req),
-        };
+        let bytes = Bytes::from(item.into_bytes());
         // length-prefix
         self.inner.encode(bytes, dst).map_err(RPCError::from)
     }
@@ -115,13 +104,7 @@ impl Decoder for SerenityOutboundCodec {
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
         match self.inner.decode(src).map_err(RPCError::from) {
-            Ok(Some(packet)) => match self.protocol.message_name.as_str() {
-                "hello" => match self.protocol.version.as_str() {
-                    "1" => Ok(Some(RPCResponse::Message(packet.to_vec()))),
-                    _ => Err(RPCError::InvalidProtocol("Unknown rpc message version.")),
-                },
-                _ => Err(RPCError::InvalidProtocol("Unknown method")),
-            },
+            Ok(Some(packet)) => RPCResponse::from_bytes(&self.protocol, packet.to_vec()).map(Some),
             Ok(None) => Ok(None),
             Err(e) => Err(e),
         }
@@ -133,7 +116,9 @@ impl OutboundCodec for SerenityOutboundCodec {
 
     fn decode_error(&mut self, src: &mut BytesMut) -> Result<Option<Self::ErrorType>, RPCError> {
         match self.inner.decode(src).map_err(RPCError::from) {
-            Ok(Some(_packet)) => Ok(None),
+            Ok(Some(packet)) => Ok(Some(ErrorMessage {
+                error_message: packet.to_vec(),
+            })),
             Ok(None) => Ok(None),
             Err(e) => Err(e),
         }
@@ -0,0 +1,299 @@
+use crate::rpc::{
+    codec::base::OutboundCodec,
+    protocol::{ProtocolId, RPCError},
+};
+use crate::rpc::{ErrorMessage, RPCErrorResponse, RPCRequest, RPCResponse};
+use bytes::BytesMut;
+use snap::read::FrameDecoder;
+use snap::write::FrameEncoder;
+use std::io::{Read, Write};
+use tokio::codec::{Decoder, Encoder};
+use unsigned_varint::codec::Uvi;
+
+/// Encodes a payload as its uncompressed length, followed by the payload compressed with the
+/// snappy frame format.
+fn encode_snappy(
+    inner: &mut Uvi<usize>,
+    payload: &[u8],
+    dst: &mut BytesMut,
+) -> Result<(), RPCError> {
+    // length-prefix with the uncompressed length
+    inner.encode(payload.len(), dst).map_err(RPCError::from)?;
+
+    let mut writer = FrameEncoder::new(Vec::new());
+    writer.write_all(payload).map_err(RPCError::from)?;
+    writer.flush().map_err(RPCError::from)?;
+    dst.extend_from_slice(writer.get_ref());
+    Ok(())
+}
+
+/// The snappy frame chunk type of compressed data.
+const COMPRESSED_CHUNK: u8 = 0x00;
+/// The snappy frame chunk type of uncompressed data.
+const UNCOMPRESSED_CHUNK: u8 = 0x01;
+/// The reserved chunk types that cannot be skipped, unlike padding and the stream identifier.
+const RESERVED_CHUNKS: std::ops::Range<u8> = 0x02..0x80;
+/// The size of a chunk header: a 1-byte chunk type and a 3-byte little-endian length.
+const CHUNK_HEADER_SIZE: usize = 4;
+/// The size of the checksum that precedes the data of a data chunk.
+const CHECKSUM_SIZE: usize = 4;
+
+/// Scans the chunk headers of the snappy frames in `src`, without decompressing them. Returns the
+/// number of bytes that hold `length` uncompressed bytes, or `None` if they have not all been
+/// received yet.
+fn compressed_len(src: &[u8], length: usize) -> Result<Option<usize>, RPCError> {
+    let mut position = 0;
+    let mut uncompressed = 0;
+    while uncompressed < length {
+        let header = match src.get(position..position + CHUNK_HEADER_SIZE) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let chunk_len =
+            usize::from(header[1]) | (usize::from(header[2]) << 8) | (usize::from(header[3]) << 16);
+        let chunk_start = position + CHUNK_HEADER_SIZE;
+        let chunk = match src.get(chunk_start..chunk_start + chunk_len) {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+        let data = chunk.get(CHECKSUM_SIZE..).unwrap_or_default();
+        match header[0] {
+            COMPRESSED_CHUNK => {
+                uncompressed += snap::raw::decompress_len(data)
+                    .map_err(|e| RPCError::Custom(format!("Invalid snappy chunk: {}", e)))?
+            }
+            UNCOMPRESSED_CHUNK => uncompressed += data.len(),
+            chunk_type if RESERVED_CHUNKS.contains(&chunk_type) => {
+                return Err(RPCError::Custom(format!(
+                    "Reserved snappy chunk type: {}",
+                    chunk_type
+                )))
+            }
+            // the stream identifier and padding hold no data
+            _ => {}
+        }
+        position = chunk_start + chunk_len;
+    }
+    Ok(Some(position))
+}
+
+/// Decodes a payload encoded by `encode_snappy`. The length prefix is stored in `len` until the
+/// whole compressed payload has been received, which is only decompressed once it is complete.
+fn decode_snappy(
+    inner: &mut Uvi<usize>,
+    len: &mut Option<usize>,
+    max_packet_size: usize,
+    src: &mut BytesMut,
+) -> Result<Option<Vec<u8>>, RPCError> {
+    let length = match *len {
+        Some(length) => length,
+        None => match inner.decode(src).map_err(RPCError::from)? {
+            Some(length) => {
+                *len = Some(length);
+                length
+            }
+            None => return Ok(None),
+        },
+    };
+
+    if length > max_packet_size {
+        return Err(RPCError::Custom(format!(
+            "RPC payload of {} bytes exceeds the maximum of {} bytes",
+            length, max_packet_size
+        )));
+    }
+
+    let compressed = match compressed_len(src, length)? {
+        Some(compressed) => compressed,
+        // the payload has not been fully received yet
+        None => return Ok(None),
+    };
+
+    let mut reader = FrameDecoder::new(&src[..compressed]);
+    let mut decoded_buffer = vec![0; length];
+    reader
+        .read_exact(&mut decoded_buffer)
+        .map_err(RPCError::from)?;
+    // remove the compressed bytes that have been read
+    src.split_to(compressed);
+    *len = None;
+    Ok(Some(decoded_buffer))
+}
+
+/* Inbound Codec */
+
+// The payloads of configured methods are passed to the application as they are, so this codec
+// also serves the raw `snappy` encoding. Only the built-in methods decode their payloads as SSZ,
+// and they always use the default encodings.
+
+pub struct SSZSnappyInboundCodec {
+    inner: Uvi<usize>,
+    protocol: ProtocolId,
+    /// The uncompressed length of the request being decoded.
+    len: Option<usize>,
+    max_packet_size: usize,
+}
+
+impl SSZSnappyInboundCodec {
+    pub fn new(protocol: ProtocolId, max_packet_size: usize) -> Self {
+        // this codec only applies to snappy compressed payloads.
+        debug_assert!(["ssz_snappy", "snappy"].contains(&protocol.encoding.as_str()));
+
+        SSZSnappyInboundCodec {
+            inner: Uvi::default(),
+            protocol,
+            len: None,
+            max_packet_size,
+        }
+    }
+}
+
+// Encoder for inbound
+impl Encoder for SSZSnappyInboundCodec {
+    type Item = RPCErrorResponse;
+    type Error = RPCError;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let bytes = match item {
+            RPCErrorResponse::Success(resp) => resp.into_bytes(),
+            RPCErrorResponse::InvalidRequest(err) => err.error_message,
+            RPCErrorResponse::ServerError(err) => err.error_message,
+            RPCErrorResponse::Unknown(err) => err.error_message,
+            // the end of a stream is signalled by closing it
+            RPCErrorResponse::StreamTermination(_) => return Ok(()),
+        };
+
+        encode_snappy(&mut self.inner, &bytes, dst)
+    }
+}
+
+// Decoder for inbound
+impl Decoder for SSZSnappyInboundCodec {
+    type Item = RPCRequest;
+    type Error = RPCError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let packet = decode_snappy(&mut self.inner, &mut self.len, self.max_packet_size, src)?;
+        packet
+            .map(|packet| RPCRequest::from_bytes(&self.protocol, packet))
+            .transpose()
+    }
+}
+
+/* Outbound Codec */
+
+pub struct SSZSnappyOutboundCodec {
+    inner: Uvi<usize>,
+    protocol: ProtocolId,
+    /// The uncompressed length of the response being decoded.
+    len: Option<usize>,
+    max_packet_size: usize,
+}
+
+impl SSZSnappyOutboundCodec {
+    pub fn new(protocol: ProtocolId, max_packet_size: usize) -> Self {
+        // this codec only applies to snappy compressed payloads.
+        debug_assert!(["ssz_snappy", "snappy"].contains(&protocol.encoding.as_str()));
+
+        SSZSnappyOutboundCodec {
+            inner: Uvi::default(),
+            protocol,
+            len: None,
+            max_packet_size,
+        }
+    }
+}
+
+// Encoder for outbound
+impl Encoder for SSZSnappyOutboundCodec {
+    type Item = RPCRequest;
+    type Error = RPCError;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        encode_snappy(&mut self.inner, &item.into_bytes(), dst)
+    }
+}
+
+// Decoder for outbound
+impl Decoder for SSZSnappyOutboundCodec {
+    type Item = RPCResponse;
+    type Error = RPCError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let packet = decode_snappy(&mut self.inner, &mut self.len, self.max_packet_size, src)?;
+        packet
+            .map(|packet| RPCResponse::from_bytes(&self.protocol, packet))
+            .transpose()
+    }
+}
+
+impl OutboundCodec for SSZSnappyOutboundCodec {
+    type ErrorType = ErrorMessage;
+
+    fn decode_error(&mut self, src: &mut BytesMut) -> Result<Option<Self::ErrorType>, RPCError> {
+        let packet = decode_snappy(&mut self.inner, &mut self.len, self.max_packet_size, src)?;
+        Ok(packet.map(|error_message| ErrorMessage { error_message }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_PACKET_SIZE: usize = 1024;
+
+    #[test]
+    fn test_request_round_trip() {
+        let protocol = ProtocolId::new("hello", "1", "ssz_snappy");
+        let mut outbound = SSZSnappyOutboundCodec::new(protocol.clone(), MAX_PACKET_SIZE);
+        let mut inbound = SSZSnappyInboundCodec::new(protocol, MAX_PACKET_SIZE);
+        let payload = vec![7; 100];
+        let request = RPCRequest::Message("hello".into(), "1".into(), payload.clone());
+        let mut src = BytesMut::new();
+        outbound.encode(request, &mut src).unwrap();
+        match inbound.decode(&mut src).unwrap() {
+            Some(RPCRequest::Message(method, version, bytes)) => {
+                assert_eq!((method.as_str(), version.as_str()), ("hello", "1"));
+                assert_eq!(bytes, payload);
+            }
+            request => panic!("Unexpected request: {:?}", request),
+        }
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn test_truncated_request() {
+        let protocol = ProtocolId::new("hello", "1", "ssz_snappy");
+        let mut outbound = SSZSnappyOutboundCodec::new(protocol.clone(), MAX_PACKET_SIZE);
+        let mut inbound = SSZSnappyInboundCodec::new(protocol, MAX_PACKET_SIZE);
+        let request = RPCRequest::Message("hello".into(), "1".into(), vec![7; 100]);
+        let mut encoded = BytesMut::new();
+        outbound.encode(request, &mut encoded).unwrap();
+
+        // a partially received request is decoded once the rest arrives
+        let mut src = BytesMut::from(&encoded[..encoded.len() - 1]);
+        assert!(inbound.decode(&mut src).unwrap().is_none());
+        src.extend_from_slice(&encoded[encoded.len() - 1..]);
+        assert!(inbound.decode(&mut src).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_oversize_request() {
+        let protocol = ProtocolId::new("hello", "1", "ssz_snappy");
+        let mut outbound = SSZSnappyOutboundCodec::new(protocol.clone(), MAX_PACKET_SIZE);
+        let mut inbound = SSZSnappyInboundCodec::new(protocol, MAX_PACKET_SIZE);
+        let request = RPCRequest::Message("hello".into(), "1".into(), vec![7; MAX_PACKET_SIZE + 1]);
+        let mut src = BytesMut::new();
+        outbound.encode(request, &mut src).unwrap();
+        assert!(inbound.decode(&mut src).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_request() {
+        // the compressed payload does not start with the snappy stream identifier
+        let mut src = BytesMut::from(&[3, 0x02, 0, 0, 0][..]);
+        let protocol = ProtocolId::new("hello", "1", "ssz_snappy");
+        let mut inbound = SSZSnappyInboundCodec::new(protocol, MAX_PACKET_SIZE);
+        assert!(inbound.decode(&mut src).is_err());
+    }
+}
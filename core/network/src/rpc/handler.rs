@@ -1,21 +1,20 @@
-use super::methods::{RPCErrorResponse, RequestId};
-use super::protocol::{RPCError, RPCProtocol, RPCRequest};
+use super::methods::{ErrorMessage, RPCErrorResponse, RequestId};
+use super::protocol::{OutboundRequest, RPCError, RPCProtocol};
+use super::rate_limiter::RateLimiter;
 use super::RPCEvent;
 use crate::rpc::protocol::{InboundFramed, OutboundFramed};
 use core::marker::PhantomData;
 use fnv::FnvHashMap;
 use futures::prelude::*;
-use libp2p::core::upgrade::{InboundUpgrade, OutboundUpgrade};
+use libp2p::core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeError};
 use libp2p::swarm::protocols_handler::{
     KeepAlive, ProtocolsHandler, ProtocolsHandlerEvent, ProtocolsHandlerUpgrErr, SubstreamProtocol,
 };
 use smallvec::SmallVec;
+use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 use tokio_io::{AsyncRead, AsyncWrite};
 
-/// The time (in seconds) before a substream that is awaiting a response times out.
-pub const RESPONSE_TIMEOUT: u64 = 10;
-
 /// Implementation of `ProtocolsHandler` for the RPC protocol.
 pub struct RPCHandler<TSubstream>
 where
@@ -24,9 +23,6 @@ where
     /// The upgrade for inbound substreams.
     listen_protocol: SubstreamProtocol<RPCProtocol>,
 
-    /// If `Some`, something bad happened and we should shut down the handler with an error.
-    pending_error: Option<ProtocolsHandlerUpgrErr<RPCError>>,
-
     /// Queue of events to produce in `poll()`.
     events_out: SmallVec<[RPCEvent; 4]>,
 
@@ -36,7 +32,7 @@ where
     /// Current number of concurrent outbound substreams being opened.
     dial_negotiated: u32,
 
-    /// Map of current substreams awaiting a response to an RPC request.
+    /// Map of current inbound substreams awaiting responses to an RPC request.
     waiting_substreams: FnvHashMap<RequestId, WaitingResponse<TSubstream>>,
 
     /// List of outbound substreams that need to be driven to completion.
@@ -48,6 +44,13 @@ where
     /// Maximum number of concurrent outbound substreams being opened. Value is never modified.
     max_dial_negotiated: u32,
 
+    /// Maximum number of inbound substreams awaiting responses. Further requests are answered
+    /// with an error.
+    max_inbound_substreams: usize,
+
+    /// Limits the rate of inbound requests on each method.
+    rate_limiter: RateLimiter,
+
     /// Value to return from `connection_keep_alive`.
     keep_alive: KeepAlive,
 
@@ -58,23 +61,50 @@ where
     _phantom: PhantomData<TSubstream>,
 }
 
-/// An outbound substream is waiting a response from the user.
+/// An inbound substream is waiting for responses from the user, or in the process of sending them.
 struct WaitingResponse<TSubstream> {
     /// The framed negotiated substream.
     substream: InboundFramed<TSubstream>,
+    /// Responses from the user that have not been written to the substream yet.
+    pending_responses: VecDeque<RPCErrorResponse>,
+    /// Whether the requested method responds with a stream of chunks.
+    streaming: bool,
+    /// Set once the last response has been received from the user. The substream is closed after
+    /// the pending responses are sent.
+    closing: bool,
+    /// The time allowed for each response of the method.
+    response_timeout: Duration,
     /// The time when the substream is closed.
     timeout: Instant,
 }
 
-/// State of an outbound substream. Either waiting for a response, or in the process of sending.
+impl<TSubstream> WaitingResponse<TSubstream>
+where
+    TSubstream: AsyncRead + AsyncWrite,
+{
+    /// Writes and flushes the pending responses. Resolves once the last response has been sent
+    /// and the substream is closed.
+    fn poll_send(&mut self) -> Poll<(), RPCError> {
+        while let Some(response) = self.pending_responses.pop_front() {
+            if let AsyncSink::NotReady(response) = self.substream.start_send(response)? {
+                self.pending_responses.push_front(response);
+                break;
+            }
+        }
+
+        if self.closing && self.pending_responses.is_empty() {
+            self.substream.close()
+        } else {
+            self.substream.poll_complete().map(|_| Async::NotReady)
+        }
+    }
+}
+
+/// State of an outbound substream, awaiting a response.
 pub enum SubstreamState<TSubstream>
 where
     TSubstream: AsyncRead + AsyncWrite,
 {
-    /// A response has been sent, pending writing and flush.
-    ResponsePendingSend {
-        substream: futures::sink::Send<InboundFramed<TSubstream>>,
-    },
     /// A request has been sent, and we are awaiting a response. This future is driven in the
     /// handler because GOODBYE requests can be handled and responses dropped instantly.
     RequestPendingResponse {
@@ -83,6 +113,11 @@ where
         /// Keeps track of the request id and the request to permit forming advanced responses which require
         /// data from the request.
         rpc_event: RPCEvent,
+        /// Whether the requested method responds with a stream of chunks, which ends when the
+        /// responder closes the substream.
+        streaming: bool,
+        /// The time allowed for each response of the method.
+        response_timeout: Duration,
         /// The time  when the substream is closed.
         timeout: Instant,
     },
@@ -94,18 +129,21 @@ where
 {
     pub fn new(
         listen_protocol: SubstreamProtocol<RPCProtocol>,
+        max_dial_negotiated: u32,
+        max_inbound_substreams: usize,
         inactive_timeout: Duration,
     ) -> Self {
         RPCHandler {
             listen_protocol,
-            pending_error: None,
             events_out: SmallVec::new(),
             dial_queue: SmallVec::new(),
             dial_negotiated: 0,
             waiting_substreams: FnvHashMap::default(),
             substreams: Vec::new(),
             current_substream_id: 1,
-            max_dial_negotiated: 8,
+            max_dial_negotiated,
+            max_inbound_substreams,
+            rate_limiter: RateLimiter::default(),
             keep_alive: KeepAlive::Yes,
             inactive_timeout,
             _phantom: PhantomData,
@@ -142,15 +180,6 @@ where
     }
 }
 
-impl<TSubstream> Default for RPCHandler<TSubstream>
-where
-    TSubstream: AsyncRead + AsyncWrite,
-{
-    fn default() -> Self {
-        RPCHandler::new(SubstreamProtocol::new(RPCProtocol), Duration::from_secs(30))
-    }
-}
-
 impl<TSubstream> ProtocolsHandler for RPCHandler<TSubstream>
 where
     TSubstream: AsyncRead + AsyncWrite,
@@ -160,7 +189,7 @@ where
     type Error = ProtocolsHandlerUpgrErr<RPCError>;
     type Substream = TSubstream;
     type InboundProtocol = RPCProtocol;
-    type OutboundProtocol = RPCRequest;
+    type OutboundProtocol = OutboundRequest;
     type OutboundOpenInfo = RPCEvent; // Keep track of the id and the request
 
     #[inline]
@@ -173,25 +202,73 @@ where
         &mut self,
         out: <RPCProtocol as InboundUpgrade<TSubstream>>::Output,
     ) {
-        let (req, substream) = out;
+        let (req, substream, negotiated) = out;
+        let protocol = self.listen_protocol.upgrade();
+        let limits = protocol.limits(&negotiated);
+
+        // requests without a response, such as Goodbye or those of one-way methods, do not keep
+        // their substream. Requests beyond the rate limits are dropped.
+        if !protocol.expect_response(&req, &negotiated) {
+            if self
+                .rate_limiter
+                .allows(&negotiated, &limits, Instant::now())
+            {
+                self.events_out
+                    .push(RPCEvent::Request(self.current_substream_id, req));
+            } else {
+                self.events_out.push(RPCEvent::Error(
+                    self.current_substream_id,
+                    RPCError::RateLimited(req.method().into()),
+                ));
+            }
+            self.current_substream_id += 1;
+            return;
+        }
+
+        let response_timeout = Duration::from_millis(limits.response_timeout);
 
         // New inbound request. Store the stream and tag the output.
-        let awaiting_stream = WaitingResponse {
+        let mut awaiting_stream = WaitingResponse {
             substream,
-            timeout: Instant::now() + Duration::from_secs(RESPONSE_TIMEOUT),
+            pending_responses: VecDeque::new(),
+            streaming: protocol.is_streaming(&negotiated),
+            closing: false,
+            response_timeout,
+            timeout: Instant::now() + response_timeout,
         };
+
+        // requests beyond the limits are answered with an error, without involving the user
+        let over_limit = self.waiting_substreams.len() >= self.max_inbound_substreams
+            || !self
+                .rate_limiter
+                .allows(&negotiated, &limits, Instant::now());
+        if over_limit {
+            awaiting_stream
+                .pending_responses
+                .push_back(RPCErrorResponse::ServerError(ErrorMessage {
+                    error_message: b"Rate limited".to_vec(),
+                }));
+            awaiting_stream.closing = true;
+        }
         self.waiting_substreams
             .insert(self.current_substream_id, awaiting_stream);
 
-        self.events_out
-            .push(RPCEvent::Request(self.current_substream_id, req));
+        if over_limit {
+            self.events_out.push(RPCEvent::Error(
+                self.current_substream_id,
+                RPCError::RateLimited(req.method().into()),
+            ));
+        } else {
+            self.events_out
+                .push(RPCEvent::Request(self.current_substream_id, req));
+        }
         self.current_substream_id += 1;
     }
 
     #[inline]
     fn inject_fully_negotiated_outbound(
         &mut self,
-        out: <RPCRequest as OutboundUpgrade<TSubstream>>::Output,
+        out: <OutboundRequest as OutboundUpgrade<TSubstream>>::Output,
         rpc_event: Self::OutboundOpenInfo,
     ) {
         self.dial_negotiated -= 1;
@@ -204,13 +281,20 @@ where
         } else {
             self.keep_alive = KeepAlive::Yes;
         }
-        // add the stream to substreams if we expect a response, otherwise drop the stream.
+        // add the stream to substreams if we expect a response, otherwise drop the stream. The
+        // request has been flushed by the upgrade, so dropping the stream closes it.
+        let (substream, negotiated) = out;
         if let RPCEvent::Request(id, req) = rpc_event {
-            if req.expect_response() {
+            let protocol = self.listen_protocol.upgrade();
+            if protocol.expect_response(&req, &negotiated) {
+                let response_timeout =
+                    Duration::from_millis(protocol.limits(&negotiated).response_timeout);
                 let awaiting_stream = SubstreamState::RequestPendingResponse {
-                    substream: out,
+                    substream,
+                    streaming: protocol.is_streaming(&negotiated),
                     rpc_event: RPCEvent::Request(id, req),
-                    timeout: Instant::now() + Duration::from_secs(RESPONSE_TIMEOUT),
+                    response_timeout,
+                    timeout: Instant::now() + response_timeout,
                 };
 
                 self.substreams.push(awaiting_stream);
@@ -226,11 +310,19 @@ where
             RPCEvent::Request(_, _) => self.send_request(rpc_event),
             RPCEvent::Response(rpc_id, res) => {
                 // check if the stream matching the response still exists
-                if let Some(waiting_stream) = self.waiting_substreams.remove(&rpc_id) {
-                    // only send one response per stream. This must be in the waiting state.
-                    self.substreams.push(SubstreamState::ResponsePendingSend {
-                        substream: waiting_stream.substream.send(res),
-                    });
+                if let Some(waiting_stream) = self.waiting_substreams.get_mut(&rpc_id) {
+                    // responses after the last one of the stream are dropped
+                    if waiting_stream.closing {
+                        return;
+                    }
+                    // only streaming methods send more than one response per stream
+                    waiting_stream.closing = !waiting_stream.streaming || res.close_after();
+                    waiting_stream.timeout = Instant::now() + waiting_stream.response_timeout;
+                    if let RPCErrorResponse::StreamTermination(_) = res {
+                        // the stream is terminated by closing it
+                    } else {
+                        waiting_stream.pending_responses.push_back(res);
+                    }
                 }
             }
             RPCEvent::Error(_, _) => std::println!("handler.rs ERROR"),
@@ -240,14 +332,24 @@ where
     #[inline]
     fn inject_dial_upgrade_error(
         &mut self,
-        _: Self::OutboundOpenInfo,
+        rpc_event: Self::OutboundOpenInfo,
         error: ProtocolsHandlerUpgrErr<
             <Self::OutboundProtocol as OutboundUpgrade<Self::Substream>>::Error,
         >,
     ) {
-        if self.pending_error.is_none() {
-            self.pending_error = Some(error);
-        }
+        self.dial_negotiated -= 1;
+
+        // Returning an error from `poll` would drop any peer that doesn't support one of the RPC
+        // protocols. Instead the failure is reported for the request.
+        let error = match error {
+            ProtocolsHandlerUpgrErr::Timeout => RPCError::StreamTimeout,
+            ProtocolsHandlerUpgrErr::Timer => RPCError::Custom("Stream timer failed".into()),
+            ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(_)) => {
+                RPCError::UnsupportedProtocol
+            }
+            ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(error)) => error,
+        };
+        self.events_out.push(RPCEvent::Error(rpc_event.id(), error));
     }
 
     #[inline]
@@ -261,14 +363,6 @@ where
         ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent>,
         Self::Error,
     > {
-        if let Some(err) = self.pending_error.take() {
-            // Returning an error here will result in dropping any peer that doesn't support any of
-            // the RPC protocols. For our immediate purposes we permit this and simply log that an
-            // upgrade was not supported.
-            // TODO: Add a logger to the handler for trace output.
-            dbg!(&err);
-        }
-
         // return any events that need to be reported
         if !self.events_out.is_empty() {
             return Ok(Async::Ready(ProtocolsHandlerEvent::Custom(
@@ -278,47 +372,65 @@ where
             self.events_out.shrink_to_fit();
         }
 
-        // remove any streams that have expired
+        // send pending responses, and remove any streams that have been closed or have expired
+        let mut send_error = None;
         self.waiting_substreams
-            .retain(|_k, waiting_stream| Instant::now() <= waiting_stream.timeout);
+            .retain(|_k, waiting_stream| match waiting_stream.poll_send() {
+                Ok(Async::Ready(())) => false, // sent, flushed and closed
+                Ok(Async::NotReady) => Instant::now() <= waiting_stream.timeout,
+                Err(e) => {
+                    send_error = Some(e);
+                    false
+                }
+            });
+        if let Some(e) = send_error {
+            return Ok(Async::Ready(ProtocolsHandlerEvent::Custom(
+                RPCEvent::Error(0, e),
+            )));
+        }
 
         // drive streams that need to be processed
         for n in (0..self.substreams.len()).rev() {
             let stream = self.substreams.swap_remove(n);
             match stream {
-                SubstreamState::ResponsePendingSend { mut substream } => {
-                    match substream.poll() {
-                        Ok(Async::Ready(_substream)) => {} // sent and flushed
-                        Ok(Async::NotReady) => {
-                            self.substreams
-                                .push(SubstreamState::ResponsePendingSend { substream });
-                        }
-                        Err(e) => {
-                            return Ok(Async::Ready(ProtocolsHandlerEvent::Custom(
-                                RPCEvent::Error(0, e),
-                            )))
-                        }
-                    }
-                }
                 SubstreamState::RequestPendingResponse {
                     mut substream,
                     rpc_event,
+                    streaming,
+                    response_timeout,
                     timeout,
                 } => match substream.poll() {
-                    Ok(Async::Ready(response)) => {
-                        if let Some(response) = response {
-                            return Ok(Async::Ready(ProtocolsHandlerEvent::Custom(
-                                RPCEvent::Response(rpc_event.id(), response),
-                            )));
-                        } else {
-                            // stream closed early
-                            return Ok(Async::Ready(ProtocolsHandlerEvent::Custom(
-                                RPCEvent::Error(
-                                    rpc_event.id(),
-                                    RPCError::Custom("Stream Closed Early".into()),
-                                ),
-                            )));
+                    Ok(Async::Ready(Some(response))) => {
+                        let id = rpc_event.id();
+                        if streaming && !response.close_after() {
+                            // further chunks may follow, each with a fresh timeout
+                            self.substreams
+                                .push(SubstreamState::RequestPendingResponse {
+                                    substream,
+                                    rpc_event,
+                                    streaming,
+                                    response_timeout,
+                                    timeout: Instant::now() + response_timeout,
+                                });
                         }
+                        return Ok(Async::Ready(ProtocolsHandlerEvent::Custom(
+                            RPCEvent::Response(id, response),
+                        )));
+                    }
+                    Ok(Async::Ready(None)) => {
+                        let event = match rpc_event {
+                            // the responder closes the stream after the last chunk
+                            RPCEvent::Request(id, req) if streaming => RPCEvent::Response(
+                                id,
+                                RPCErrorResponse::StreamTermination(req.method().into()),
+                            ),
+                            // stream closed early
+                            rpc_event => RPCEvent::Error(
+                                rpc_event.id(),
+                                RPCError::Custom("Stream Closed Early".into()),
+                            ),
+                        };
+                        return Ok(Async::Ready(ProtocolsHandlerEvent::Custom(event)));
                     }
                     Ok(Async::NotReady) => {
                         if Instant::now() < timeout {
@@ -326,8 +438,14 @@ where
                                 .push(SubstreamState::RequestPendingResponse {
                                     substream,
                                     rpc_event,
+                                    streaming,
+                                    response_timeout,
                                     timeout,
                                 });
+                        } else {
+                            return Ok(Async::Ready(ProtocolsHandlerEvent::Custom(
+                                RPCEvent::Error(rpc_event.id(), RPCError::StreamTimeout),
+                            )));
                         }
                     }
                     Err(e) => {
@@ -342,12 +460,22 @@ where
         // establish outbound substreams
         if !self.dial_queue.is_empty() {
             if self.dial_negotiated < self.max_dial_negotiated {
-                self.dial_negotiated += 1;
                 let rpc_event = self.dial_queue.remove(0);
                 if let RPCEvent::Request(id, req) = rpc_event {
+                    let protocol = self.listen_protocol.upgrade();
+                    let versions = protocol.versions(req.method());
+                    if versions.is_empty() {
+                        return Ok(Async::Ready(ProtocolsHandlerEvent::Custom(
+                            RPCEvent::Error(id, RPCError::InvalidProtocol("Unknown RPC method")),
+                        )));
+                    }
+                    self.dial_negotiated += 1;
                     return Ok(Async::Ready(
                         ProtocolsHandlerEvent::OutboundSubstreamRequest {
-                            protocol: SubstreamProtocol::new(req.clone()),
+                            protocol: SubstreamProtocol::new(OutboundRequest {
+                                versions,
+                                request: req.clone(),
+                            }),
                             info: RPCEvent::Request(id, req),
                         },
                     ));
@@ -1,30 +1,348 @@
+use super::protocol::ProtocolId;
+use crate::rpc::RPCError;
+use crate::EnrBitfield;
+use serde_derive::{Deserialize, Serialize};
+use ssz::{Decode, Encode};
+use ssz_derive::{Decode, Encode};
+use std::str::FromStr;
+
 pub type RequestId = usize;
 
+/// The name of the built-in method that carries Goodbye requests. It is supported by every node.
+pub const GOODBYE_METHOD: &str = "goodbye";
+
+/// The name of the built-in method that carries the status handshake. It is supported when the
+/// handshake is enabled.
+pub const STATUS_METHOD: &str = "status";
+
+/// The name of the built-in method that carries pings. It is supported by every node.
+pub const PING_METHOD: &str = "ping";
+
+/// The name of the built-in method that returns the metadata of a node. It is supported by every
+/// node.
+pub const METADATA_METHOD: &str = "metadata";
+
+/// The version assigned to an RPC method when none is specified.
+const DEFAULT_METHOD_VERSION: &str = "1";
+/// The supported encodings. Raw `snappy` compresses payloads that are not SSZ encoded, and is only
+/// used by the method versions that ask for it with the `encoding` option.
+pub(crate) const ENCODINGS: [&str; 3] = ["ssz_snappy", "ssz", "snappy"];
+/// The encodings of a method version that does not restrict them, in order of preference. Snappy
+/// compressed payloads are preferred, with a fallback to uncompressed `ssz` for peers that do not
+/// support them.
+const DEFAULT_ENCODINGS: [&str; 2] = ["ssz_snappy", "ssz"];
+/// The default maximum size of a request or response payload, in bytes.
+const DEFAULT_MAX_SIZE: usize = 4_194_304; // 4M
+/// The default time (in milliseconds) allowed for the first byte of a request to arrive (Time To
+/// First Byte).
+const DEFAULT_TTFB_TIMEOUT: u64 = 5_000;
+/// The default time (in milliseconds) allowed for the whole request to arrive once a protocol has
+/// been established.
+const DEFAULT_REQUEST_TIMEOUT: u64 = 15_000;
+/// The default time (in milliseconds) to wait for each response before the substream times out.
+const DEFAULT_RESPONSE_TIMEOUT: u64 = 10_000;
+/// The default number of inbound requests a peer may send on a method per quota window.
+const DEFAULT_QUOTA: u64 = 128;
+/// The default quota window, in milliseconds.
+const DEFAULT_QUOTA_WINDOW: u64 = 10_000;
+
+/// The size limit and timeouts of the requests and responses of an RPC method.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RPCLimits {
+    /// The maximum size of a request or response payload, in bytes.
+    pub max_size: usize,
+    /// The time (in milliseconds) allowed for the first byte of an inbound request to arrive.
+    pub ttfb_timeout: u64,
+    /// The time (in milliseconds) allowed for an inbound request to arrive in full.
+    pub request_timeout: u64,
+    /// The time (in milliseconds) to wait for a response, or for each chunk of a streaming
+    /// response. Inbound requests must also be answered within this time.
+    pub response_timeout: u64,
+    /// The number of inbound requests a peer may send on the method per `quota_window`. Requests
+    /// beyond the quota are answered with an error. A quota of 0 does not limit the method.
+    pub quota: u64,
+    /// The time (in milliseconds) over which the quota of a peer is replenished.
+    pub quota_window: u64,
+}
+
+impl Default for RPCLimits {
+    fn default() -> Self {
+        RPCLimits {
+            max_size: DEFAULT_MAX_SIZE,
+            ttfb_timeout: DEFAULT_TTFB_TIMEOUT,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
+            quota: DEFAULT_QUOTA,
+            quota_window: DEFAULT_QUOTA_WINDOW,
+        }
+    }
+}
+
+/// A request/response method supported by the RPC. Each method is advertised as its own protocol
+/// id.
+///
+/// A method may be listed once per version, each with its own encodings and limits. The versions
+/// are listed in order of preference, so that the newest version both peers support is negotiated.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCMethod {
+    /// The name of the method, as it appears in the protocol id.
+    pub name: String,
+    /// The version of the method.
+    pub version: String,
+    /// The encodings supported for this version, in order of preference.
+    #[serde(default = "default_encodings")]
+    pub encodings: Vec<String>,
+    /// Whether the method responds with a stream of zero or more chunks, which ends when the
+    /// responder closes the stream. Otherwise a single response is expected.
+    #[serde(default)]
+    pub streaming: bool,
+    /// Whether the method is one-way. Requests of a one-way method are not answered, and their
+    /// substream is closed once the request has been sent.
+    #[serde(default)]
+    pub one_way: bool,
+    /// The size limit and timeouts of the method.
+    #[serde(default)]
+    pub limits: RPCLimits,
+}
+
+impl RPCMethod {
+    pub fn new(name: &str, version: &str) -> Self {
+        RPCMethod {
+            name: name.into(),
+            version: version.into(),
+            encodings: default_encodings(),
+            streaming: false,
+            one_way: false,
+            limits: RPCLimits::default(),
+        }
+    }
+}
+
+/// The default encodings, in order of preference.
+fn default_encodings() -> Vec<String> {
+    DEFAULT_ENCODINGS
+        .iter()
+        .map(|encoding| (*encoding).into())
+        .collect()
+}
+
+/// Parses a method of the form `name` or `name/version`, optionally followed by `;`-separated
+/// options: `streaming` for methods that respond with a stream of chunks, `one_way` for methods
+/// whose requests are not answered, `encoding=ENCODING` once
+/// for each encoding to restrict the version to, in order of preference, and `max_size`,
+/// `ttfb_timeout`, `request_timeout`, `response_timeout`, `quota` or `quota_window` given as
+/// `key=value` to override a limit, e.g. `blocks/1;streaming;max_size=10485760;quota=16`. This is
+/// the grammar of `--rpc-methods`, described in full in the README. Names reserved for the
+/// built-in methods are rejected by `validate_methods`, as they apply to a list of methods.
+impl FromStr for RPCMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut options = s.split(';');
+        let mut parts = options.next().unwrap_or_default().splitn(2, '/');
+        let name = parts.next().unwrap_or_default();
+        let version = parts.next().unwrap_or(DEFAULT_METHOD_VERSION);
+        if name.is_empty() || version.is_empty() || version.contains('/') {
+            return Err(format!("Invalid RPC method: {}", s));
+        }
+        let mut method = RPCMethod::new(name, version);
+        let mut encodings = Vec::new();
+        for option in options {
+            let mut parts = option.splitn(2, '=');
+            let key = parts.next().unwrap_or_default();
+            let value = parts.next();
+            let limit = || {
+                value
+                    .and_then(|value| value.parse::<u64>().ok())
+                    .ok_or_else(|| format!("Invalid RPC method option: {}", option))
+            };
+            match (key, value) {
+                ("streaming", None) => method.streaming = true,
+                ("one_way", None) => method.one_way = true,
+                ("encoding", Some(encoding)) if ENCODINGS.contains(&encoding) => {
+                    encodings.push(encoding.into())
+                }
+                ("max_size", Some(_)) => method.limits.max_size = limit()? as usize,
+                ("ttfb_timeout", Some(_)) => method.limits.ttfb_timeout = limit()?,
+                ("request_timeout", Some(_)) => method.limits.request_timeout = limit()?,
+                ("response_timeout", Some(_)) => method.limits.response_timeout = limit()?,
+                ("quota", Some(_)) => method.limits.quota = limit()?,
+                ("quota_window", Some(_)) => method.limits.quota_window = limit()?,
+                _ => return Err(format!("Unknown RPC method option: {}", option)),
+            }
+        }
+        if method.streaming && method.one_way {
+            return Err(format!("A one-way RPC method cannot be streaming: {}", s));
+        }
+        if !encodings.is_empty() {
+            method.encodings = encodings;
+        }
+        Ok(method)
+    }
+}
+
+/// Checks that no method is named after a built-in method, and that the versions of each method
+/// agree on whether it is one-way. Whether a request is answered must be known before a version
+/// is negotiated with the peer.
+pub fn validate_methods(methods: &[RPCMethod]) -> Result<(), String> {
+    for method in methods {
+        if [GOODBYE_METHOD, PING_METHOD, METADATA_METHOD].contains(&method.name.as_str()) {
+            return Err(format!(
+                "RPC method {} is built in, and cannot be configured",
+                method.name
+            ));
+        }
+        if methods
+            .iter()
+            .any(|m| m.name == method.name && m.one_way != method.one_way)
+        {
+            return Err(format!(
+                "All versions of RPC method {} must be one-way, or none of them",
+                method.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a comma-delimited list of methods, as given with `--rpc-methods`, and checks it with
+/// `validate_methods`.
+pub fn parse_methods(s: &str) -> Result<Vec<RPCMethod>, String> {
+    let methods = s
+        .split(',')
+        .map(str::parse)
+        .collect::<Result<Vec<RPCMethod>, _>>()?;
+    validate_methods(&methods)?;
+    Ok(methods)
+}
+
+/// The reason given in a Goodbye request, sent to a peer before disconnecting from it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GoodbyeReason {
+    /// The node is shutting down.
+    ClientShutdown,
+    /// The peer is on a different network or fork.
+    IrrelevantNetwork,
+    /// The peer misbehaved, or an error occurred.
+    Fault,
+    /// The node is connected to too many peers.
+    TooManyPeers,
+    /// A reason code that is not known to this node.
+    Unknown(u64),
+}
+
+impl From<u64> for GoodbyeReason {
+    fn from(code: u64) -> Self {
+        match code {
+            1 => GoodbyeReason::ClientShutdown,
+            2 => GoodbyeReason::IrrelevantNetwork,
+            3 => GoodbyeReason::Fault,
+            129 => GoodbyeReason::TooManyPeers,
+            _ => GoodbyeReason::Unknown(code),
+        }
+    }
+}
+
+impl From<GoodbyeReason> for u64 {
+    fn from(reason: GoodbyeReason) -> u64 {
+        match reason {
+            GoodbyeReason::ClientShutdown => 1,
+            GoodbyeReason::IrrelevantNetwork => 2,
+            GoodbyeReason::Fault => 3,
+            GoodbyeReason::TooManyPeers => 129,
+            GoodbyeReason::Unknown(code) => code,
+        }
+    }
+}
+
+/// The body of a ping and its response: the sequence number of the sender's metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Encode, Decode)]
+pub struct Ping {
+    pub data: u64,
+}
+
+/// The metadata that a node advertises to its peers.
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub struct MetaData {
+    /// Increases whenever any other field of the metadata changes.
+    pub seq_number: u64,
+    /// The long-lived subnets that the node is subscribed to.
+    pub attnets: EnrBitfield,
+}
+
 /* RPC Handling and Grouping */
 // Collection of enums and structs used by the Codecs to encode/decode RPC messages
 
 #[derive(Debug, Clone)]
 pub enum RPCResponse {
-    /// An RPC message.
-    Message(Vec<u8>),
+    /// An RPC message, tagged with the name and version of the method it responds to. The version
+    /// is the one negotiated with the peer; it is left empty when responding, and is ignored.
+    Message(String, String, Vec<u8>),
+    /// The response to a ping, carrying our metadata sequence number.
+    Pong(Ping),
+    /// The response to a metadata request.
+    MetaData(MetaData),
+}
+
+impl RPCResponse {
+    /// Decodes a response received on `protocol`.
+    pub fn from_bytes(protocol: &ProtocolId, bytes: Vec<u8>) -> Result<Self, RPCError> {
+        match protocol.message_name.as_str() {
+            PING_METHOD => Ping::from_ssz_bytes(&bytes)
+                .map(RPCResponse::Pong)
+                .map_err(|e| RPCError::Custom(format!("Invalid Pong response: {:?}", e))),
+            METADATA_METHOD => MetaData::from_ssz_bytes(&bytes)
+                .map(RPCResponse::MetaData)
+                .map_err(|e| RPCError::Custom(format!("Invalid MetaData response: {:?}", e))),
+            method => Ok(RPCResponse::Message(
+                method.into(),
+                protocol.version.clone(),
+                bytes,
+            )),
+        }
+    }
+
+    /// Encodes the response. Pongs and metadata are SSZ encoded.
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self {
+            RPCResponse::Message(_, _, bytes) => bytes,
+            RPCResponse::Pong(ping) => ping.as_ssz_bytes(),
+            RPCResponse::MetaData(meta_data) => meta_data.as_ssz_bytes(),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum RPCErrorResponse {
     Success(RPCResponse),
     InvalidRequest(ErrorMessage),
     ServerError(ErrorMessage),
     Unknown(ErrorMessage),
+    /// Marks the end of a stream of responses, tagged with the name of the method. This is not
+    /// sent on the wire; the responder closes the stream instead.
+    StreamTermination(String),
 }
 
 impl RPCErrorResponse {
-    /// Used to encode the response.
-    pub fn as_u8(&self) -> u8 {
+    /// Used to encode the response. Returns `None` for a stream termination, which has no
+    /// response code.
+    pub fn as_u8(&self) -> Option<u8> {
+        match self {
+            RPCErrorResponse::Success(_) => Some(0),
+            RPCErrorResponse::InvalidRequest(_) => Some(2),
+            RPCErrorResponse::ServerError(_) => Some(3),
+            RPCErrorResponse::Unknown(_) => Some(255),
+            RPCErrorResponse::StreamTermination(_) => None,
+        }
+    }
+
+    /// Returns `true` if no further responses may follow this one on the stream of a `streaming`
+    /// method. Error responses also end a stream.
+    pub fn close_after(&self) -> bool {
         match self {
-            RPCErrorResponse::Success(_) => 0,
-            RPCErrorResponse::InvalidRequest(_) => 2,
-            RPCErrorResponse::ServerError(_) => 3,
-            RPCErrorResponse::Unknown(_) => 255,
+            RPCErrorResponse::Success(_) => false,
+            _ => true,
         }
     }
 
@@ -46,7 +364,7 @@ impl RPCErrorResponse {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ErrorMessage {
     /// The UTF-8 encoded Error message string.
     pub error_message: Vec<u8>,
@@ -57,3 +375,70 @@ impl ErrorMessage {
         String::from_utf8(self.error_message.clone()).unwrap_or_else(|_| "".into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_method_defaults() {
+        let m = "hello".parse::<RPCMethod>().unwrap();
+        assert_eq!(m, RPCMethod::new("hello", "1"));
+        assert_eq!(m.encodings, vec!["ssz_snappy", "ssz"]);
+        assert_eq!("hello/2".parse::<RPCMethod>().unwrap().version, "2");
+    }
+
+    #[test]
+    fn test_method_options() {
+        let m = "blocks/2;streaming;encoding=ssz;max_size=1024;quota=5;quota_window=100"
+            .parse::<RPCMethod>()
+            .unwrap();
+        assert_eq!(m.version, "2");
+        assert!(m.streaming);
+        assert!(!m.one_way);
+        assert_eq!(m.encodings, vec!["ssz"]);
+        assert_eq!(m.limits.max_size, 1024);
+        assert_eq!(m.limits.quota, 5);
+        assert_eq!(m.limits.quota_window, 100);
+        assert_eq!(m.limits.ttfb_timeout, DEFAULT_TTFB_TIMEOUT);
+
+        let m = "notify;one_way;ttfb_timeout=1;request_timeout=2;response_timeout=3"
+            .parse::<RPCMethod>()
+            .unwrap();
+        assert!(m.one_way);
+        assert_eq!(m.limits.ttfb_timeout, 1);
+        assert_eq!(m.limits.request_timeout, 2);
+        assert_eq!(m.limits.response_timeout, 3);
+
+        let m = "hello;encoding=snappy".parse::<RPCMethod>().unwrap();
+        assert_eq!(m.encodings, vec!["snappy"]);
+    }
+
+    #[test]
+    fn test_method_invalid() {
+        for s in &[
+            "",
+            "/1",
+            "hello/",
+            "hello/1/2",
+            "hello;streaming=1",
+            "hello;encoding=json",
+            "hello;max_size",
+            "hello;quota=-1",
+            "hello;unknown",
+            "hello;streaming;one_way",
+        ] {
+            assert!(s.parse::<RPCMethod>().is_err(), "{} should not parse", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_methods() {
+        let methods = parse_methods("hello/2,hello/1,notify;one_way").unwrap();
+        assert_eq!(methods.len(), 3);
+        assert_eq!(methods[1].version, "1");
+        assert!(parse_methods("hello,").is_err());
+        assert!(parse_methods("goodbye").is_err());
+        assert!(parse_methods("notify/2;one_way,notify/1").is_err());
+    }
+}
@@ -4,23 +4,41 @@
 //! direct peer-to-peer communication primarily for sending/receiving chain information for
 //! syncing.
 
+use crate::NetworkConfig;
+use fnv::{FnvHashMap, FnvHashSet};
 use futures::prelude::*;
 use handler::RPCHandler;
 use libp2p::core::ConnectedPoint;
 use libp2p::swarm::{
-    protocols_handler::ProtocolsHandler, NetworkBehaviour, NetworkBehaviourAction, PollParameters,
+    protocols_handler::{ProtocolsHandler, SubstreamProtocol},
+    NetworkBehaviour, NetworkBehaviourAction, PollParameters,
 };
 use libp2p::{Multiaddr, PeerId};
-pub use methods::{ErrorMessage, RPCErrorResponse, RPCResponse, RequestId};
+pub use methods::{
+    parse_methods, validate_methods, ErrorMessage, GoodbyeReason, MetaData, Ping, RPCErrorResponse,
+    RPCLimits, RPCMethod, RPCResponse, RequestId, METADATA_METHOD, PING_METHOD, STATUS_METHOD,
+};
 pub use protocol::{RPCError, RPCProtocol, RPCRequest};
 use slog::o;
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 use tokio::io::{AsyncRead, AsyncWrite};
 
 pub(crate) mod codec;
 mod handler;
 pub mod methods;
 mod protocol;
+mod rate_limiter;
+
+/// Sequential id for outbound RPC requests. Starts at 1, so that 0 is never handed out.
+static NEXT_REQUEST_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Returns a new id for an outbound RPC request. Ids are unique across all peers, so that the
+/// requests of the application and of the network layer never clash.
+pub fn next_request_id() -> RequestId {
+    NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed)
+}
 
 /// The return type used in the behaviour and the resultant event from the protocols handler.
 #[derive(Debug)]
@@ -46,11 +64,34 @@ impl RPCEvent {
     }
 }
 
+/// An outbound request that has not been answered yet.
+struct PendingRequest {
+    /// The name of the method the request was sent on.
+    method: String,
+}
+
 /// Implements the libp2p `NetworkBehaviour` trait and therefore manages network-level
 /// logic.
 pub struct RPC<TSubstream> {
     /// Queue of events to processed.
     events: Vec<NetworkBehaviourAction<RPCEvent, RPCMessage>>,
+    /// The methods supported by this node.
+    methods: Vec<RPCMethod>,
+    /// The maximum number of outbound substreams being negotiated at once with a peer.
+    max_dial_negotiated: u32,
+    /// The maximum number of inbound substreams of a peer awaiting responses.
+    max_inbound_substreams: usize,
+    /// The number of rate limited requests after which a peer is reported.
+    max_rate_limit_offences: u32,
+    /// The rate limited requests of each peer since it was last reported.
+    rate_limit_offences: FnvHashMap<PeerId, u32>,
+    /// The time after which a connection without any RPC activity is closed.
+    inactive_timeout: Duration,
+    /// The peers that we are connected to. Requests to any other peer fail immediately.
+    connected_peers: FnvHashSet<PeerId>,
+    /// Outbound requests awaiting a response, so that their failure can be reported with the
+    /// method they were sent on.
+    pending_requests: FnvHashMap<(PeerId, RequestId), PendingRequest>,
     /// Pins the generic substream.
     marker: PhantomData<TSubstream>,
     /// Slog logger for RPC behaviour.
@@ -58,10 +99,22 @@ pub struct RPC<TSubstream> {
 }
 
 impl<TSubstream> RPC<TSubstream> {
-    pub fn new(log: &slog::Logger) -> Self {
+    pub fn new(config: &NetworkConfig, log: &slog::Logger) -> Self {
         let log = log.new(o!("Service" => "Libp2p-RPC"));
+        let mut methods = config.rpc_methods.clone();
+        if config.status_handshake && !methods.iter().any(|m| m.name == STATUS_METHOD) {
+            methods.push(RPCMethod::new(STATUS_METHOD, "1"));
+        }
         RPC {
             events: Vec::new(),
+            methods,
+            max_dial_negotiated: config.rpc_max_dial_negotiated,
+            max_inbound_substreams: config.rpc_max_inbound_substreams,
+            max_rate_limit_offences: config.rpc_max_rate_limit_offences,
+            rate_limit_offences: FnvHashMap::default(),
+            inactive_timeout: Duration::from_millis(config.rpc_inactive_timeout),
+            connected_peers: FnvHashSet::default(),
+            pending_requests: FnvHashMap::default(),
             marker: PhantomData,
             _log: log,
         }
@@ -69,8 +122,35 @@ impl<TSubstream> RPC<TSubstream> {
 
     /// Submits an RPC request.
     ///
-    /// The peer must be connected for this to succeed.
+    /// The peer must be connected for this to succeed. A request to a peer that is not connected
+    /// fails with `RPCError::Disconnected`.
     pub fn send_rpc(&mut self, peer_id: PeerId, rpc_event: RPCEvent) {
+        if let RPCEvent::Request(id, req) = &rpc_event {
+            let method = req.method().to_string();
+            // the versions of a method agree on whether it is one-way, so this is known before
+            // a version is negotiated
+            let one_way = self.methods.iter().any(|m| m.name == method && m.one_way);
+            let expect_response = req.expect_response() && !one_way;
+            if !self.connected_peers.contains(&peer_id) {
+                // requests without a response, such as Goodbye or those of one-way methods, are
+                // dropped silently
+                if expect_response {
+                    self.events.push(NetworkBehaviourAction::GenerateEvent(
+                        RPCMessage::RequestFailed {
+                            peer_id,
+                            request_id: *id,
+                            method,
+                            error: RPCError::Disconnected,
+                        },
+                    ));
+                }
+                return;
+            }
+            if expect_response {
+                self.pending_requests
+                    .insert((peer_id.clone(), *id), PendingRequest { method });
+            }
+        }
         self.events.push(NetworkBehaviourAction::SendEvent {
             peer_id,
             event: rpc_event,
@@ -86,7 +166,12 @@ where
     type OutEvent = RPCMessage;
 
     fn new_handler(&mut self) -> Self::ProtocolsHandler {
-        Default::default()
+        RPCHandler::new(
+            SubstreamProtocol::new(RPCProtocol::new(self.methods.clone())),
+            self.max_dial_negotiated,
+            self.max_inbound_substreams,
+            self.inactive_timeout,
+        )
     }
 
     // handled by discovery
@@ -95,15 +180,41 @@ where
     }
 
     fn inject_connected(&mut self, peer_id: PeerId, connected_point: ConnectedPoint) {
-        // if initialised the connection, report this upwards to send the HELLO request
-        if let ConnectedPoint::Dialer { .. } = connected_point {
-            self.events.push(NetworkBehaviourAction::GenerateEvent(
-                RPCMessage::PeerDialed(peer_id),
-            ));
-        }
+        self.connected_peers.insert(peer_id.clone());
+        // if initialised the connection, report this upwards to start the status handshake
+        // otherwise report it so that it can be refused if we have too many inbound peers
+        let message = match connected_point {
+            ConnectedPoint::Dialer { .. } => RPCMessage::PeerDialed(peer_id),
+            ConnectedPoint::Listener { .. } => RPCMessage::PeerConnectedIncoming(peer_id),
+        };
+        self.events
+            .push(NetworkBehaviourAction::GenerateEvent(message));
     }
 
     fn inject_disconnected(&mut self, peer_id: &PeerId, _: ConnectedPoint) {
+        self.connected_peers.remove(peer_id);
+        self.rate_limit_offences.remove(peer_id);
+
+        // fail the requests that were awaiting a response from the peer
+        let failed: Vec<RequestId> = self
+            .pending_requests
+            .keys()
+            .filter(|(peer, _)| peer == peer_id)
+            .map(|(_, id)| *id)
+            .collect();
+        for request_id in failed {
+            if let Some(request) = self.pending_requests.remove(&(peer_id.clone(), request_id)) {
+                self.events.push(NetworkBehaviourAction::GenerateEvent(
+                    RPCMessage::RequestFailed {
+                        peer_id: peer_id.clone(),
+                        request_id,
+                        method: request.method,
+                        error: RPCError::Disconnected,
+                    },
+                ));
+            }
+        }
+
         // inform the rpc handler that the peer has disconnected
         self.events.push(NetworkBehaviourAction::GenerateEvent(
             RPCMessage::PeerDisconnected(peer_id.clone()),
@@ -115,6 +226,54 @@ where
         source: PeerId,
         event: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent,
     ) {
+        match event {
+            RPCEvent::Response(id, ref response) => {
+                let key = (source.clone(), id);
+                // a request is answered by its last response. Whether more may follow depends
+                // on the version of the method negotiated, which the response is tagged with
+                let last = match response {
+                    RPCErrorResponse::Success(RPCResponse::Message(method, version, _)) => !self
+                        .methods
+                        .iter()
+                        .any(|m| m.name == *method && m.version == *version && m.streaming),
+                    // built-in methods do not stream, and errors end a stream
+                    _ => true,
+                };
+                if last {
+                    self.pending_requests.remove(&key);
+                }
+            }
+            RPCEvent::Error(_, RPCError::RateLimited(_)) => {
+                // peers that keep exceeding the rate limits are reported
+                let offences = self.rate_limit_offences.entry(source.clone()).or_insert(0);
+                *offences += 1;
+                if *offences >= self.max_rate_limit_offences {
+                    *offences = 0;
+                    self.events.push(NetworkBehaviourAction::GenerateEvent(
+                        RPCMessage::PeerRateLimited(source.clone()),
+                    ));
+                }
+                // the request was answered with an error by the handler, so the user is not told
+                return;
+            }
+            RPCEvent::Error(id, error) => {
+                let message = match self.pending_requests.remove(&(source.clone(), id)) {
+                    Some(request) => RPCMessage::RequestFailed {
+                        peer_id: source,
+                        request_id: id,
+                        method: request.method,
+                        error,
+                    },
+                    // errors of inbound substreams are not related to any of our requests
+                    None => RPCMessage::RPC(source, RPCEvent::Error(id, error)),
+                };
+                self.events
+                    .push(NetworkBehaviourAction::GenerateEvent(message));
+                return;
+            }
+            RPCEvent::Request(_, _) => {}
+        }
+
         // send the event to the user
         self.events
             .push(NetworkBehaviourAction::GenerateEvent(RPCMessage::RPC(
@@ -142,5 +301,17 @@ where
 pub enum RPCMessage {
     RPC(PeerId, RPCEvent),
     PeerDialed(PeerId),
+    /// A peer dialed us.
+    PeerConnectedIncoming(PeerId),
     PeerDisconnected(PeerId),
+    /// The peer repeatedly exceeded the rate limits of its inbound requests.
+    PeerRateLimited(PeerId),
+    /// An outbound request failed before it was answered.
+    RequestFailed {
+        peer_id: PeerId,
+        request_id: RequestId,
+        /// The name of the method the request was sent on.
+        method: String,
+        error: RPCError,
+    },
 }
@@ -1,13 +1,19 @@
 use crate::rpc::codec::{
     base::{BaseInboundCodec, BaseOutboundCodec},
     serenity::{SerenityInboundCodec, SerenityOutboundCodec},
+    ssz_snappy::{SSZSnappyInboundCodec, SSZSnappyOutboundCodec},
     InboundCodec, OutboundCodec,
 };
+use crate::rpc::methods::{
+    GoodbyeReason, Ping, RPCLimits, RPCMethod, ENCODINGS, GOODBYE_METHOD, METADATA_METHOD,
+    PING_METHOD,
+};
 use futures::{
     future::{self, FutureResult},
     sink, stream, Sink, Stream,
 };
 use libp2p::core::{upgrade, InboundUpgrade, OutboundUpgrade, ProtocolName, UpgradeInfo};
+use ssz::{Decode, Encode};
 use std::io;
 use std::time::Duration;
 use tokio::codec::Framed;
@@ -17,30 +23,97 @@ use tokio::timer::timeout;
 use tokio::util::FutureExt;
 use tokio_io_timeout::TimeoutStream;
 
-/// The maximum bytes that can be sent across the RPC.
-const MAX_RPC_SIZE: usize = 4_194_304; // 4M
 /// The protocol prefix the RPC protocol id.
 const PROTOCOL_PREFIX: &str = "/eth2/beacon_chain/req";
-/// Time allowed for the first byte of a request to arrive before we time out (Time To First Byte).
-const TTFB_TIMEOUT: u64 = 5;
-/// The number of seconds to wait for the first bytes of a request once a protocol has been
-/// established before the stream is terminated.
-const REQUEST_TIMEOUT: u64 = 15;
 
 #[derive(Debug, Clone)]
-pub struct RPCProtocol;
+pub struct RPCProtocol {
+    /// The methods that are advertised and accepted on inbound substreams.
+    methods: Vec<RPCMethod>,
+}
+
+impl RPCProtocol {
+    pub fn new(mut methods: Vec<RPCMethod>) -> Self {
+        // Goodbye is built in, so that any peer can be told why it is disconnected, as are the
+        // ping and metadata methods that keep track of our peers
+        for built_in in &[GOODBYE_METHOD, PING_METHOD, METADATA_METHOD] {
+            if !methods.iter().any(|m| m.name == *built_in) {
+                methods.push(RPCMethod::new(built_in, "1"));
+            }
+        }
+        RPCProtocol { methods }
+    }
+
+    /// Returns the supported versions of `method`, in order of preference. The list is empty if
+    /// the method is not supported.
+    pub fn versions(&self, method: &str) -> Vec<RPCMethod> {
+        self.methods
+            .iter()
+            .filter(|m| m.name == method)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the protocol ids of a version of a method, one for each of its encodings. Unknown
+    /// encodings are skipped.
+    fn method_protocols(method: &RPCMethod) -> Vec<ProtocolId> {
+        method
+            .encodings
+            .iter()
+            .filter(|encoding| ENCODINGS.contains(&encoding.as_str()))
+            .map(|encoding| ProtocolId::new(&method.name, &method.version, encoding))
+            .collect()
+    }
+
+    /// Returns the version of a method negotiated on `protocol`.
+    fn negotiated(&self, protocol: &ProtocolId) -> Option<&RPCMethod> {
+        self.methods
+            .iter()
+            .find(|m| m.name == protocol.message_name && m.version == protocol.version)
+    }
+
+    /// Returns `true` if the substream of `request`, negotiated on `protocol`, is kept open to
+    /// await a response. Requests without a response, such as Goodbye or those of one-way
+    /// methods, are not answered.
+    pub fn expect_response(&self, request: &RPCRequest, protocol: &ProtocolId) -> bool {
+        request.expect_response()
+            && !self
+                .negotiated(protocol)
+                .map(|m| m.one_way)
+                .unwrap_or(false)
+    }
+
+    /// Returns `true` if the version of a method negotiated on `protocol` responds with a stream
+    /// of chunks.
+    pub fn is_streaming(&self, protocol: &ProtocolId) -> bool {
+        self.negotiated(protocol)
+            .map(|m| m.streaming)
+            .unwrap_or(false)
+    }
+
+    /// Returns the size limit and timeouts of the version of a method negotiated on `protocol`,
+    /// or the defaults if it is not supported.
+    pub fn limits(&self, protocol: &ProtocolId) -> RPCLimits {
+        self.negotiated(protocol)
+            .map(|m| m.limits.clone())
+            .unwrap_or_default()
+    }
+}
 
 impl UpgradeInfo for RPCProtocol {
     type Info = ProtocolId;
     type InfoIter = Vec<Self::Info>;
 
     fn protocol_info(&self) -> Self::InfoIter {
-        vec![ProtocolId::new("hello", "1", "ssz")]
+        self.methods
+            .iter()
+            .flat_map(Self::method_protocols)
+            .collect()
     }
 }
 
 /// Tracks the types in a protocol id.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct ProtocolId {
     /// The rpc message type/name.
     pub message_name: String,
@@ -81,12 +154,12 @@ impl ProtocolName for ProtocolId {
 /* Inbound upgrade */
 
 // The inbound protocol reads the request, decodes it and returns the stream to the protocol
-// handler to respond to once ready.
+// handler to respond to once ready, along with the protocol id it was negotiated on.
 
-pub type InboundOutput<TSocket> = (RPCRequest, InboundFramed<TSocket>);
+pub type InboundOutput<TSocket> = (RPCRequest, InboundFramed<TSocket>, ProtocolId);
 pub type InboundFramed<TSocket> = Framed<TimeoutStream<upgrade::Negotiated<TSocket>>, InboundCodec>;
 type FnAndThen<TSocket> = fn(
-    (Option<RPCRequest>, InboundFramed<TSocket>),
+    (ProtocolId, (Option<RPCRequest>, InboundFramed<TSocket>)),
 ) -> FutureResult<InboundOutput<TSocket>, RPCError>;
 type FnMapErr<TSocket> = fn(timeout::Error<(RPCError, InboundFramed<TSocket>)>) -> RPCError;
 
@@ -98,9 +171,12 @@ where
     type Error = RPCError;
 
     type Future = future::AndThen<
-        future::MapErr<
-            timeout::Timeout<stream::StreamFuture<InboundFramed<TSocket>>>,
-            FnMapErr<TSocket>,
+        future::Join<
+            FutureResult<ProtocolId, RPCError>,
+            future::MapErr<
+                timeout::Timeout<stream::StreamFuture<InboundFramed<TSocket>>>,
+                FnMapErr<TSocket>,
+            >,
         >,
         FutureResult<InboundOutput<TSocket>, RPCError>,
         FnAndThen<TSocket>,
@@ -111,27 +187,28 @@ where
         socket: upgrade::Negotiated<TSocket>,
         protocol: ProtocolId,
     ) -> Self::Future {
-        match protocol.encoding.as_str() {
-            "ssz" | _ => {
-                let serenity_codec =
-                    BaseInboundCodec::new(SerenityInboundCodec::new(protocol, MAX_RPC_SIZE));
-                let codec = InboundCodec::Serenity(serenity_codec);
-                let mut timed_socket = TimeoutStream::new(socket);
-                timed_socket.set_read_timeout(Some(Duration::from_secs(TTFB_TIMEOUT)));
-                Framed::new(timed_socket, codec)
-                    .into_future()
-                    .timeout(Duration::from_secs(REQUEST_TIMEOUT))
-                    .map_err(RPCError::from as FnMapErr<TSocket>)
-                    .and_then({
-                        |(req, stream)| match req {
-                            Some(req) => futures::future::ok((req, stream)),
-                            None => futures::future::err(RPCError::Custom(
-                                "Stream terminated early".into(),
-                            )),
-                        }
-                    } as FnAndThen<TSocket>)
+        let limits = self.limits(&protocol);
+        let codec = match protocol.encoding.as_str() {
+            "ssz_snappy" | "snappy" => InboundCodec::SSZSnappy(BaseInboundCodec::new(
+                SSZSnappyInboundCodec::new(protocol.clone(), limits.max_size),
+            )),
+            "ssz" | _ => InboundCodec::Serenity(BaseInboundCodec::new(SerenityInboundCodec::new(
+                protocol.clone(),
+                limits.max_size,
+            ))),
+        };
+        let mut timed_socket = TimeoutStream::new(socket);
+        timed_socket.set_read_timeout(Some(Duration::from_millis(limits.ttfb_timeout)));
+        let request = Framed::new(timed_socket, codec)
+            .into_future()
+            .timeout(Duration::from_millis(limits.request_timeout))
+            .map_err(RPCError::from as FnMapErr<TSocket>);
+        future::ok(protocol).join(request).and_then({
+            |(protocol, (req, stream))| match req {
+                Some(req) => futures::future::ok((req, stream, protocol)),
+                None => futures::future::err(RPCError::Custom("Stream terminated early".into())),
             }
-        }
+        } as FnAndThen<TSocket>)
     }
 }
 
@@ -142,62 +219,127 @@ where
 
 #[derive(Debug, Clone)]
 pub enum RPCRequest {
-    Message(Vec<u8>),
+    /// An RPC message, tagged with the name and version of the method being called. The version
+    /// is the one negotiated with the peer; it is left empty when sending, and is ignored.
+    Message(String, String, Vec<u8>),
+    /// Informs the peer that it is about to be disconnected, and why.
+    Goodbye(GoodbyeReason),
+    /// Checks that the peer is alive, and carries our metadata sequence number.
+    Ping(Ping),
+    /// Requests the metadata of the peer. The request has no body.
+    MetaData,
 }
 
-impl UpgradeInfo for RPCRequest {
-    type Info = ProtocolId;
-    type InfoIter = Vec<Self::Info>;
+impl RPCRequest {
+    /// Decodes a request received on `protocol`.
+    pub fn from_bytes(protocol: &ProtocolId, bytes: Vec<u8>) -> Result<Self, RPCError> {
+        match protocol.message_name.as_str() {
+            GOODBYE_METHOD => u64::from_ssz_bytes(&bytes)
+                .map(|code| RPCRequest::Goodbye(code.into()))
+                .map_err(|e| RPCError::Custom(format!("Invalid Goodbye request: {:?}", e))),
+            PING_METHOD => Ping::from_ssz_bytes(&bytes)
+                .map(RPCRequest::Ping)
+                .map_err(|e| RPCError::Custom(format!("Invalid Ping request: {:?}", e))),
+            METADATA_METHOD => Ok(RPCRequest::MetaData),
+            method => Ok(RPCRequest::Message(
+                method.into(),
+                protocol.version.clone(),
+                bytes,
+            )),
+        }
+    }
 
-    // add further protocols as we support more encodings/versions
-    fn protocol_info(&self) -> Self::InfoIter {
-        self.supported_protocols()
+    /// Encodes the request. A Goodbye request is sent as its SSZ encoded reason code, and a ping
+    /// as its SSZ encoded sequence number.
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self {
+            RPCRequest::Message(_, _, bytes) => bytes,
+            RPCRequest::Goodbye(reason) => u64::from(reason).as_ssz_bytes(),
+            RPCRequest::Ping(ping) => ping.as_ssz_bytes(),
+            RPCRequest::MetaData => Vec::new(),
+        }
     }
-}
 
-/// Implements the encoding per supported protocol for RPCRequest.
-impl RPCRequest {
-    pub fn supported_protocols(&self) -> Vec<ProtocolId> {
+    /// The name of the method this request is sent on.
+    pub fn method(&self) -> &str {
         match self {
-            // add more protocols when versions/encodings are supported
-            RPCRequest::Message(_) => vec![ProtocolId::new("hello", "1", "ssz")],
+            RPCRequest::Message(method, _, _) => method,
+            RPCRequest::Goodbye(_) => GOODBYE_METHOD,
+            RPCRequest::Ping(_) => PING_METHOD,
+            RPCRequest::MetaData => METADATA_METHOD,
         }
     }
 
     /// This specifies whether a stream should remain open and await a response, given a request.
     pub fn expect_response(&self) -> bool {
         match self {
-            RPCRequest::Message(_) => true,
+            RPCRequest::Message(_, _, _) | RPCRequest::Ping(_) | RPCRequest::MetaData => true,
+            RPCRequest::Goodbye(_) => false,
         }
     }
 }
 
+/// An outbound request, along with the versions of its method it may be negotiated on.
+#[derive(Debug, Clone)]
+pub struct OutboundRequest {
+    /// The versions supported for the request's method, in order of preference.
+    pub versions: Vec<RPCMethod>,
+    /// The request to send once a protocol has been negotiated.
+    pub request: RPCRequest,
+}
+
+impl UpgradeInfo for OutboundRequest {
+    type Info = ProtocolId;
+    type InfoIter = Vec<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.versions
+            .iter()
+            .flat_map(RPCProtocol::method_protocols)
+            .collect()
+    }
+}
+
 /* RPC Response type - used for outbound upgrades */
 
 /* Outbound upgrades */
 
 pub type OutboundFramed<TSocket> = Framed<upgrade::Negotiated<TSocket>, OutboundCodec>;
 
-impl<TSocket> OutboundUpgrade<TSocket> for RPCRequest
+/// The stream of an outbound request that has been sent, along with the protocol id it was
+/// negotiated on.
+pub type OutboundOutput<TSocket> = (OutboundFramed<TSocket>, ProtocolId);
+
+impl<TSocket> OutboundUpgrade<TSocket> for OutboundRequest
 where
     TSocket: AsyncRead + AsyncWrite,
 {
-    type Output = OutboundFramed<TSocket>;
+    type Output = OutboundOutput<TSocket>;
     type Error = RPCError;
-    type Future = sink::Send<OutboundFramed<TSocket>>;
+    type Future =
+        future::Join<sink::Send<OutboundFramed<TSocket>>, FutureResult<ProtocolId, RPCError>>;
     fn upgrade_outbound(
         self,
         socket: upgrade::Negotiated<TSocket>,
         protocol: Self::Info,
     ) -> Self::Future {
-        match protocol.encoding.as_str() {
-            "ssz" | _ => {
-                let serenity_codec =
-                    BaseOutboundCodec::new(SerenityOutboundCodec::new(protocol, MAX_RPC_SIZE));
-                let codec = OutboundCodec::Serenity(serenity_codec);
-                Framed::new(socket, codec).send(self)
-            }
-        }
+        let max_size = self
+            .versions
+            .iter()
+            .find(|m| m.version == protocol.version)
+            .map(|m| m.limits.max_size)
+            .unwrap_or_else(|| RPCLimits::default().max_size);
+        let codec = match protocol.encoding.as_str() {
+            "ssz_snappy" | "snappy" => OutboundCodec::SSZSnappy(BaseOutboundCodec::new(
+                SSZSnappyOutboundCodec::new(protocol.clone(), max_size),
+            )),
+            "ssz" | _ => OutboundCodec::Serenity(BaseOutboundCodec::new(
+                SerenityOutboundCodec::new(protocol.clone(), max_size),
+            )),
+        };
+        Framed::new(socket, codec)
+            .send(self.request)
+            .join(future::ok(protocol))
     }
 }
 
@@ -212,6 +354,13 @@ pub enum RPCError {
     IoError(io::Error),
     /// Waiting for a request/response timed out, or timer error'd.
     StreamTimeout,
+    /// The peer does not support any of the protocol ids of the method.
+    UnsupportedProtocol,
+    /// The peer is not connected, or disconnected before responding.
+    Disconnected,
+    /// An inbound request of the peer on the given method exceeded the rate limits, and was
+    /// answered with an error.
+    RateLimited(String),
     /// Custom message.
     Custom(String),
 }
@@ -247,6 +396,9 @@ impl std::fmt::Display for RPCError {
             RPCError::InvalidProtocol(ref err) => write!(f, "Invalid Protocol: {}", err),
             RPCError::IoError(ref err) => write!(f, "IO Error: {}", err),
             RPCError::StreamTimeout => write!(f, "Stream Timeout"),
+            RPCError::UnsupportedProtocol => write!(f, "Protocol not supported by the peer"),
+            RPCError::Disconnected => write!(f, "Peer disconnected"),
+            RPCError::RateLimited(ref method) => write!(f, "Rate limit exceeded on {}", method),
             RPCError::Custom(ref err) => write!(f, "{}", err),
         }
     }
@@ -259,6 +411,9 @@ impl std::error::Error for RPCError {
             RPCError::InvalidProtocol(_) => None,
             RPCError::IoError(ref err) => Some(err),
             RPCError::StreamTimeout => None,
+            RPCError::UnsupportedProtocol => None,
+            RPCError::Disconnected => None,
+            RPCError::RateLimited(_) => None,
             RPCError::Custom(_) => None,
         }
     }
@@ -0,0 +1,151 @@
+//! Limits the rate of inbound RPC requests of a peer, with a token bucket per version of a method.
+
+use super::methods::RPCLimits;
+use super::protocol::ProtocolId;
+use fnv::FnvHashMap;
+use std::time::{Duration, Instant};
+
+/// A bucket holding up to `quota` tokens, which refills at `quota` tokens per window. Each request
+/// takes a token.
+struct TokenBucket {
+    /// The tokens currently available.
+    tokens: f64,
+    /// The last time the bucket was refilled.
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket at `now`.
+    fn new(quota: u64, now: Instant) -> Self {
+        TokenBucket {
+            tokens: quota as f64,
+            last_refill: now,
+        }
+    }
+
+    /// Refills the bucket for the time elapsed between the last refill and `now`, then takes a
+    /// token if one is available.
+    fn take(&mut self, quota: u64, window: Duration, now: Instant) -> bool {
+        let elapsed = now
+            .checked_duration_since(self.last_refill)
+            .unwrap_or_default();
+        self.last_refill = self.last_refill.max(now);
+
+        let window_secs = window.as_secs_f64();
+        let refill = if window_secs > 0.0 {
+            quota as f64 * elapsed.as_secs_f64() / window_secs
+        } else {
+            quota as f64
+        };
+        self.tokens = (self.tokens + refill).min(quota as f64);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Keeps a token bucket for each version of a method a peer sends requests on, as each version has
+/// its own limits.
+#[derive(Default)]
+pub struct RateLimiter {
+    /// The buckets, by method name and version.
+    buckets: FnvHashMap<(String, String), TokenBucket>,
+}
+
+impl RateLimiter {
+    /// Returns `true` if a request negotiated on `protocol` is within the quota given by
+    /// `limits` at `now`, taking a token from the bucket of its method version. A quota of 0 does
+    /// not limit the method.
+    pub fn allows(&mut self, protocol: &ProtocolId, limits: &RPCLimits, now: Instant) -> bool {
+        if limits.quota == 0 {
+            return true;
+        }
+        let window = Duration::from_millis(limits.quota_window);
+        self.buckets
+            .entry((protocol.message_name.clone(), protocol.version.clone()))
+            .or_insert_with(|| TokenBucket::new(limits.quota, now))
+            .take(limits.quota, window, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_takes_up_to_quota() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(3, now);
+        let window = Duration::from_secs(1);
+        assert!(bucket.take(3, window, now));
+        assert!(bucket.take(3, window, now));
+        assert!(bucket.take(3, window, now));
+        assert!(!bucket.take(3, window, now));
+    }
+
+    #[test]
+    fn test_bucket_refills_over_window() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2, now);
+        let window = Duration::from_secs(1);
+        assert!(bucket.take(2, window, now));
+        assert!(bucket.take(2, window, now));
+        assert!(!bucket.take(2, window, now));
+
+        // half a window refills one token
+        let later = now + Duration::from_millis(500);
+        assert!(bucket.take(2, window, later));
+        assert!(!bucket.take(2, window, later));
+
+        // the bucket never holds more than the quota
+        let much_later = later + Duration::from_secs(10);
+        assert!(bucket.take(2, window, much_later));
+        assert!(bucket.take(2, window, much_later));
+        assert!(!bucket.take(2, window, much_later));
+    }
+
+    #[test]
+    fn test_bucket_ignores_earlier_instants() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(1, now + Duration::from_secs(1));
+        let window = Duration::from_secs(1);
+        assert!(bucket.take(1, window, now));
+        assert!(!bucket.take(1, window, now));
+    }
+
+    #[test]
+    fn test_zero_quota_is_unlimited() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::default();
+        let protocol = ProtocolId::new("hello", "1", "ssz");
+        let limits = RPCLimits {
+            quota: 0,
+            ..RPCLimits::default()
+        };
+        for _ in 0..1000 {
+            assert!(limiter.allows(&protocol, &limits, now));
+        }
+    }
+
+    #[test]
+    fn test_bucket_per_method_version() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::default();
+        let v1 = ProtocolId::new("hello", "1", "ssz");
+        let v1_snappy = ProtocolId::new("hello", "1", "ssz_snappy");
+        let v2 = ProtocolId::new("hello", "2", "ssz");
+        let limits = RPCLimits {
+            quota: 1,
+            ..RPCLimits::default()
+        };
+        assert!(limiter.allows(&v1, &limits, now));
+        // the encoding does not get a peer a fresh bucket
+        assert!(!limiter.allows(&v1_snappy, &limits, now));
+        assert!(limiter.allows(&v2, &limits, now));
+        assert!(!limiter.allows(&v2, &limits, now));
+    }
+}
@@ -1,8 +1,11 @@
+use crate::ban_list::{BanList, IpBan, IpRange};
 use crate::behaviour::{Behaviour, BehaviourEvent};
 use crate::multiaddr::Protocol;
-use crate::rpc::RPCEvent;
+use crate::peer_manager::{PeerAction, PeerManager, ScoreState};
+use crate::rpc::{GoodbyeReason, RPCError, RPCErrorResponse, RPCEvent, RPCRequest, RequestId};
 use crate::types::error;
-use crate::{EnrForkId, GossipTopic, NetworkConfig, NetworkGlobals, TopicHash};
+use crate::{Enr, EnrForkId, GossipTopic, NetworkConfig, NetworkGlobals, TopicHash};
+use fnv::FnvHashSet;
 use futures::prelude::*;
 use futures::Stream;
 use libp2p::core::{
@@ -20,9 +23,10 @@ use slog::{crit, debug, error, info, trace, warn};
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::timer::DelayQueue;
+use tokio::timer::{DelayQueue, Interval};
 
 type Libp2pStream = Boxed<(PeerId, StreamMuxerBox), Error>;
 type Libp2pBehaviour = Behaviour<Substream<StreamMuxerBox>>;
@@ -31,6 +35,13 @@ const NETWORK_KEY_FILENAME: &str = "key";
 /// The time in milliseconds to wait before banning a peer. This allows for any Goodbye messages to be
 /// flushed and protocols to be negotiated.
 const BAN_PEER_WAIT_TIMEOUT: u64 = 200;
+/// The interval in seconds at which connected peers are pinged.
+const PING_INTERVAL: u64 = 30;
+/// The interval in seconds at which peer scores decay.
+const PEER_MANAGER_HEARTBEAT: u64 = 30;
+/// The time in seconds a peer that is disconnected for its score or a banned IP address is banned
+/// for, so that it does not reconnect right away.
+const DISCONNECT_BAN_TIMEOUT: u64 = 30;
 
 /// The configuration and state of the libp2p components for the beacon node.
 pub struct Service {
@@ -47,6 +58,30 @@ pub struct Service {
     /// A list of timeouts after which peers become unbanned.
     peer_ban_timeout: DelayQueue<PeerId>,
 
+    /// The peers with a ban that has not timed out yet, including bans waiting for a Goodbye.
+    banned_peers: FnvHashSet<PeerId>,
+
+    /// A current list of peers to disconnect, without banning them, after a given timeout.
+    peers_to_disconnect: DelayQueue<PeerId>,
+
+    /// Triggers the periodic ping of our peers.
+    ping_interval: Interval,
+
+    /// Keeps the reputation scores of our peers.
+    peer_manager: PeerManager,
+
+    /// Triggers the periodic decay of peer scores.
+    peer_manager_heartbeat: Interval,
+
+    /// The time a peer whose score fell to the ban threshold is banned for.
+    peer_ban_duration: Duration,
+
+    /// A collection of variables accessible outside the network service.
+    network_globals: Arc<NetworkGlobals>,
+
+    /// The directory the ban list is stored in.
+    network_dir: PathBuf,
+
     /// The libp2p logger handle.
     pub log: slog::Logger,
 }
@@ -74,8 +109,12 @@ impl Service {
             local_peer_id.clone(),
             config.libp2p_port,
             config.discovery_port,
+            BanList::load(&config.network_dir, &log),
         ));
 
+        // topics are prefixed with the digest of the current fork
+        let fork_digest = enr_fork_id.fork_digest;
+
         let mut swarm = {
             // Set up the transport - tcp/ws with noise/secio and mplex/yamux
             let transport = build_transport(local_keypair.clone());
@@ -116,6 +155,14 @@ impl Service {
 
         // helper closure for dialing peers
         let mut dial_addr = |multiaddr: &Multiaddr| {
+            if network_globals
+                .ban_list
+                .read()
+                .is_banned_multiaddr(multiaddr)
+            {
+                debug!(log, "Not dialing banned address"; "address" => format!("{}", multiaddr));
+                return;
+            }
             match Swarm::dial_addr(&mut swarm, multiaddr.clone()) {
                 Ok(()) => debug!(log, "Dialing libp2p peer"; "address" => format!("{}", multiaddr)),
                 Err(err) => debug!(
@@ -150,7 +197,7 @@ impl Service {
                 .topics
                 .iter()
                 .cloned()
-                .map(GossipTopic::new)
+                .map(|topic| GossipTopic::new(topic, fork_digest))
                 .collect(),
         );
 
@@ -170,6 +217,16 @@ impl Service {
             swarm,
             peers_to_ban: DelayQueue::new(),
             peer_ban_timeout: DelayQueue::new(),
+            banned_peers: FnvHashSet::default(),
+            peers_to_disconnect: DelayQueue::new(),
+            ping_interval: Interval::new_interval(Duration::from_secs(PING_INTERVAL)),
+            peer_manager: PeerManager::new(config, network_globals.clone()),
+            peer_manager_heartbeat: Interval::new_interval(Duration::from_secs(
+                PEER_MANAGER_HEARTBEAT,
+            )),
+            peer_ban_duration: Duration::from_secs(config.peer_ban_duration),
+            network_globals: network_globals.clone(),
+            network_dir: config.network_dir.clone(),
             log,
         };
 
@@ -183,8 +240,178 @@ impl Service {
             peer_id.clone(),
             Duration::from_millis(BAN_PEER_WAIT_TIMEOUT),
         );
+        self.banned_peers.insert(peer_id.clone());
         self.peer_ban_timeout.insert(peer_id, timeout);
     }
+
+    /// Sends a Goodbye request with the given reason to a peer, then disconnects and bans it for
+    /// a period of time. The ban is delayed long enough for the Goodbye to be sent.
+    pub fn goodbye_and_ban_peer(
+        &mut self,
+        peer_id: PeerId,
+        reason: GoodbyeReason,
+        timeout: Duration,
+    ) {
+        self.swarm.send_rpc(
+            peer_id.clone(),
+            RPCEvent::Request(0, RPCRequest::Goodbye(reason)),
+        );
+        self.disconnect_and_ban_peer(peer_id, timeout);
+    }
+
+    /// Sends a Goodbye request with the given reason to a peer, then disconnects it without a ban,
+    /// so that it may reconnect later. The disconnect is delayed long enough for the Goodbye to be
+    /// sent.
+    pub fn goodbye_and_disconnect_peer(&mut self, peer_id: PeerId, reason: GoodbyeReason) {
+        debug!(self.log, "Disconnecting peer"; "peer_id" => format!("{:?}", peer_id), "reason" => format!("{:?}", reason));
+        self.swarm.send_rpc(
+            peer_id.clone(),
+            RPCEvent::Request(0, RPCRequest::Goodbye(reason)),
+        );
+        self.peers_to_disconnect
+            .insert(peer_id, Duration::from_millis(BAN_PEER_WAIT_TIMEOUT));
+    }
+
+    /// Closes the connection to a peer and informs the behaviours that it has disconnected.
+    fn close_connection(&mut self, peer_id: &PeerId) {
+        Swarm::ban_peer_id(&mut self.swarm, peer_id.clone());
+        // TODO: Correctly notify protocols of the disconnect
+        // TODO: Also remove peer from the DHT: https://github.com/sigp/lighthouse/issues/629
+        let dummy_connected_point = ConnectedPoint::Dialer {
+            address: "/ip4/0.0.0.0"
+                .parse::<Multiaddr>()
+                .expect("valid multiaddr"),
+        };
+        self.swarm
+            .inject_disconnected(peer_id, dummy_connected_point);
+    }
+
+    /// Updates the score of a peer after an action, and disconnects or bans the peer if its score
+    /// fell below the thresholds.
+    pub fn report_peer(&mut self, peer_id: &PeerId, action: PeerAction) {
+        match self.peer_manager.report_peer(peer_id, action) {
+            Some(ScoreState::Disconnect) => {
+                debug!(self.log, "Peer score fell below the disconnect threshold"; "peer_id" => format!("{:?}", peer_id), "score" => self.peer_manager.score(peer_id));
+                self.goodbye_and_ban_peer(
+                    peer_id.clone(),
+                    GoodbyeReason::Fault,
+                    Duration::from_secs(DISCONNECT_BAN_TIMEOUT),
+                );
+            }
+            Some(ScoreState::Ban) => {
+                debug!(self.log, "Peer score fell below the ban threshold"; "peer_id" => format!("{:?}", peer_id), "score" => self.peer_manager.score(peer_id));
+                self.goodbye_and_ban_peer(
+                    peer_id.clone(),
+                    GoodbyeReason::Fault,
+                    self.peer_ban_duration,
+                );
+            }
+            Some(ScoreState::Healthy) | None => {}
+        }
+    }
+
+    /// The current reputation score of a peer.
+    pub fn peer_score(&self, peer_id: &PeerId) -> f64 {
+        self.peer_manager.score(peer_id)
+    }
+
+    /// Bans an IP address or range, and disconnects the connected peers within it. The ban is
+    /// stored in the network directory.
+    pub fn ban_ip(&mut self, ban: IpBan) {
+        info!(self.log, "Banning IP range"; "range" => ban.range.to_string(), "reason" => &ban.reason);
+        let range = ban.range;
+        self.network_globals.ban_list.write().add(ban);
+        self.persist_ban_list();
+
+        let banned_peers: Vec<PeerId> = self
+            .network_globals
+            .connected_peer_set
+            .read()
+            .iter()
+            .filter(|(_, peer_info)| {
+                peer_info
+                    .ip_address
+                    .map(|ip| range.contains(&ip))
+                    .unwrap_or(false)
+            })
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect();
+        for peer_id in banned_peers {
+            self.goodbye_and_ban_peer(
+                peer_id,
+                GoodbyeReason::Fault,
+                Duration::from_secs(DISCONNECT_BAN_TIMEOUT),
+            );
+        }
+    }
+
+    /// Removes the ban of an IP address or range. Returns `false` if it was not banned.
+    pub fn unban_ip(&mut self, range: &IpRange) -> bool {
+        let removed = self.network_globals.ban_list.write().remove(range);
+        if removed {
+            info!(self.log, "Unbanning IP range"; "range" => range.to_string());
+            self.persist_ban_list();
+        }
+        removed
+    }
+
+    /// Stores the ban list in the network directory.
+    fn persist_ban_list(&self) {
+        if let Err(e) = self
+            .network_globals
+            .ban_list
+            .read()
+            .persist(&self.network_dir)
+        {
+            warn!(self.log, "Could not store the ban list"; "error" => format!("{}", e));
+        }
+    }
+
+    /// Disconnects a newly connected peer if its IP address is banned. Returns `true` if it was
+    /// disconnected.
+    fn disconnect_if_ip_banned(&mut self, peer_id: &PeerId) -> bool {
+        let ip_address = self
+            .network_globals
+            .connected_peer_set
+            .read()
+            .get(peer_id)
+            .and_then(|peer_info| peer_info.ip_address);
+        let banned = ip_address
+            .map(|ip| self.network_globals.ban_list.read().is_banned(&ip))
+            .unwrap_or(false);
+        if banned {
+            debug!(self.log, "Refusing peer with a banned IP address"; "peer_id" => format!("{:?}", peer_id), "ip" => format!("{:?}", ip_address));
+            self.disconnect_and_ban_peer(
+                peer_id.clone(),
+                Duration::from_secs(DISCONNECT_BAN_TIMEOUT),
+            );
+        }
+        banned
+    }
+}
+
+/// The effect of an RPC event received from a peer on its score, if any.
+fn rpc_event_action(rpc_event: &RPCEvent) -> Option<PeerAction> {
+    match rpc_event {
+        RPCEvent::Request(_, _) => None,
+        RPCEvent::Response(_, RPCErrorResponse::Success(_)) => Some(PeerAction::Good),
+        RPCEvent::Response(_, RPCErrorResponse::StreamTermination(_)) => None,
+        RPCEvent::Response(_, _) => Some(PeerAction::HighToleranceError),
+        // reported once the peer repeatedly exceeds the rate limits
+        RPCEvent::Error(_, RPCError::RateLimited(_)) => None,
+        RPCEvent::Error(_, _) => Some(PeerAction::MidToleranceError),
+    }
+}
+
+/// The effect of a failed outbound request on the score of the peer, if any.
+fn request_failed_action(error: &RPCError) -> Option<PeerAction> {
+    match error {
+        RPCError::StreamTimeout => Some(PeerAction::MidToleranceError),
+        RPCError::Disconnected | RPCError::UnsupportedProtocol | RPCError::InvalidProtocol(_) => {
+            None
+        }
+        _ => Some(PeerAction::HighToleranceError),
+    }
 }
 
 impl Stream for Service {
@@ -192,6 +419,23 @@ impl Stream for Service {
     type Error = error::Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        // ping our peers, to check they are alive and to keep track of their metadata
+        while let Ok(Async::Ready(Some(_))) = self.ping_interval.poll() {
+            self.swarm.ping_peers();
+        }
+
+        // decay the scores of our peers, and prune the lowest scoring peers if we have too many
+        while let Ok(Async::Ready(Some(_))) = self.peer_manager_heartbeat.poll() {
+            self.peer_manager.heartbeat();
+            if self.network_globals.ban_list.write().remove_expired() {
+                self.persist_ban_list();
+            }
+            for peer_id in self.peer_manager.peers_to_prune() {
+                debug!(self.log, "Pruning peer"; "peer_id" => format!("{:?}", peer_id), "score" => self.peer_manager.score(&peer_id));
+                self.goodbye_and_disconnect_peer(peer_id, GoodbyeReason::TooManyPeers);
+            }
+        }
+
         loop {
             match self.swarm.poll() {
                 Ok(Async::Ready(Some(event))) => match event {
@@ -210,19 +454,64 @@ impl Stream for Service {
                         })));
                     }
                     BehaviourEvent::RPC(peer_id, event) => {
+                        if let Some(action) = rpc_event_action(&event) {
+                            self.report_peer(&peer_id, action);
+                        }
                         return Ok(Async::Ready(Some(Libp2pEvent::RPC(peer_id, event))));
                     }
+                    BehaviourEvent::RPCFailed {
+                        peer_id,
+                        request_id,
+                        method,
+                        error,
+                    } => {
+                        if let Some(action) = request_failed_action(&error) {
+                            self.report_peer(&peer_id, action);
+                        }
+                        return Ok(Async::Ready(Some(Libp2pEvent::RPCFailed {
+                            peer_id,
+                            request_id,
+                            method,
+                            error,
+                        })));
+                    }
                     BehaviourEvent::PeerDialed(peer_id) => {
-                        return Ok(Async::Ready(Some(Libp2pEvent::PeerDialed(peer_id))));
+                        if !self.disconnect_if_ip_banned(&peer_id) {
+                            return Ok(Async::Ready(Some(Libp2pEvent::PeerDialed(peer_id))));
+                        }
+                    }
+                    BehaviourEvent::PeerConnectedIncoming(peer_id) => {
+                        if self.disconnect_if_ip_banned(&peer_id) {
+                            continue;
+                        }
+                        if self.peer_manager.inbound_limit_exceeded() {
+                            debug!(self.log, "Refusing inbound peer, too many inbound peers"; "peer_id" => format!("{:?}", peer_id));
+                            self.goodbye_and_disconnect_peer(peer_id, GoodbyeReason::TooManyPeers);
+                            continue;
+                        }
+                        return Ok(Async::Ready(Some(Libp2pEvent::PeerConnectedIncoming(
+                            peer_id,
+                        ))));
                     }
                     BehaviourEvent::PeerDisconnected(peer_id) => {
                         return Ok(Async::Ready(Some(Libp2pEvent::PeerDisconnected(peer_id))));
                     }
+                    BehaviourEvent::PeerRateLimited(peer_id) => {
+                        self.report_peer(&peer_id, PeerAction::LowToleranceError);
+                    }
                     BehaviourEvent::PeerSubscribed(peer_id, topic) => {
                         return Ok(Async::Ready(Some(Libp2pEvent::PeerSubscribed(
                             peer_id, topic,
                         ))));
                     }
+                    BehaviourEvent::PeerUnsubscribed(peer_id, topic) => {
+                        return Ok(Async::Ready(Some(Libp2pEvent::PeerUnsubscribed(
+                            peer_id, topic,
+                        ))));
+                    }
+                    BehaviourEvent::LocalEnrUpdated(enr) => {
+                        return Ok(Async::Ready(Some(Libp2pEvent::LocalEnrUpdated(enr))));
+                    }
                 },
                 Ok(Async::Ready(None)) => unreachable!("Swarm stream shouldn't end"),
                 Ok(Async::NotReady) => break,
@@ -235,16 +524,7 @@ impl Stream for Service {
             match self.peers_to_ban.poll() {
                 Ok(Async::Ready(Some(peer_id))) => {
                     let peer_id = peer_id.into_inner();
-                    Swarm::ban_peer_id(&mut self.swarm, peer_id.clone());
-                    // TODO: Correctly notify protocols of the disconnect
-                    // TODO: Also remove peer from the DHT: https://github.com/sigp/lighthouse/issues/629
-                    let dummy_connected_point = ConnectedPoint::Dialer {
-                        address: "/ip4/0.0.0.0"
-                            .parse::<Multiaddr>()
-                            .expect("valid multiaddr"),
-                    };
-                    self.swarm
-                        .inject_disconnected(&peer_id, dummy_connected_point);
+                    self.close_connection(&peer_id);
                     // inform the behaviour that the peer has been banned
                     self.swarm.peer_banned(peer_id);
                 }
@@ -255,12 +535,31 @@ impl Stream for Service {
             }
         }
 
+        // disconnect peers without banning them, by lifting the ban that closes the connection
+        // right away. A peer that was banned in the meantime stays banned.
+        loop {
+            match self.peers_to_disconnect.poll() {
+                Ok(Async::Ready(Some(peer_id))) => {
+                    let peer_id = peer_id.into_inner();
+                    self.close_connection(&peer_id);
+                    if !self.banned_peers.contains(&peer_id) {
+                        Swarm::unban_peer_id(&mut self.swarm, peer_id);
+                    }
+                }
+                Ok(Async::NotReady) | Ok(Async::Ready(None)) => break,
+                Err(e) => {
+                    warn!(self.log, "Peer disconnect queue failed"; "error" => format!("{:?}", e));
+                }
+            }
+        }
+
         // un-ban peer if it's timeout has expired
         loop {
             match self.peer_ban_timeout.poll() {
                 Ok(Async::Ready(Some(peer_id))) => {
                     let peer_id = peer_id.into_inner();
                     debug!(self.log, "Peer has been unbanned"; "peer" => format!("{:?}", peer_id));
+                    self.banned_peers.remove(&peer_id);
                     self.swarm.peer_unbanned(&peer_id);
                     Swarm::unban_peer_id(&mut self.swarm, peer_id);
                 }
@@ -335,8 +634,19 @@ fn build_transport(local_private_key: Keypair) -> Boxed<(PeerId, StreamMuxerBox)
 pub enum Libp2pEvent {
     /// An RPC response request has been received on the swarm.
     RPC(PeerId, RPCEvent),
+    /// An outbound RPC request failed, because the peer is not connected, the request timed out
+    /// or the substream failed.
+    RPCFailed {
+        peer_id: PeerId,
+        request_id: RequestId,
+        /// The name of the method the request was sent on.
+        method: String,
+        error: RPCError,
+    },
     /// Initiated the connection to a new peer.
     PeerDialed(PeerId),
+    /// A peer has connected to us, and was not refused.
+    PeerConnectedIncoming(PeerId),
     /// A peer has disconnected.
     PeerDisconnected(PeerId),
     /// Received pubsub message.
@@ -348,6 +658,10 @@ pub enum Libp2pEvent {
     },
     /// Subscribed to peer for a topic hash.
     PeerSubscribed(PeerId, TopicHash),
+    /// A peer has unsubscribed from a topic hash.
+    PeerUnsubscribed(PeerId, TopicHash),
+    /// The local ENR has been updated.
+    LocalEnrUpdated(Enr),
 }
 
 fn keypair_from_hex(hex_bytes: &str) -> error::Result<Keypair> {
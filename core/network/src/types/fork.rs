@@ -0,0 +1,160 @@
+use serde_derive::{Deserialize, Serialize};
+use ssz_derive::{Decode, Encode};
+use std::str::FromStr;
+
+/// A four byte identifier of a fork. Gossipsub topics are prefixed with the digest of the
+/// current fork, so that nodes on different forks do not share messages.
+pub type ForkDigest = [u8; 4];
+
+/// The time of a fork that has not been scheduled.
+pub const FAR_FUTURE_TIME: u64 = u64::max_value();
+
+/// The `eth2` field of the local ENR. Discovery only searches for peers with the same
+/// `fork_digest`.
+///
+/// The SSZ layout matches the eth2 `ENRForkID`. The next fork is identified by its digest, and
+/// activates at a unix timestamp in seconds.
+#[derive(Clone, Debug, Default, PartialEq, Encode, Decode)]
+pub struct EnrForkId {
+    /// The digest of the current fork.
+    pub fork_digest: ForkDigest,
+    /// The digest of the next scheduled fork, or the current digest if none is scheduled.
+    pub next_fork_digest: ForkDigest,
+    /// The activation time of the next scheduled fork, or `FAR_FUTURE_TIME` if none is scheduled.
+    pub next_fork_time: u64,
+}
+
+impl EnrForkId {
+    /// Returns the fork id at the unix time `now`, given the digest of the initial fork and the
+    /// schedule of future forks.
+    pub fn at_time(fork_digest: ForkDigest, fork_schedule: &[ScheduledFork], now: u64) -> Self {
+        let fork_digest = fork_schedule
+            .iter()
+            .filter(|fork| fork.time <= now)
+            .max_by_key(|fork| fork.time)
+            .map_or(fork_digest, |fork| fork.fork_digest);
+
+        match fork_schedule
+            .iter()
+            .filter(|fork| fork.time > now)
+            .min_by_key(|fork| fork.time)
+        {
+            Some(next_fork) => EnrForkId {
+                fork_digest,
+                next_fork_digest: next_fork.fork_digest,
+                next_fork_time: next_fork.time,
+            },
+            None => EnrForkId {
+                fork_digest,
+                next_fork_digest: fork_digest,
+                next_fork_time: FAR_FUTURE_TIME,
+            },
+        }
+    }
+}
+
+/// A fork that activates at a unix timestamp in seconds.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledFork {
+    pub time: u64,
+    pub fork_digest: ForkDigest,
+}
+
+impl FromStr for ScheduledFork {
+    type Err = String;
+
+    /// Parses a fork given as `TIME:DIGEST`, with the digest in hex.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let time = parts
+            .next()
+            .and_then(|time| time.parse::<u64>().ok())
+            .ok_or_else(|| format!("Invalid fork time: {}", s))?;
+        let fork_digest = parts
+            .next()
+            .ok_or_else(|| format!("Missing fork digest: {}", s))
+            .and_then(parse_fork_digest)?;
+        Ok(ScheduledFork { time, fork_digest })
+    }
+}
+
+/// Parses a fork digest given as four hex encoded bytes, with or without a `0x` prefix.
+pub fn parse_fork_digest(s: &str) -> Result<ForkDigest, String> {
+    let s = s.trim_start_matches("0x");
+    let bytes = hex::decode(s).map_err(|_| format!("Invalid fork digest: {}", s))?;
+    if bytes.len() != 4 {
+        return Err(format!("Fork digest must be 4 bytes: {}", s));
+    }
+    let mut fork_digest = ForkDigest::default();
+    fork_digest.copy_from_slice(&bytes);
+    Ok(fork_digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssz::{Decode, Encode};
+
+    fn fork(time: u64, digest: u8) -> ScheduledFork {
+        ScheduledFork {
+            time,
+            fork_digest: [digest; 4],
+        }
+    }
+
+    #[test]
+    fn test_parse_fork_digest() {
+        assert_eq!(parse_fork_digest("0x01020304"), Ok([1, 2, 3, 4]));
+        assert_eq!(parse_fork_digest("0a0b0c0d"), Ok([10, 11, 12, 13]));
+        assert!(parse_fork_digest("0x010203").is_err());
+        assert!(parse_fork_digest("0x0102030405").is_err());
+        assert!(parse_fork_digest("zz020304").is_err());
+    }
+
+    #[test]
+    fn test_scheduled_fork_from_str() {
+        assert_eq!("100:0x01010101".parse(), Ok(fork(100, 1)));
+        assert!("100".parse::<ScheduledFork>().is_err());
+        assert!("soon:0x01010101".parse::<ScheduledFork>().is_err());
+        assert!("100:0x0101".parse::<ScheduledFork>().is_err());
+    }
+
+    #[test]
+    fn test_enr_fork_id_at_time() {
+        let genesis = [0; 4];
+        let schedule = vec![fork(200, 2), fork(100, 1)];
+
+        let enr_fork_id = EnrForkId::at_time(genesis, &schedule, 50);
+        assert_eq!(enr_fork_id.fork_digest, genesis);
+        assert_eq!(enr_fork_id.next_fork_digest, [1; 4]);
+        assert_eq!(enr_fork_id.next_fork_time, 100);
+
+        // a fork is active from its activation time
+        let enr_fork_id = EnrForkId::at_time(genesis, &schedule, 100);
+        assert_eq!(enr_fork_id.fork_digest, [1; 4]);
+        assert_eq!(enr_fork_id.next_fork_digest, [2; 4]);
+        assert_eq!(enr_fork_id.next_fork_time, 200);
+
+        let enr_fork_id = EnrForkId::at_time(genesis, &schedule, 300);
+        assert_eq!(enr_fork_id.fork_digest, [2; 4]);
+        assert_eq!(enr_fork_id.next_fork_digest, [2; 4]);
+        assert_eq!(enr_fork_id.next_fork_time, FAR_FUTURE_TIME);
+
+        assert_eq!(
+            EnrForkId::at_time(genesis, &[], 0),
+            EnrForkId {
+                fork_digest: genesis,
+                next_fork_digest: genesis,
+                next_fork_time: FAR_FUTURE_TIME,
+            }
+        );
+    }
+
+    #[test]
+    fn test_enr_fork_id_ssz() {
+        let enr_fork_id = EnrForkId::at_time([0; 4], &[fork(100, 1)], 0);
+        let bytes = enr_fork_id.as_ssz_bytes();
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(EnrForkId::from_ssz_bytes(&bytes), Ok(enr_fork_id));
+    }
+}
@@ -1,5 +1,5 @@
 //! A collection of variables that are accessible outside of the network thread itself.
-use crate::{Enr, GossipTopic, Multiaddr, PeerId, PeerInfo};
+use crate::{BanList, Enr, GossipTopic, Multiaddr, PeerId, PeerInfo};
 use parking_lot::RwLock;
 use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicU16, Ordering};
@@ -19,10 +19,12 @@ pub struct NetworkGlobals {
     pub connected_peer_set: RwLock<HashMap<PeerId, PeerInfo>>,
     /// The current gossipsub topic subscriptions.
     pub gossipsub_subscriptions: RwLock<HashSet<GossipTopic>>,
+    /// The banned IP addresses and ranges.
+    pub ban_list: RwLock<BanList>,
 }
 
 impl NetworkGlobals {
-    pub fn new(peer_id: PeerId, tcp_port: u16, udp_port: u16) -> Self {
+    pub fn new(peer_id: PeerId, tcp_port: u16, udp_port: u16, ban_list: BanList) -> Self {
         NetworkGlobals {
             local_enr: RwLock::new(None),
             peer_id: RwLock::new(peer_id),
@@ -31,6 +33,7 @@ impl NetworkGlobals {
             listen_port_udp: AtomicU16::new(udp_port),
             connected_peer_set: RwLock::new(HashMap::new()),
             gossipsub_subscriptions: RwLock::new(HashSet::new()),
+            ban_list: RwLock::new(ban_list),
         }
     }
 
@@ -64,4 +67,22 @@ impl NetworkGlobals {
     pub fn connected_peers(&self) -> usize {
         self.connected_peer_set.read().len()
     }
+
+    /// Returns the number of connected peers that dialed us.
+    pub fn connected_inbound_peers(&self) -> usize {
+        self.connected_peer_set
+            .read()
+            .values()
+            .filter(|peer_info| peer_info.is_incoming())
+            .count()
+    }
+
+    /// Returns the number of connected peers that we dialed.
+    pub fn connected_outbound_peers(&self) -> usize {
+        self.connected_peer_set
+            .read()
+            .values()
+            .filter(|peer_info| peer_info.is_outgoing())
+            .count()
+    }
 }
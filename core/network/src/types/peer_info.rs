@@ -1,5 +1,8 @@
-//NOTE: This should be removed in favour of the PeerManager PeerInfo, once built.
+//NOTE: The score of a peer is kept by the `PeerManager`, which mirrors it here while the peer is
+// connected.
 use crate::{EnrBitfield, SubnetId};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
 
 /// Information about a given connected peer.
 #[derive(Default, Debug, Clone)]
@@ -10,6 +13,28 @@ pub struct PeerInfo {
     /// The ENR subnet bitfield of the peer. This may be determined after it's initial
     /// connection.
     pub enr_bitfield: Option<EnrBitfield>,
+    /// The sequence number of the peer's metadata that `enr_bitfield` was taken from, if it has
+    /// been requested.
+    pub meta_data_seq: Option<u64>,
+    /// The round-trip time of our last ping to the peer.
+    pub ping_latency: Option<Duration>,
+    /// When the peer last answered one of our pings.
+    pub last_seen: Option<Instant>,
+    /// The reputation score of the peer, as kept by the `PeerManager`.
+    pub score: f64,
+    /// Whether we dialed the peer or the peer dialed us.
+    pub connection_direction: Option<ConnectionDirection>,
+    /// The IP address the peer is connected from, if the connection has one.
+    pub ip_address: Option<IpAddr>,
+}
+
+/// The side that initiated the connection to a peer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionDirection {
+    /// The peer dialed us.
+    Incoming,
+    /// We dialed the peer.
+    Outgoing,
 }
 
 #[derive(Debug, Clone)]
@@ -28,9 +53,25 @@ impl PeerInfo {
         PeerInfo {
             syncing_state: None,
             enr_bitfield: None,
+            meta_data_seq: None,
+            ping_latency: None,
+            last_seen: None,
+            score: 0.0,
+            connection_direction: None,
+            ip_address: None,
         }
     }
 
+    /// Returns `true` if we dialed the peer.
+    pub fn is_outgoing(&self) -> bool {
+        self.connection_direction == Some(ConnectionDirection::Outgoing)
+    }
+
+    /// Returns `true` if the peer dialed us.
+    pub fn is_incoming(&self) -> bool {
+        self.connection_direction == Some(ConnectionDirection::Incoming)
+    }
+
     /// Returns if the peer is subscribed to a given `SubnetId`
     pub fn on_subnet(&self, subnet_id: SubnetId) -> bool {
         if let Some(bitfield) = &self.enr_bitfield {
@@ -1,14 +1,51 @@
+use crate::ForkDigest;
 use libp2p::gossipsub::Topic;
 use serde_derive::{Deserialize, Serialize};
 
+/// A gossipsub topic on a given fork. On the wire the topic name is prefixed with the hex encoded
+/// fork digest, as `/<fork_digest>/<name>`. Topic names always start with a `/`, which is added if
+/// missing.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct GossipTopic {
     topic: String,
+    fork_digest: ForkDigest,
 }
 
 impl GossipTopic {
-    pub fn new(topic: String) -> Self {
-        GossipTopic { topic }
+    pub fn new(topic: String, fork_digest: ForkDigest) -> Self {
+        let topic = if topic.starts_with('/') {
+            topic
+        } else {
+            format!("/{}", topic)
+        };
+        GossipTopic { topic, fork_digest }
+    }
+
+    /// The topic name, without the fork digest prefix.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// The fork digest of the topic.
+    pub fn digest(&mut self) -> &mut ForkDigest {
+        &mut self.fork_digest
+    }
+
+    /// Decodes a topic name as sent on the wire.
+    pub fn decode(topic: &str) -> Result<Self, String> {
+        let mut parts = topic.trim_start_matches('/').splitn(2, '/');
+        let fork_digest = parts
+            .next()
+            .and_then(|digest| hex::decode(digest).ok())
+            .filter(|digest| digest.len() == 4)
+            .ok_or_else(|| format!("Topic has no fork digest: {}", topic))?;
+        let name = parts
+            .next()
+            .ok_or_else(|| format!("Topic has no name: {}", topic))?;
+
+        let mut digest = ForkDigest::default();
+        digest.copy_from_slice(&fork_digest);
+        Ok(GossipTopic::new(name.to_string(), digest))
     }
 }
 
@@ -20,6 +57,6 @@ impl Into<Topic> for GossipTopic {
 
 impl Into<String> for GossipTopic {
     fn into(self) -> String {
-        self.topic
+        format!("/{}{}", hex::encode(self.fork_digest), self.topic)
     }
 }
@@ -44,10 +44,17 @@ pub fn cli_app<'a, 'b>() -> App<'a, 'b> {
     .arg(
         Arg::with_name("maxpeers")
             .long("maxpeers")
-            .help("The maximum number of peers.")
+            .help("The maximum number of peers. Peers over this are pruned periodically, lowest score first.")
             .default_value("10")
             .takes_value(true),
     )
+    .arg(
+        Arg::with_name("min-outbound-peers-ratio")
+            .long("min-outbound-peers-ratio")
+            .value_name("RATIO")
+            .help("The fraction of --maxpeers kept for peers we dial, between 0 and 1. Inbound connections over the rest are refused. Defaults to 0.2.")
+            .takes_value(true),
+    )
     .arg(
         Arg::with_name("boot-nodes")
             .long("boot-nodes")
@@ -69,6 +76,91 @@ pub fn cli_app<'a, 'b>() -> App<'a, 'b> {
             .value_name("STRING")
             .help("One or more comma-delimited gossipsub topics to subscribe to.")
             .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("rpc-methods")
+            .long("rpc-methods")
+            .value_name("METHOD-LIST")
+            .help("Comma-delimited RPC methods to support, as NAME[/VERSION][;OPTION]..., newest version first. See the README for the options. Defaults to hello/1.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("rpc-max-dial-negotiated")
+            .long("rpc-max-dial-negotiated")
+            .value_name("COUNT")
+            .help("The maximum number of outbound RPC substreams being negotiated at once with a peer. Further requests are queued. Defaults to 8.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("rpc-inactive-timeout")
+            .long("rpc-inactive-timeout")
+            .value_name("MS")
+            .help("The time in milliseconds after which a connection without RPC activity is closed. Defaults to 30000.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("rpc-max-inbound-substreams")
+            .long("rpc-max-inbound-substreams")
+            .value_name("COUNT")
+            .help("The maximum number of inbound RPC requests of a peer awaiting responses. Further requests are answered with an error. Defaults to 32.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("rpc-max-rate-limit-offences")
+            .long("rpc-max-rate-limit-offences")
+            .value_name("COUNT")
+            .help("The number of inbound RPC requests over the limits after which the score of a peer is lowered. Defaults to 10.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("peer-disconnect-score")
+            .long("peer-disconnect-score")
+            .value_name("SCORE")
+            .help("Peers whose score falls to or below this are disconnected. Scores range from -100 to 100. Defaults to -20.")
+            .allow_hyphen_values(true)
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("peer-ban-score")
+            .long("peer-ban-score")
+            .value_name("SCORE")
+            .help("Peers whose score falls to or below this are banned. Defaults to -50.")
+            .allow_hyphen_values(true)
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("peer-score-halflife")
+            .long("peer-score-halflife")
+            .value_name("SECONDS")
+            .help("The time over which the score of a peer decays to half its value. Defaults to 600.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("peer-ban-duration")
+            .long("peer-ban-duration")
+            .value_name("SECONDS")
+            .help("The time a peer whose score fell to the ban score is banned for. Defaults to 3600.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("status-handshake")
+            .long("status-handshake")
+            .help("Exchange statuses with every new peer over the status RPC method. Peers whose status is incompatible are disconnected, and only accepted peers are reported as connected.")
+            .takes_value(false),
+    )
+    .arg(
+        Arg::with_name("fork-digest")
+            .long("fork-digest")
+            .value_name("HEX")
+            .help("The 4 byte fork digest, in hex, that prefixes gossipsub topics and is advertised in the ENR. Defaults to 00000000.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("fork-schedule")
+            .long("fork-schedule")
+            .value_name("FORK-LIST")
+            .help("One or more comma-delimited future forks, given as TIME:HEX with TIME a unix timestamp in seconds.")
+            .takes_value(true),
     )
         .arg(
         Arg::with_name("libp2p-addresses")
@@ -77,6 +169,31 @@ pub fn cli_app<'a, 'b>() -> App<'a, 'b> {
             .help("One or more comma-delimited multiaddrs to manually connect to a libp2p peer without an ENR.")
             .takes_value(true),
         )
+    .arg(
+        Arg::with_name("topic-faults")
+            .long("topic-faults")
+            .value_name("TOPIC@FAULTS")
+            .help("Testing only. Injects faults into the messages sent on a gossipsub topic, e.g. /mothra/topic1@drop=10,delay=200,duplicate=5,reorder=20. Percentages are given from 0 to 100 and delays in milliseconds. The topic * applies to all other topics. May be given multiple times.")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+    )
+    .arg(
+        Arg::with_name("rpc-faults")
+            .long("rpc-faults")
+            .value_name("METHOD@FAULTS")
+            .help("Testing only. Injects faults into the requests and responses sent on an RPC method, given as for --topic-faults. May be given multiple times.")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+    )
+    .arg(
+        Arg::with_name("fault-seed")
+            .long("fault-seed")
+            .value_name("SEED")
+            .help("Seeds the random number generator of the fault injection, to reproduce the same faults.")
+            .takes_value(true),
+    )
     .arg(
         Arg::with_name("debug-level")
             .long("debug-level")
@@ -1,5 +1,9 @@
+use crate::fault_injection::FaultInjectionConfig;
 use clap::ArgMatches;
-use network::{unused_port, Enr, Multiaddr, NetworkConfig, DEFAULT_CLIENT_NAME};
+use network::{
+    parse_fork_digest, parse_methods, unused_port, Enr, Multiaddr, NetworkConfig, ScheduledFork,
+    DEFAULT_CLIENT_NAME,
+};
 use std::path::PathBuf;
 
 pub const DEFAULT_DEBUG_LEVEL: &str = "info";
@@ -17,6 +21,9 @@ pub struct Config {
 
     /// Network configuration
     pub network_config: NetworkConfig,
+
+    /// Faults to inject into outbound messages, for testing.
+    pub fault_injection: FaultInjectionConfig,
 }
 
 impl Default for Config {
@@ -28,6 +35,7 @@ impl Default for Config {
             client_version: format!("v{}", env!("CARGO_PKG_VERSION")),
             debug_level: DEFAULT_DEBUG_LEVEL.into(),
             network_config,
+            fault_injection: FaultInjectionConfig::default(),
         }
     }
 }
@@ -73,6 +81,19 @@ impl Config {
                 .map_err(|_| format!("Invalid number of max peers: {}", max_peers_str))?;
         }
 
+        if let Some(ratio_str) = args.value_of("min-outbound-peers-ratio") {
+            let ratio = ratio_str
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid min outbound peers ratio: {}", ratio_str))?;
+            if !(0.0..=1.0).contains(&ratio) {
+                return Err(format!(
+                    "Min outbound peers ratio must be between 0 and 1: {}",
+                    ratio_str
+                ));
+            }
+            self.network_config.min_outbound_peers_ratio = ratio;
+        }
+
         if let Some(port_str) = args.value_of("port") {
             let port = port_str
                 .parse::<u16>()
@@ -140,6 +161,105 @@ impl Config {
             self.network_config.topics = topics_str.split(',').map(|s| s.into()).collect();
         }
 
+        if let Some(rpc_methods_str) = args.value_of("rpc-methods") {
+            self.network_config.rpc_methods = parse_methods(rpc_methods_str)?;
+        }
+
+        if let Some(max_dial_str) = args.value_of("rpc-max-dial-negotiated") {
+            self.network_config.rpc_max_dial_negotiated = max_dial_str
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid RPC max dial negotiated: {}", max_dial_str))?;
+        }
+
+        if let Some(inactive_timeout_str) = args.value_of("rpc-inactive-timeout") {
+            self.network_config.rpc_inactive_timeout = inactive_timeout_str
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid RPC inactive timeout: {}", inactive_timeout_str))?;
+        }
+
+        if let Some(max_inbound_str) = args.value_of("rpc-max-inbound-substreams") {
+            self.network_config.rpc_max_inbound_substreams = max_inbound_str
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid RPC max inbound substreams: {}", max_inbound_str))?;
+        }
+
+        if let Some(max_offences_str) = args.value_of("rpc-max-rate-limit-offences") {
+            self.network_config.rpc_max_rate_limit_offences =
+                max_offences_str.parse::<u32>().map_err(|_| {
+                    format!("Invalid RPC max rate limit offences: {}", max_offences_str)
+                })?;
+        }
+
+        if let Some(score_str) = args.value_of("peer-disconnect-score") {
+            self.network_config.peer_score_disconnect_threshold = score_str
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid peer disconnect score: {}", score_str))?;
+        }
+
+        if let Some(score_str) = args.value_of("peer-ban-score") {
+            self.network_config.peer_score_ban_threshold = score_str
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid peer ban score: {}", score_str))?;
+        }
+
+        // a peer is disconnected before it is banned, so the ban score may not be above the
+        // disconnect score
+        let disconnect_score = self.network_config.peer_score_disconnect_threshold;
+        let ban_score = self.network_config.peer_score_ban_threshold;
+        if ban_score.is_nan() || disconnect_score.is_nan() || ban_score > disconnect_score {
+            return Err(format!(
+                "Peer ban score {} must not be above the peer disconnect score {}",
+                ban_score, disconnect_score
+            ));
+        }
+
+        if let Some(halflife_str) = args.value_of("peer-score-halflife") {
+            self.network_config.peer_score_halflife = halflife_str
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid peer score halflife: {}", halflife_str))?;
+        }
+
+        if let Some(duration_str) = args.value_of("peer-ban-duration") {
+            self.network_config.peer_ban_duration = duration_str
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid peer ban duration: {}", duration_str))?;
+        }
+
+        if args.is_present("status-handshake") {
+            self.network_config.status_handshake = true;
+        }
+
+        if let Some(fork_digest_str) = args.value_of("fork-digest") {
+            self.network_config.fork_digest = parse_fork_digest(fork_digest_str)?;
+        }
+
+        if let Some(fork_schedule_str) = args.value_of("fork-schedule") {
+            self.network_config.fork_schedule = fork_schedule_str
+                .split(',')
+                .map(|fork| fork.parse())
+                .collect::<Result<Vec<ScheduledFork>, _>>()?;
+        }
+
+        if let Some(seed_str) = args.value_of("fault-seed") {
+            self.fault_injection.seed = Some(
+                seed_str
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid fault seed: {}", seed_str))?,
+            );
+        }
+
+        if let Some(rules) = args.values_of("topic-faults") {
+            self.fault_injection.topics = rules
+                .map(FaultInjectionConfig::parse_rule)
+                .collect::<Result<_, _>>()?;
+        }
+
+        if let Some(rules) = args.values_of("rpc-faults") {
+            self.fault_injection.methods = rules
+                .map(FaultInjectionConfig::parse_rule)
+                .collect::<Result<_, _>>()?;
+        }
+
         if let Some(debug_level_str) = args.value_of("debug-level") {
             self.debug_level = debug_level_str
                 .parse()
@@ -160,3 +280,98 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli_app;
+
+    #[test]
+    fn test_peer_scores() {
+        let matches = cli_app().get_matches_from(vec![
+            "mothra",
+            "--peer-disconnect-score",
+            "-10",
+            "--peer-ban-score",
+            "-30",
+        ]);
+        let mut config = Config::default();
+        config.apply_cli_args(&matches).unwrap();
+        assert_eq!(config.network_config.peer_score_disconnect_threshold, -10.0);
+        assert_eq!(config.network_config.peer_score_ban_threshold, -30.0);
+
+        for (args, valid) in &[
+            // equal scores ban a peer straight away
+            (
+                vec!["--peer-disconnect-score", "-30", "--peer-ban-score", "-30"],
+                true,
+            ),
+            (vec!["--peer-ban-score", "-10"], false),
+            (vec!["--peer-disconnect-score", "-60"], false),
+            (vec!["--peer-ban-score", "NaN"], false),
+            (vec!["--peer-ban-score", "low"], false),
+        ] {
+            let matches = cli_app()
+                .get_matches_from_safe(std::iter::once(&"mothra").chain(args))
+                .unwrap();
+            let result = Config::default().apply_cli_args(&matches);
+            assert_eq!(result.is_ok(), *valid, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_rpc_methods() {
+        let matches = cli_app().get_matches_from(vec![
+            "mothra",
+            "--rpc-methods",
+            "blocks/2;streaming,blocks/1,notify;one_way",
+        ]);
+        let mut config = Config::default();
+        config.apply_cli_args(&matches).unwrap();
+        let methods = config.network_config.rpc_methods;
+        assert_eq!(methods.len(), 3);
+        assert_eq!(methods[0].name, "blocks");
+        assert_eq!(methods[0].version, "2");
+        assert!(methods[0].streaming);
+        assert!(!methods[1].streaming);
+        assert!(methods[2].one_way);
+
+        for (methods, valid) in &[
+            // the built-in methods cannot be replaced
+            ("ping", false),
+            ("hello,metadata/2", false),
+            ("goodbye;one_way", false),
+            ("status", true),
+            // whether a method is one-way is known before a version is negotiated
+            ("notify/2;one_way,notify/1", false),
+        ] {
+            let matches = cli_app().get_matches_from(vec!["mothra", "--rpc-methods", *methods]);
+            let result = Config::default().apply_cli_args(&matches);
+            assert_eq!(result.is_ok(), *valid, "{}", methods);
+        }
+    }
+
+    #[test]
+    fn test_min_outbound_peers_ratio() {
+        let matches =
+            cli_app().get_matches_from(vec!["mothra", "--min-outbound-peers-ratio", "0.5"]);
+        let mut config = Config::default();
+        config.apply_cli_args(&matches).unwrap();
+        assert_eq!(config.network_config.min_outbound_peers_ratio, 0.5);
+
+        for (ratio, valid) in &[
+            ("0", true),
+            ("1", true),
+            ("1.5", false),
+            ("-0.1", false),
+            ("NaN", false),
+        ] {
+            // a negative ratio is rejected by the argument parser
+            let result = cli_app()
+                .get_matches_from_safe(vec!["mothra", "--min-outbound-peers-ratio", *ratio])
+                .map_err(|e| e.to_string())
+                .and_then(|matches| Config::default().apply_cli_args(&matches));
+            assert_eq!(result.is_ok(), *valid, "{}", ratio);
+        }
+    }
+}
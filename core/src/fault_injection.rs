@@ -0,0 +1,315 @@
+//! Injects faults into outbound gossipsub and RPC messages, to reproduce adverse network
+//! conditions in test networks. This should never be enabled on a production network.
+use futures::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::timer::DelayQueue;
+
+/// The name of the rule that applies to all topics or methods without a rule of their own.
+pub const WILDCARD: &str = "*";
+/// The time in milliseconds that a reordered message is held back, on top of its delay.
+const REORDER_DELAY: u64 = 100;
+
+/// The faults applied to the messages of a gossipsub topic or an RPC method.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Faults {
+    /// The percentage of messages that are dropped.
+    pub drop_percentage: u8,
+    /// The delay added to every message.
+    pub delay: Duration,
+    /// The percentage of messages that are sent twice.
+    pub duplicate_percentage: u8,
+    /// The percentage of messages that are held back, so that later messages overtake them.
+    pub reorder_percentage: u8,
+}
+
+impl FromStr for Faults {
+    type Err = String;
+
+    /// Parses a comma-delimited list of faults, e.g. `drop=10,delay=200,duplicate=5,reorder=20`.
+    /// Percentages are given from 0 to 100 and the delay in milliseconds.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut faults = Faults::default();
+        for fault in s.split(',').filter(|fault| !fault.is_empty()) {
+            let mut parts = fault.splitn(2, '=');
+            let (key, value) = match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => (key, value),
+                _ => return Err(format!("Invalid fault: {}", fault)),
+            };
+            let percentage = || match value.parse::<u8>() {
+                Ok(percentage) if percentage <= 100 => Ok(percentage),
+                _ => Err(format!("Invalid percentage: {}", fault)),
+            };
+            match key {
+                "drop" => faults.drop_percentage = percentage()?,
+                "duplicate" => faults.duplicate_percentage = percentage()?,
+                "reorder" => faults.reorder_percentage = percentage()?,
+                "delay" => {
+                    faults.delay = value
+                        .parse::<u64>()
+                        .map(Duration::from_millis)
+                        .map_err(|_| format!("Invalid delay: {}", fault))?
+                }
+                _ => return Err(format!("Unknown fault: {}", key)),
+            }
+        }
+        Ok(faults)
+    }
+}
+
+/// Fault injection configuration. Faults are disabled unless a topic or method rule is given.
+#[derive(Clone, Debug, Default)]
+pub struct FaultInjectionConfig {
+    /// Seeds the random number generator, so that the same faults are injected on every run.
+    pub seed: Option<u64>,
+    /// Faults by gossipsub topic name. The `*` rule applies to all other topics.
+    pub topics: HashMap<String, Faults>,
+    /// Faults by RPC method name. The `*` rule applies to all other methods.
+    pub methods: HashMap<String, Faults>,
+}
+
+impl FaultInjectionConfig {
+    /// Parses a rule given as `NAME@FAULTS`, e.g. `/mothra/topic1@drop=10,delay=200`.
+    pub fn parse_rule(s: &str) -> Result<(String, Faults), String> {
+        let mut parts = s.rsplitn(2, '@');
+        match (parts.next(), parts.next()) {
+            (Some(faults), Some(name)) if !name.is_empty() => Ok((name.into(), faults.parse()?)),
+            _ => Err(format!("Invalid fault rule: {}", s)),
+        }
+    }
+
+    /// Returns `true` if faults are injected into any gossipsub topic.
+    pub fn has_topic_faults(&self) -> bool {
+        !self.topics.is_empty()
+    }
+}
+
+/// What a message is sent on, to look up the faults that apply to it.
+pub enum FaultTarget<'a> {
+    /// The topics of a gossipsub message. The rule of the first topic that has one applies, once
+    /// for the whole message.
+    Topics(&'a [String]),
+    Method(&'a str),
+}
+
+/// Applies the configured faults to outbound messages, holding back delayed messages until they
+/// are due.
+pub struct FaultInjector<T> {
+    config: FaultInjectionConfig,
+    rng: StdRng,
+    delayed: DelayQueue<T>,
+}
+
+impl<T: Clone> FaultInjector<T> {
+    pub fn new(config: FaultInjectionConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        FaultInjector {
+            config,
+            rng,
+            delayed: DelayQueue::new(),
+        }
+    }
+
+    pub fn config(&self) -> &FaultInjectionConfig {
+        &self.config
+    }
+
+    /// Applies the faults of `target` to a message. Returns the messages to send now, or `None`
+    /// if the message was dropped; delayed messages are returned by `poll` once they are due.
+    pub fn inject(&mut self, target: FaultTarget, message: T) -> Option<Vec<T>> {
+        let faults = match self.faults(target) {
+            Some(faults) => faults,
+            None => return Some(vec![message]),
+        };
+
+        if self.happens(faults.drop_percentage) {
+            return None;
+        }
+        let copies = if self.happens(faults.duplicate_percentage) {
+            2
+        } else {
+            1
+        };
+
+        let mut delay = faults.delay;
+        if self.happens(faults.reorder_percentage) {
+            delay += Duration::from_millis(REORDER_DELAY);
+        }
+
+        let messages = vec![message; copies];
+        if delay == Duration::from_secs(0) {
+            return Some(messages);
+        }
+        for message in messages {
+            self.delayed.insert(message, delay);
+        }
+        Some(Vec::new())
+    }
+
+    /// Sends a message that must not overtake the messages sent before it on `target`, such as
+    /// the end of a response stream. It is never dropped, duplicated or reordered, but is held
+    /// back until every earlier message that may have been delayed is due. Returns the messages
+    /// to send now, like `inject`.
+    pub fn inject_last(&mut self, target: FaultTarget, message: T) -> Vec<T> {
+        let faults = match self.faults(target) {
+            Some(faults) => faults,
+            None => return vec![message],
+        };
+
+        let mut delay = faults.delay;
+        if faults.reorder_percentage > 0 {
+            delay += Duration::from_millis(REORDER_DELAY);
+        }
+        if delay == Duration::from_secs(0) {
+            return vec![message];
+        }
+        // due a millisecond after the latest earlier message, so that it is never sent before it
+        self.delayed
+            .insert(message, delay + Duration::from_millis(1));
+        Vec::new()
+    }
+
+    /// The faults that apply to the messages of `target`, if any.
+    fn faults(&self, target: FaultTarget) -> Option<Faults> {
+        let (rules, rule) = match target {
+            FaultTarget::Topics(topics) => (
+                &self.config.topics,
+                topics
+                    .iter()
+                    .find_map(|topic| self.config.topics.get(topic)),
+            ),
+            FaultTarget::Method(method) => (&self.config.methods, self.config.methods.get(method)),
+        };
+        rule.or_else(|| rules.get(WILDCARD)).cloned()
+    }
+
+    /// Returns delayed messages that are due to be sent.
+    pub fn poll(&mut self) -> Poll<Option<T>, tokio::timer::Error> {
+        match self.delayed.poll()? {
+            Async::Ready(Some(expired)) => Ok(Async::Ready(Some(expired.into_inner()))),
+            Async::Ready(None) => Ok(Async::Ready(None)),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+
+    /// Returns `true` with the given percentage of probability.
+    fn happens(&mut self, percentage: u8) -> bool {
+        percentage > 0 && self.rng.gen_range(0, 100) < percentage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_faults_from_str() {
+        assert_eq!(
+            "drop=10,delay=200,duplicate=5,reorder=20".parse(),
+            Ok(Faults {
+                drop_percentage: 10,
+                delay: Duration::from_millis(200),
+                duplicate_percentage: 5,
+                reorder_percentage: 20,
+            })
+        );
+        assert_eq!("".parse(), Ok(Faults::default()));
+        for s in &["drop", "drop=101", "drop=-1", "delay=soon", "corrupt=10"] {
+            assert!(s.parse::<Faults>().is_err(), "{} should not parse", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        let (name, faults) = FaultInjectionConfig::parse_rule("/mothra/topic1@drop=10").unwrap();
+        assert_eq!(name, "/mothra/topic1");
+        assert_eq!(faults.drop_percentage, 10);
+
+        // the faults follow the last `@`
+        let (name, _) = FaultInjectionConfig::parse_rule("a@b@drop=10").unwrap();
+        assert_eq!(name, "a@b");
+
+        assert!(FaultInjectionConfig::parse_rule("drop=10").is_err());
+        assert!(FaultInjectionConfig::parse_rule("@drop=10").is_err());
+        assert!(FaultInjectionConfig::parse_rule("hello@drop=200").is_err());
+    }
+
+    #[test]
+    fn test_inject_by_rule() {
+        let config = FaultInjectionConfig {
+            methods: vec![
+                ("hello".to_string(), "drop=100".parse().unwrap()),
+                ("*".to_string(), "duplicate=100".parse().unwrap()),
+            ]
+            .into_iter()
+            .collect(),
+            ..FaultInjectionConfig::default()
+        };
+        let mut injector = FaultInjector::new(config);
+        assert_eq!(injector.inject(FaultTarget::Method("hello"), 1), None);
+        assert_eq!(
+            injector.inject(FaultTarget::Method("other"), 2),
+            Some(vec![2, 2])
+        );
+        // topics have no rules, so their messages are sent unchanged
+        assert_eq!(
+            injector.inject(FaultTarget::Topics(&["hello".into()]), 3),
+            Some(vec![3])
+        );
+        // the last message is never dropped
+        assert_eq!(
+            injector.inject_last(FaultTarget::Method("hello"), 4),
+            vec![4]
+        );
+    }
+
+    #[test]
+    fn test_inject_by_first_topic_rule() {
+        let config = FaultInjectionConfig {
+            topics: vec![
+                ("dropped".to_string(), "drop=100".parse().unwrap()),
+                ("*".to_string(), "duplicate=100".parse().unwrap()),
+            ]
+            .into_iter()
+            .collect(),
+            ..FaultInjectionConfig::default()
+        };
+        let mut injector = FaultInjector::new(config);
+        let topics = ["other".to_string(), "dropped".to_string()];
+        assert_eq!(injector.inject(FaultTarget::Topics(&topics), 1), None);
+        let topics = ["other".to_string()];
+        assert_eq!(
+            injector.inject(FaultTarget::Topics(&topics), 2),
+            Some(vec![2, 2])
+        );
+    }
+
+    #[test]
+    fn test_seeded_injector_is_deterministic() {
+        let run = |seed| {
+            let config = FaultInjectionConfig {
+                seed: Some(seed),
+                methods: vec![("*".to_string(), "drop=50,duplicate=50".parse().unwrap())]
+                    .into_iter()
+                    .collect(),
+                ..FaultInjectionConfig::default()
+            };
+            let mut injector = FaultInjector::<u32>::new(config);
+            (0..100)
+                .map(|i| injector.inject(FaultTarget::Method("hello"), i))
+                .collect::<Vec<_>>()
+        };
+        let faults = run(42);
+        assert_eq!(faults, run(42));
+        assert!(faults.contains(&None));
+        assert!(faults
+            .iter()
+            .any(|messages| messages.as_ref().map(Vec::len) == Some(2)));
+    }
+}
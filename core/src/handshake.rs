@@ -0,0 +1,137 @@
+//! Tracks the status handshakes with our peers. When the handshake is enabled, the dialer of a
+//! connection sends its status in a request on the status method, and the listener answers with
+//! its own. Each side asks the application whether the other's status is compatible, and a peer
+//! is only reported as connected once its status has been accepted.
+use fnv::{FnvHashMap, FnvHashSet};
+use network::{PeerId, RequestId};
+use std::mem;
+
+/// The state of the status handshakes with our peers.
+#[derive(Default)]
+pub struct Handshake {
+    /// Our current status, as supplied by the application. Handshakes wait until it is known.
+    status: Option<Vec<u8>>,
+    /// Our status requests awaiting a response, and the peers they were sent to.
+    requests: FnvHashMap<RequestId, PeerId>,
+    /// The peers whose status has been accepted.
+    accepted: FnvHashSet<PeerId>,
+    /// The peers we dialed before our status was known.
+    deferred_dials: Vec<PeerId>,
+    /// The status requests received before our status was known, with the status of the peer.
+    deferred_requests: Vec<(PeerId, RequestId, Vec<u8>)>,
+}
+
+impl Handshake {
+    /// Our current status, if the application has supplied one.
+    pub fn status(&self) -> Option<&[u8]> {
+        self.status.as_ref().map(|status| status.as_slice())
+    }
+
+    /// Replaces our status. Returns the dials and requests that were waiting for a status, so
+    /// that their handshakes can be resumed.
+    pub fn update_status(
+        &mut self,
+        status: Vec<u8>,
+    ) -> (Vec<PeerId>, Vec<(PeerId, RequestId, Vec<u8>)>) {
+        self.status = Some(status);
+        (
+            mem::replace(&mut self.deferred_dials, Vec::new()),
+            mem::replace(&mut self.deferred_requests, Vec::new()),
+        )
+    }
+
+    /// Holds back the handshake with a peer we dialed until our status is known.
+    pub fn defer_dial(&mut self, peer_id: PeerId) {
+        self.deferred_dials.push(peer_id);
+    }
+
+    /// Holds back the answer to a status request until our status is known.
+    pub fn defer_request(&mut self, peer_id: PeerId, request_id: RequestId, peer_status: Vec<u8>) {
+        self.deferred_requests
+            .push((peer_id, request_id, peer_status));
+    }
+
+    /// Records a status request sent to a peer.
+    pub fn request_sent(&mut self, request_id: RequestId, peer_id: PeerId) {
+        self.requests.insert(request_id, peer_id);
+    }
+
+    /// Removes a status request that was answered or failed, returning the peer it was sent to.
+    /// Returns `None` if `request_id` is not one of our status requests.
+    pub fn take_request(&mut self, request_id: RequestId) -> Option<PeerId> {
+        self.requests.remove(&request_id)
+    }
+
+    /// Accepts the status of a peer. Returns `true` if the peer was not accepted before.
+    pub fn accept(&mut self, peer_id: PeerId) -> bool {
+        self.accepted.insert(peer_id)
+    }
+
+    /// Rejects the status of a peer, which may have been accepted before.
+    pub fn reject(&mut self, peer_id: &PeerId) {
+        self.accepted.remove(peer_id);
+    }
+
+    /// Forgets a disconnected peer. Returns `true` if the peer had been accepted.
+    pub fn peer_disconnected(&mut self, peer_id: &PeerId) -> bool {
+        self.deferred_dials.retain(|peer| peer != peer_id);
+        self.deferred_requests
+            .retain(|(peer, _, _)| peer != peer_id);
+        self.accepted.remove(peer_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deferred_until_status() {
+        let mut handshake = Handshake::default();
+        let (dialed, requested) = (PeerId::random(), PeerId::random());
+        assert_eq!(handshake.status(), None);
+        handshake.defer_dial(dialed.clone());
+        handshake.defer_request(requested.clone(), 1, vec![1]);
+
+        let (dials, requests) = handshake.update_status(vec![0]);
+        assert_eq!(handshake.status(), Some(&[0][..]));
+        assert_eq!(dials, vec![dialed]);
+        assert_eq!(requests, vec![(requested, 1, vec![1])]);
+
+        // nothing is deferred once the status is known
+        let (dials, requests) = handshake.update_status(vec![1]);
+        assert!(dials.is_empty() && requests.is_empty());
+    }
+
+    #[test]
+    fn test_status_requests() {
+        let mut handshake = Handshake::default();
+        let peer_id = PeerId::random();
+        handshake.request_sent(1, peer_id.clone());
+        assert_eq!(handshake.take_request(2), None);
+        assert_eq!(handshake.take_request(1), Some(peer_id));
+        assert_eq!(handshake.take_request(1), None);
+    }
+
+    #[test]
+    fn test_accept_reject_disconnect() {
+        let mut handshake = Handshake::default();
+        let peer_id = PeerId::random();
+        assert!(handshake.accept(peer_id.clone()));
+        assert!(!handshake.accept(peer_id.clone()));
+        assert!(handshake.peer_disconnected(&peer_id));
+        assert!(!handshake.peer_disconnected(&peer_id));
+
+        // a rejected peer is not reported as disconnected
+        handshake.accept(peer_id.clone());
+        handshake.reject(&peer_id);
+        assert!(!handshake.peer_disconnected(&peer_id));
+
+        // the deferred handshakes of a disconnected peer are dropped
+        handshake.defer_dial(peer_id.clone());
+        handshake.defer_request(peer_id.clone(), 1, vec![1]);
+        handshake.peer_disconnected(&peer_id);
+        let (dials, requests) = handshake.update_status(vec![0]);
+        assert!(dials.is_empty() && requests.is_empty());
+    }
+}
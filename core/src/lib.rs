@@ -1,7 +1,15 @@
 pub mod cli;
 pub mod config;
 pub mod error;
+pub mod fault_injection;
+mod handshake;
 mod mothra;
+mod persisted_dht;
+mod requests;
 pub use cli::cli_app;
-pub use mothra::{gossip, rpc_request, rpc_response, Mothra, NetworkMessage};
-pub use network::NetworkGlobals;
+pub use mothra::{
+    ban_ip, disconnect, gossip, ip_bans, request, rpc_error_response, rpc_request, rpc_response,
+    rpc_stream_end, subscribe, unban_ip, unsubscribe, update_status, validate_gossip, Mothra,
+    MothraEvent, MothraHandler, NetworkMessage, RequestError, RpcErrorKind, ValidationResult,
+};
+pub use network::{Enr, GoodbyeReason, IpBan, IpRange, NetworkGlobals, RequestId};
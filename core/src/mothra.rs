@@ -1,27 +1,183 @@
+use crate::fault_injection::{FaultInjector, FaultTarget};
+use crate::handshake::Handshake;
+use crate::persisted_dht::{load_dht, persist_dht};
+use crate::requests::{RequestResult, Requests};
 use crate::{config::Config, error};
 use env_logger::Env;
+use fnv::FnvHashSet;
 use futures::prelude::*;
 use futures::Stream;
+use lru::LruCache;
 use network::Service as LibP2PService;
 use network::{
-    GossipTopic, Libp2pEvent, MessageId, NetworkConfig, NetworkGlobals, PeerId, RPCErrorResponse,
-    RPCEvent, RPCRequest, RPCResponse, Swarm,
+    next_request_id, Enr, EnrForkId, ErrorMessage, ForkDigest, GoodbyeReason, GossipTopic, IpBan,
+    IpRange, Libp2pEvent, MessageId, NetworkConfig, NetworkGlobals, PeerAction, PeerId, RPCError,
+    RPCErrorResponse, RPCEvent, RPCRequest, RPCResponse, RequestId, ScheduledFork, Swarm,
+    TopicHash, FAR_FUTURE_TIME, STATUS_METHOD,
 };
 
 use clap::ArgMatches;
-use slog::{debug, info, o, trace, warn, Drain, Level, Logger};
+use slog::{debug, error, info, o, trace, warn, Drain, Level, Logger};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::{mpsc, oneshot};
-use tokio::timer::Delay;
+use tokio::timer::{Delay, Interval};
 use tokio_compat::runtime::TaskExecutor;
 
 /// The time in seconds that a peer will be banned and prevented from reconnecting.
 const BAN_PEER_TIMEOUT: u64 = 30;
+/// The time in seconds between persisting the DHT to disk.
+const PERSIST_DHT_INTERVAL: u64 = 300;
+/// The number of received gossipsub messages whose topic is remembered for fault injection.
+const GOSSIP_TOPIC_CACHE_SIZE: usize = 10_000;
+/// The time in milliseconds allowed for Goodbye requests to be sent when shutting down.
+const SHUTDOWN_GOODBYE_TIMEOUT: u64 = 500;
 
-pub type DiscoveredPeerType = fn(peer: String);
-pub type ReceiveGossipType = fn(topic: String, data: Vec<u8>);
-pub type ReceiveRpcType = fn(method: String, req_resp: u8, peer: String, data: Vec<u8>);
+/// Handles the events produced by the network service.
+///
+/// The handler is owned by the network service and is called from its thread. All methods have a
+/// default no-op implementation, so only the events of interest need to be implemented.
+pub trait MothraHandler: Send {
+    /// Called for every network event. By default, the event is dispatched to the method
+    /// handling it below.
+    fn handle_event(&mut self, event: MothraEvent) {
+        match event {
+            MothraEvent::PeerConnected(peer) => self.discovered_peer(peer),
+            MothraEvent::PeerDisconnected(peer) => self.peer_disconnected(peer),
+            MothraEvent::PeerSubscribed { peer, topic } => self.peer_subscribed(peer, topic),
+            MothraEvent::PeerUnsubscribed { peer, topic } => self.peer_unsubscribed(peer, topic),
+            MothraEvent::GossipReceived {
+                message_id,
+                peer,
+                mut topics,
+                data,
+            } => {
+                let topic = if topics.is_empty() {
+                    String::new()
+                } else {
+                    topics.swap_remove(0)
+                };
+                self.receive_gossip(message_id, peer, topic, data)
+            }
+            MothraEvent::RpcRequest {
+                method,
+                version,
+                peer,
+                request_id,
+                data,
+            } => self.receive_rpc(method, version, 0, peer, request_id, data),
+            MothraEvent::RpcResponse {
+                method,
+                version,
+                peer,
+                request_id,
+                data,
+            } => self.receive_rpc(method, version, 1, peer, request_id, data),
+            MothraEvent::RpcStreamEnd {
+                method,
+                peer,
+                request_id,
+            } => self.receive_rpc(method, String::new(), 2, peer, request_id, Vec::new()),
+            MothraEvent::RpcErrorResponse {
+                peer,
+                request_id,
+                code,
+                message,
+            } => self.receive_rpc_error(peer, request_id, code, message),
+            MothraEvent::RpcFailed {
+                method,
+                peer,
+                request_id,
+                kind,
+                error,
+            } => self.rpc_failed(method, peer, request_id, kind, error),
+            MothraEvent::Goodbye { peer, reason } => self.receive_goodbye(peer, reason),
+            MothraEvent::LocalEnrUpdated(_) => {}
+        }
+    }
+
+    /// A connection to a new peer has been established. When the status handshake is enabled,
+    /// this is only called once the peer's status has been accepted by `check_status`.
+    fn discovered_peer(&mut self, _peer: String) {}
+
+    /// A peer has disconnected.
+    fn peer_disconnected(&mut self, _peer: String) {}
+
+    /// A peer has subscribed to a gossipsub topic.
+    fn peer_subscribed(&mut self, _peer: String, _topic: String) {}
+
+    /// A peer has unsubscribed from a gossipsub topic.
+    fn peer_unsubscribed(&mut self, _peer: String, _topic: String) {}
+
+    /// A gossipsub message has been received. The result of validating it must be reported with
+    /// `validate_gossip`.
+    fn receive_gossip(
+        &mut self,
+        _message_id: String,
+        _peer: String,
+        _topic: String,
+        _data: Vec<u8>,
+    ) {
+    }
+
+    /// An RPC request (`req_resp` is 0) or response (`req_resp` is 1) has been received on the
+    /// `version` of the method negotiated with the peer. For streaming methods, each chunk of the
+    /// response is received separately, followed by the end of the stream (`req_resp` is 2)
+    /// without data or version.
+    fn receive_rpc(
+        &mut self,
+        _method: String,
+        _version: String,
+        _req_resp: u8,
+        _peer: String,
+        _request_id: RequestId,
+        _data: Vec<u8>,
+    ) {
+    }
+
+    /// A peer has responded to an RPC request with an error. The response `code` is 2 for an
+    /// invalid request, 3 for a server error and 255 for an unknown error.
+    fn receive_rpc_error(
+        &mut self,
+        _peer: String,
+        _request_id: RequestId,
+        _code: u8,
+        _message: String,
+    ) {
+    }
+
+    /// An RPC request sent with `rpc_request` failed before it was answered, for example because
+    /// the peer disconnected or did not respond in time.
+    fn rpc_failed(
+        &mut self,
+        _method: String,
+        _peer: String,
+        _request_id: RequestId,
+        _kind: RpcErrorKind,
+        _error: String,
+    ) {
+    }
+
+    /// A peer has sent a Goodbye request before disconnecting from us.
+    fn receive_goodbye(&mut self, _peer: String, _reason: GoodbyeReason) {}
+
+    /// Decides whether the status a peer sent in the status handshake is compatible with ours.
+    /// Incompatible peers are disconnected. Only called when the handshake is enabled; every
+    /// status is accepted by default.
+    fn check_status(&mut self, _peer: String, _status: Vec<u8>) -> bool {
+        true
+    }
+}
+
+/// Forwards every network event into a channel. The receiving end is a `Stream` of
+/// `MothraEvent`s, for applications that consume network events asynchronously.
+impl MothraHandler for mpsc::UnboundedSender<MothraEvent> {
+    fn handle_event(&mut self, event: MothraEvent) {
+        // the receiver has been dropped if this fails, which leaves nothing to notify
+        let _ = self.try_send(event);
+    }
+}
 
 /// Handles communication between calling code and the `libp2p_p2p` service.
 pub struct Mothra {
@@ -35,22 +191,40 @@ pub struct Mothra {
     network_globals: Arc<NetworkGlobals>,
     /// An initial delay to update variables after the libp2p service has started.
     initial_delay: Delay,
-    /// Probability of message propagation.
-    propagation_percentage: Option<u8>,
-    discovered_peer: DiscoveredPeerType,
-    receive_gossip: ReceiveGossipType,
-    receive_rpc: ReceiveRpcType,
+    /// The directory where the DHT is persisted.
+    network_dir: PathBuf,
+    /// Periodically persists the DHT, so that it survives an unclean shutdown.
+    persist_dht_interval: Interval,
+    /// The digest of the initial fork.
+    fork_digest: ForkDigest,
+    /// Future forks and their activation times.
+    fork_schedule: Vec<ScheduledFork>,
+    /// A delay that expires when the next scheduled fork activates.
+    next_fork_update: Option<Delay>,
+    /// Injects faults into outbound messages, for testing.
+    fault_injector: FaultInjector<Outbound>,
+    /// The topics of received gossipsub messages, to inject faults when propagating them.
+    gossip_topics: LruCache<MessageId, Vec<String>>,
+    /// A delay that expires when the network service stops, once shutdown has begun. Goodbye
+    /// requests are sent to all peers in the meantime.
+    shutdown_delay: Option<Delay>,
+    /// The status handshakes with our peers, if the handshake is enabled.
+    handshake: Option<Handshake>,
+    /// The peers reported as connected, if the handshake is disabled.
+    connected_peers: FnvHashSet<PeerId>,
+    /// The requests sent with `request` that are awaiting a response.
+    requests: Requests,
+    /// The application's handler for network events.
+    handler: Box<dyn MothraHandler>,
     /// The logger for the network service.
     log: slog::Logger,
 }
 
 impl Mothra {
-    pub fn new(
+    pub fn new<H: MothraHandler + 'static>(
         mut config: Config,
         executor: &TaskExecutor,
-        discovered_peer: DiscoveredPeerType,
-        receive_gossip: ReceiveGossipType,
-        receive_rpc: ReceiveRpcType,
+        handler: H,
     ) -> error::Result<(
         Arc<NetworkGlobals>,
         mpsc::UnboundedSender<NetworkMessage>,
@@ -58,7 +232,8 @@ impl Mothra {
         slog::Logger,
     )> {
         // configure logging
-        env_logger::Builder::from_env(Env::default()).init();
+        // the logger may already be initialised by another instance in this process
+        let _ = env_logger::Builder::from_env(Env::default()).try_init();
         let decorator = slog_term::TermDecorator::new().build();
         let drain = slog_term::CompactFormat::new(decorator).build().fuse();
         let drain = slog_async::Async::new(drain).build();
@@ -78,17 +253,28 @@ impl Mothra {
         let (network_send, network_recv) = mpsc::unbounded_channel::<NetworkMessage>();
 
         // build the current enr_fork_id for adding to our local ENR
-        //TODO
-        let enr_fork_id = [0u8; 32].to_vec();
+        let fork_digest = config.network_config.fork_digest;
+        let fork_schedule = config.network_config.fork_schedule.clone();
+        let enr_fork_id = EnrForkId::at_time(fork_digest, &fork_schedule, unix_time());
+        let next_fork_update = next_fork_delay(&enr_fork_id);
+        let handshake = if config.network_config.status_handshake {
+            Some(Handshake::default())
+        } else {
+            None
+        };
+        let requests = Requests::new(&config.network_config.rpc_methods);
 
         // launch libp2p Network
-        let (network_globals, libp2p) =
+        let (network_globals, mut libp2p) =
             LibP2PService::new(&mut config.network_config, enr_fork_id, log.clone())?;
 
-        //TODO
-        // for enr in load_dht::<T::Store, T::EthSpec>(store.clone()) {
-        //     libp2p.swarm.add_enr(enr);
-        // }
+        // re-add the peers known before the last shutdown to the routing table
+        let network_dir = config.network_config.network_dir.clone();
+        let enrs = load_dht(&network_dir, &log);
+        debug!(log, "Loaded DHT from disk"; "Number of peers" => enrs.len());
+        for enr in enrs {
+            libp2p.swarm.add_enr(enr);
+        }
 
         // A delay used to initialise code after the network has started
         // This is currently used to obtain the listening addresses from the libp2p service.
@@ -101,10 +287,21 @@ impl Mothra {
             network_send: network_send.clone(),
             network_globals: network_globals.clone(),
             initial_delay,
-            propagation_percentage: config.network_config.propagation_percentage,
-            discovered_peer,
-            receive_gossip,
-            receive_rpc,
+            network_dir,
+            persist_dht_interval: Interval::new(
+                Instant::now() + Duration::from_secs(PERSIST_DHT_INTERVAL),
+                Duration::from_secs(PERSIST_DHT_INTERVAL),
+            ),
+            fork_digest,
+            fork_schedule,
+            next_fork_update,
+            fault_injector: FaultInjector::new(config.fault_injection.clone()),
+            gossip_topics: LruCache::new(GOSSIP_TOPIC_CACHE_SIZE),
+            shutdown_delay: None,
+            handshake,
+            connected_peers: FnvHashSet::default(),
+            requests,
+            handler: Box::new(handler),
             log: log.clone(),
         };
 
@@ -124,6 +321,279 @@ impl Mothra {
         config.apply_cli_args(args).unwrap();
         config
     }
+
+    /// Sends an outbound message, after applying the faults configured for its topic or method.
+    /// A dropped request fails as if it had timed out, so that whoever sent it hears back.
+    fn send_with_faults(&mut self, outbound: Outbound) {
+        let messages = match &outbound {
+            Outbound::Publish { topics, .. } => {
+                let topics = topics
+                    .iter()
+                    .map(|topic| topic.topic().to_string())
+                    .collect::<Vec<_>>();
+                self.fault_injector
+                    .inject(FaultTarget::Topics(&topics), outbound)
+                    .unwrap_or_default()
+            }
+            Outbound::Propagate { message_id, .. } => match self.gossip_topics.pop(message_id) {
+                Some(topics) => self
+                    .fault_injector
+                    .inject(FaultTarget::Topics(&topics), outbound)
+                    .unwrap_or_default(),
+                None => vec![outbound],
+            },
+            Outbound::RpcRequest(peer_id, request_id, request) => {
+                let method = request.method().to_string();
+                let (peer_id, request_id) = (peer_id.clone(), *request_id);
+                match self
+                    .fault_injector
+                    .inject(FaultTarget::Method(&method), outbound)
+                {
+                    Some(messages) => messages,
+                    None => {
+                        debug!(self.log, "Fault injection dropped RPC request"; "peer_id" => format!("{:?}", peer_id), "method" => &method);
+                        return self.request_failed(
+                            peer_id,
+                            request_id,
+                            method,
+                            RPCError::StreamTimeout,
+                        );
+                    }
+                }
+            }
+            Outbound::RpcResponse(
+                _,
+                _,
+                RPCErrorResponse::Success(RPCResponse::Message(method, _, _)),
+            ) => {
+                let method = method.clone();
+                self.fault_injector
+                    .inject(FaultTarget::Method(&method), outbound)
+                    .unwrap_or_default()
+            }
+            // the end of a stream must not overtake the chunks sent before it
+            Outbound::RpcResponse(_, _, RPCErrorResponse::StreamTermination(method)) => {
+                let method = method.clone();
+                self.fault_injector
+                    .inject_last(FaultTarget::Method(&method), outbound)
+            }
+            Outbound::RpcResponse(_, _, _) => vec![outbound],
+        };
+        for outbound in messages {
+            self.send(outbound);
+        }
+    }
+
+    /// Handles an outbound request that failed before it was answered. Status requests fail the
+    /// handshake and requests sent with `request` resolve with the error; the handler is told of
+    /// any other request.
+    fn request_failed(
+        &mut self,
+        peer_id: PeerId,
+        request_id: RequestId,
+        method: String,
+        error: RPCError,
+    ) {
+        debug!(self.log, "RPC request failed"; "peer_id" => format!("{:?}", peer_id), "method" => &method, "error" => format!("{}", error));
+        if self.take_status_request(request_id).is_some() {
+            // a peer that disconnected has nothing left to be told
+            if let RPCError::Disconnected = error {
+                return;
+            }
+            self.libp2p.goodbye_and_ban_peer(
+                peer_id,
+                GoodbyeReason::Fault,
+                Duration::from_secs(BAN_PEER_TIMEOUT),
+            );
+            return;
+        }
+        let kind = RpcErrorKind::from(&error);
+        if self.requests.fail(
+            request_id,
+            RequestError::Failed {
+                kind,
+                error: error.to_string(),
+            },
+        ) {
+            return;
+        }
+        self.handler.handle_event(MothraEvent::RpcFailed {
+            method,
+            peer: peer_id.to_string(),
+            request_id,
+            kind,
+            error: error.to_string(),
+        });
+    }
+
+    /// Sends an outbound message to the libp2p service.
+    fn send(&mut self, outbound: Outbound) {
+        match outbound {
+            Outbound::Publish { topics, message } => {
+                debug!(self.log, "Sending pubsub message"; "topics" => format!("{:?}", topics));
+                self.libp2p.swarm.publish(topics, message);
+            }
+            Outbound::Propagate {
+                propagation_source,
+                message_id,
+            } => {
+                trace!(self.log, "Propagating gossipsub message";
+                "propagation_peer" => format!("{:?}", propagation_source),
+                "message_id" => message_id.to_string(),
+                );
+                self.libp2p
+                    .swarm
+                    .propagate_message(&propagation_source, message_id);
+            }
+            Outbound::RpcRequest(peer_id, id, request) => {
+                self.requests.request_sent(id);
+                let rpc_event = RPCEvent::Request(id, request);
+                trace!(self.log, "Sending RPC"; "rpc" => format!("{:?}", rpc_event));
+                self.libp2p.swarm.send_rpc(peer_id, rpc_event);
+            }
+            Outbound::RpcResponse(peer_id, id, response) => {
+                let rpc_event = RPCEvent::Response(id, response);
+                trace!(self.log, "Sending RPC"; "rpc" => format!("{:?}", rpc_event));
+                self.libp2p.swarm.send_rpc(peer_id, rpc_event);
+            }
+        }
+    }
+
+    /// Sends a request whose responses resolve `result` instead of being passed to the handler.
+    fn send_request(
+        &mut self,
+        peer_id: PeerId,
+        method: String,
+        data: Vec<u8>,
+        result: oneshot::Sender<RequestResult>,
+    ) {
+        let request_id = next_request_id();
+        self.requests.register(request_id, &method, result);
+        let request = RPCRequest::Message(method, String::new(), data);
+        self.send_with_faults(Outbound::RpcRequest(peer_id, request_id, request));
+    }
+
+    /// Reports a new peer as connected, when the handshake is disabled. Its disconnect is only
+    /// reported if it was reported here.
+    fn peer_connected(&mut self, peer_id: PeerId) {
+        if self.connected_peers.insert(peer_id.clone()) {
+            self.handler
+                .handle_event(MothraEvent::PeerConnected(peer_id.to_string()));
+        }
+    }
+
+    /// Starts the status handshake with a peer we dialed, by sending it our status. The handshake
+    /// waits if our status is not known yet.
+    fn send_status(&mut self, peer_id: PeerId) {
+        let handshake = match self.handshake.as_mut() {
+            Some(handshake) => handshake,
+            None => return,
+        };
+        let status = match handshake.status() {
+            Some(status) => status.to_vec(),
+            None => return handshake.defer_dial(peer_id),
+        };
+        let request_id = next_request_id();
+        handshake.request_sent(request_id, peer_id.clone());
+        let request = RPCRequest::Message(STATUS_METHOD.into(), String::new(), status);
+        self.send_with_faults(Outbound::RpcRequest(peer_id, request_id, request));
+    }
+
+    /// Answers the status request of a peer with our status, then checks the peer's status. The
+    /// answer waits if our status is not known yet.
+    fn respond_status(&mut self, peer_id: PeerId, request_id: RequestId, peer_status: Vec<u8>) {
+        let handshake = match self.handshake.as_mut() {
+            Some(handshake) => handshake,
+            None => return,
+        };
+        let status = match handshake.status() {
+            Some(status) => status.to_vec(),
+            None => return handshake.defer_request(peer_id, request_id, peer_status),
+        };
+        let response = RPCErrorResponse::Success(RPCResponse::Message(
+            STATUS_METHOD.into(),
+            String::new(),
+            status,
+        ));
+        self.send_with_faults(Outbound::RpcResponse(peer_id.clone(), request_id, response));
+        self.check_status(peer_id, peer_status);
+    }
+
+    /// Asks the application whether the status of a peer is compatible with ours. A compatible
+    /// peer is reported as connected the first time, an incompatible one is disconnected.
+    fn check_status(&mut self, peer_id: PeerId, peer_status: Vec<u8>) {
+        let compatible = self.handler.check_status(peer_id.to_string(), peer_status);
+        let handshake = match self.handshake.as_mut() {
+            Some(handshake) => handshake,
+            None => return,
+        };
+        if compatible {
+            if handshake.accept(peer_id.clone()) {
+                debug!(self.log, "Peer status accepted"; "peer_id" => format!("{:?}", peer_id));
+                self.handler
+                    .handle_event(MothraEvent::PeerConnected(peer_id.to_string()));
+            }
+        } else {
+            debug!(self.log, "Peer has an incompatible status"; "peer_id" => format!("{:?}", peer_id));
+            handshake.reject(&peer_id);
+            self.libp2p.goodbye_and_ban_peer(
+                peer_id,
+                GoodbyeReason::IrrelevantNetwork,
+                Duration::from_secs(BAN_PEER_TIMEOUT),
+            );
+        }
+    }
+
+    /// Removes one of our status requests that was answered or failed, returning the peer it was
+    /// sent to. Returns `None` for any other request.
+    fn take_status_request(&mut self, request_id: RequestId) -> Option<PeerId> {
+        self.handshake
+            .as_mut()
+            .and_then(|handshake| handshake.take_request(request_id))
+    }
+
+    /// Replaces our status, and resumes the handshakes that were waiting for it.
+    fn update_status(&mut self, status: Vec<u8>) {
+        let (dials, requests) = match self.handshake.as_mut() {
+            Some(handshake) => handshake.update_status(status),
+            None => {
+                debug!(self.log, "Status handshake is disabled, ignoring status");
+                return;
+            }
+        };
+        for peer_id in dials {
+            self.send_status(peer_id);
+        }
+        for (peer_id, request_id, peer_status) in requests {
+            self.respond_status(peer_id, request_id, peer_status);
+        }
+    }
+
+    /// Stores the ENRs of the DHT in the network directory. An empty DHT is not stored, so that
+    /// the peers of a previous run are kept while we are isolated.
+    fn persist_dht(&mut self) {
+        let enrs: Vec<Enr> = self.libp2p.swarm.enr_entries().cloned().collect();
+        if enrs.is_empty() {
+            debug!(self.log, "DHT is empty, not persisting");
+            return;
+        }
+        debug!(
+            self.log,
+            "Persisting DHT to disk";
+            "Number of peers" => format!("{}", enrs.len()),
+        );
+        match persist_dht(&self.network_dir, &enrs) {
+            Err(e) => error!(
+                self.log,
+                "Failed to persist DHT";
+                "error" => format!("{:?}", e)
+            ),
+            Ok(_) => info!(
+                self.log,
+                "Saved DHT state";
+            ),
+        }
+    }
 }
 
 fn spawn_mothra(
@@ -136,7 +606,8 @@ fn spawn_mothra(
     executor.spawn(
     futures::future::poll_fn(move || -> Result<_, ()> {
 
-        let log = &mothra.log;
+        // cloned, as persisting the DHT borrows the service mutably
+        let log = &mothra.log.clone();
 
         // handles any logic which requires an initial delay
         if !mothra.initial_delay.is_elapsed() {
@@ -146,29 +617,49 @@ fn spawn_mothra(
             }
         }
 
-        // perform termination tasks when the network is being shutdown
-        if let Ok(Async::Ready(_)) | Err(_) = exit_rx.poll() {
-                    // network thread is terminating TODO
-                    // let enrs: Vec<Enr> = service.libp2p.swarm.enr_entries().cloned().collect();
-                    // debug!(
-                    //     log,
-                    //     "Persisting DHT to store";
-                    //     "Number of peers" => format!("{}", enrs.len()),
-                    // );
-                    // match persist_dht::<T::Store, T::EthSpec>(service.store.clone(), enrs) {
-                    //     Err(e) => error!(
-                    //         log,
-                    //         "Failed to persist DHT on drop";
-                    //         "error" => format!("{:?}", e)
-                    //     ),
-                    //     Ok(_) => info!(
-                    //         log,
-                    //         "Saved DHT state";
-                    //     ),
-                    // }
+        // say goodbye to all peers when the network is being shutdown
+        if mothra.shutdown_delay.is_none() {
+            if let Ok(Async::Ready(_)) | Err(_) = exit_rx.poll() {
+                let peers: Vec<PeerId> = mothra
+                    .network_globals
+                    .connected_peer_set
+                    .read()
+                    .keys()
+                    .cloned()
+                    .collect();
+                for peer_id in peers {
+                    mothra.libp2p.swarm.send_rpc(
+                        peer_id,
+                        RPCEvent::Request(0, RPCRequest::Goodbye(GoodbyeReason::ClientShutdown)),
+                    );
+                }
+                mothra.shutdown_delay = Some(Delay::new(
+                    Instant::now() + Duration::from_millis(SHUTDOWN_GOODBYE_TIMEOUT),
+                ));
+            }
+        }
+
+        // perform termination tasks once the goodbyes have been sent
+        if let Some(shutdown_delay) = mothra.shutdown_delay.as_mut() {
+            if let Ok(Async::Ready(_)) | Err(_) = shutdown_delay.poll() {
+                    // network thread is terminating
+                    mothra.persist_dht();
 
                     info!(log.clone(), "Network service shutdown");
                     return Ok(Async::Ready(()));
+            }
+        }
+
+        // periodically persist the DHT
+        loop {
+            match mothra.persist_dht_interval.poll() {
+                Ok(Async::Ready(Some(_))) => mothra.persist_dht(),
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) => break,
+                Err(e) => {
+                    warn!(log, "DHT persistence timer failed"; "error" => format!("{:?}", e));
+                    break;
+                }
+            }
         }
 
         // processes the network channel before processing the libp2p swarm
@@ -176,62 +667,107 @@ fn spawn_mothra(
             // poll the network channel
             match mothra.network_recv.poll() {
                 Ok(Async::Ready(Some(message))) => match message {
-                    NetworkMessage::RPC(peer_id, rpc_event) => {
-                        trace!(log, "Sending RPC"; "rpc" => format!("{:?}", rpc_event));
-                        mothra.libp2p.swarm.send_rpc(peer_id, rpc_event);
-                    }
+                    NetworkMessage::RPC(peer_id, rpc_event) => match rpc_event {
+                        RPCEvent::Request(id, request) => {
+                            mothra.send_with_faults(Outbound::RpcRequest(peer_id, id, request));
+                        }
+                        RPCEvent::Response(id, response @ RPCErrorResponse::Success(_))
+                        | RPCEvent::Response(
+                            id,
+                            response @ RPCErrorResponse::StreamTermination(_),
+                        ) => {
+                            mothra.send_with_faults(Outbound::RpcResponse(peer_id, id, response));
+                        }
+                        rpc_event => {
+                            trace!(log, "Sending RPC"; "rpc" => format!("{:?}", rpc_event));
+                            mothra.libp2p.swarm.send_rpc(peer_id, rpc_event);
+                        }
+                    },
                     NetworkMessage::Propagate {
                         propagation_source,
                         message_id,
                     } => {
-                        // TODO: Remove this for mainnet
-                        // randomly prevents propagation
-                        let mut should_send = true;
-                        if let Some(percentage) = mothra.propagation_percentage {
-                            // not exact percentage but close enough
-                            let rand = rand::random::<u8>() % 100;
-                            if rand > percentage {
-                                // don't propagate
-                                should_send = false;
-                            }
+                        mothra.send_with_faults(Outbound::Propagate {
+                            propagation_source,
+                            message_id,
+                        });
+                    }
+                    NetworkMessage::Validate {
+                        propagation_source,
+                        message_id,
+                        validation_result,
+                    } => match validation_result {
+                        ValidationResult::Accept => {
+                            mothra
+                                .libp2p
+                                .report_peer(&propagation_source, PeerAction::Good);
+                            mothra
+                                .network_send
+                                .try_send(NetworkMessage::Propagate {
+                                    propagation_source,
+                                    message_id,
+                                })
+                                .unwrap_or_else(|_| {
+                                    warn!(log, "Could not queue gossipsub message for propagation")
+                                });
                         }
-                        if !should_send {
-                            info!(log, "Random filter did not propagate message");
-                        } else {
-                            trace!(log, "Propagating gossipsub message";
+                        ValidationResult::Reject => {
+                            warn!(log, "Gossipsub message rejected by the application";
                             "propagation_peer" => format!("{:?}", propagation_source),
                             "message_id" => message_id.to_string(),
                             );
-                            mothra.libp2p
-                                .swarm
-                                .propagate_message(&propagation_source, message_id);
+                            mothra
+                                .libp2p
+                                .report_peer(&propagation_source, PeerAction::LowToleranceError);
                         }
-                    }
+                        ValidationResult::Ignore => {
+                            trace!(log, "Gossipsub message ignored by the application";
+                            "message_id" => message_id.to_string(),
+                            );
+                        }
+                    },
                     NetworkMessage::Publish { topics, message } => {
-                        // TODO: Remove this for mainnet
-                        // randomly prevents propagation
-                        let mut should_send = true;
-                        if let Some(percentage) = mothra.propagation_percentage {
-                            // not exact percentage but close enough
-                            let rand = rand::random::<u8>() % 100;
-                            if rand > percentage {
-                                // don't propagate
-                                should_send = false;
-                            }
+                        let fork_digest = mothra.libp2p.swarm.fork_digest();
+                        let topics = topics
+                            .into_iter()
+                            .map(|topic| GossipTopic::new(topic, fork_digest))
+                            .collect();
+                        mothra.send_with_faults(Outbound::Publish { topics, message });
+                    }
+                    NetworkMessage::Subscribe { topic, result } => {
+                        let topic = GossipTopic::new(topic, mothra.libp2p.swarm.fork_digest());
+                        let subscribed = mothra.libp2p.swarm.subscribe(topic.clone());
+                        if !subscribed {
+                            warn!(log, "Could not subscribe to topic"; "topic" => format!("{:?}", topic));
                         }
-                        if !should_send {
-                            info!(log, "Random filter did not publish messages");
-                        } else {
-                            debug!(log, "Sending pubsub message"; "topics" => format!("{:?}",topics));
-                            mothra.libp2p.swarm.publish(topics, message);
+                        let _ = result.send(subscribed);
+                    }
+                    NetworkMessage::Unsubscribe { topic, result } => {
+                        let topic = GossipTopic::new(topic, mothra.libp2p.swarm.fork_digest());
+                        let unsubscribed = mothra.libp2p.swarm.unsubscribe(topic.clone());
+                        if !unsubscribed {
+                            warn!(log, "Could not unsubscribe from topic"; "topic" => format!("{:?}", topic));
                         }
+                        let _ = result.send(unsubscribed);
                     }
-                    NetworkMessage::Disconnect { peer_id } => {
-                        mothra.libp2p.disconnect_and_ban_peer(
+                    NetworkMessage::Disconnect { peer_id, reason } => {
+                        mothra.libp2p.goodbye_and_ban_peer(
                             peer_id,
+                            reason,
                             std::time::Duration::from_secs(BAN_PEER_TIMEOUT),
                         );
                     }
+                    NetworkMessage::UpdateStatus(status) => mothra.update_status(status),
+                    NetworkMessage::BanIp(ban) => mothra.libp2p.ban_ip(ban),
+                    NetworkMessage::UnbanIp { range, result } => {
+                        let _ = result.send(mothra.libp2p.unban_ip(&range));
+                    }
+                    NetworkMessage::Request {
+                        peer_id,
+                        method,
+                        data,
+                        result,
+                    } => mothra.send_request(peer_id, method, data, result),
                 },
                 Ok(Async::NotReady) => break,
                 Ok(Async::Ready(None)) => {
@@ -245,6 +781,18 @@ fn spawn_mothra(
             }
         }
 
+        // send the messages held back by the fault injector that are now due
+        loop {
+            match mothra.fault_injector.poll() {
+                Ok(Async::Ready(Some(outbound))) => mothra.send(outbound),
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) => break,
+                Err(e) => {
+                    warn!(log, "Fault injection timer failed"; "error" => format!("{:?}", e));
+                    break;
+                }
+            }
+        }
+
         let mut peers_to_ban = Vec::<PeerId>::new();
         // poll the swarm
         loop {
@@ -253,50 +801,142 @@ fn spawn_mothra(
                     Libp2pEvent::RPC(peer_id, rpc_event) => {
                         debug!(log, "RPC Event: {:?}", rpc_event);
                         match rpc_event {
-                            RPCEvent::Request(_, request) => match request {
-                                RPCRequest::Message(data) => {
+                            RPCEvent::Request(id, request) => match request {
+                                RPCRequest::Message(method, _, data)
+                                    if method == STATUS_METHOD && mothra.handshake.is_some() =>
+                                {
+                                    mothra.respond_status(peer_id, id, data);
+                                }
+                                RPCRequest::Message(method, version, data) => {
                                     debug!(log, "RPCRequest message received: {:?}", data);
-                                    (mothra.receive_rpc)(
-                                        "".to_string(),
-                                        0,
-                                        peer_id.to_string(),
-                                        data.to_vec(),
-                                    );
+                                    mothra.handler.handle_event(MothraEvent::RpcRequest {
+                                        method,
+                                        version,
+                                        peer: peer_id.to_string(),
+                                        request_id: id,
+                                        data,
+                                    });
+                                }
+                                // answered by the network layer
+                                RPCRequest::Ping(_) | RPCRequest::MetaData => {}
+                                RPCRequest::Goodbye(reason) => {
+                                    debug!(log, "Peer sent Goodbye"; "peer_id" => format!("{:?}", peer_id), "reason" => format!("{:?}", reason));
+                                    mothra.handler.handle_event(MothraEvent::Goodbye {
+                                        peer: peer_id.to_string(),
+                                        reason,
+                                    });
+                                    peers_to_ban.push(peer_id);
                                 }
                             },
                             RPCEvent::Response(id, err_response) => match err_response {
-                                RPCErrorResponse::InvalidRequest(error) => {
-                                    warn!(log, "Peer indicated invalid request";"peer_id" => format!("{:?}", peer_id), "error" => error.as_string())
-                                }
-                                RPCErrorResponse::ServerError(error) => {
-                                    warn!(log, "Peer internal server error";"peer_id" => format!("{:?}", peer_id), "error" => error.as_string())
-                                }
-                                RPCErrorResponse::Unknown(error) => {
-                                    warn!(log, "Unknown peer error";"peer" => format!("{:?}", peer_id), "error" => error.as_string())
+                                RPCErrorResponse::InvalidRequest(ref error)
+                                | RPCErrorResponse::ServerError(ref error)
+                                | RPCErrorResponse::Unknown(ref error) => {
+                                    let code = err_response.as_u8().unwrap_or_default();
+                                    if mothra.take_status_request(id).is_some() {
+                                        warn!(log, "Peer failed the status handshake"; "peer_id" => format!("{:?}", peer_id), "code" => code, "error" => error.as_string());
+                                        mothra.libp2p.goodbye_and_ban_peer(
+                                            peer_id,
+                                            GoodbyeReason::Fault,
+                                            Duration::from_secs(BAN_PEER_TIMEOUT),
+                                        );
+                                        continue;
+                                    }
+                                    if mothra.requests.fail(
+                                        id,
+                                        RequestError::ErrorResponse {
+                                            code,
+                                            message: error.as_string(),
+                                        },
+                                    ) {
+                                        continue;
+                                    }
+                                    warn!(log, "Peer responded with an RPC error";"peer_id" => format!("{:?}", peer_id), "code" => code, "error" => error.as_string());
+                                    mothra.handler.handle_event(MothraEvent::RpcErrorResponse {
+                                        peer: peer_id.to_string(),
+                                        request_id: id,
+                                        code,
+                                        message: error.as_string(),
+                                    });
                                 }
                                 RPCErrorResponse::Success(response) => match response {
-                                    RPCResponse::Message(data) => {
+                                    RPCResponse::Message(method, _, data)
+                                        if method == STATUS_METHOD
+                                            && mothra.take_status_request(id).is_some() =>
+                                    {
+                                        mothra.check_status(peer_id, data);
+                                    }
+                                    RPCResponse::Message(method, version, data)
+                                        if mothra.requests.contains(id) =>
+                                    {
+                                        mothra.requests.response(id, method, version, data);
+                                    }
+                                    RPCResponse::Message(method, version, data) => {
                                         debug!(log, "RPCResponse message received: {:?}", data);
-                                        (mothra.receive_rpc)(
-                                            "".to_string(),
-                                            1,
-                                            peer_id.to_string(),
-                                            data.to_vec(),
-                                        );
+                                        mothra.handler.handle_event(MothraEvent::RpcResponse {
+                                            method,
+                                            version,
+                                            peer: peer_id.to_string(),
+                                            request_id: id,
+                                            data,
+                                        });
                                     }
+                                    // handled by the network layer
+                                    RPCResponse::Pong(_) | RPCResponse::MetaData(_) => {}
                                 },
+                                RPCErrorResponse::StreamTermination(_)
+                                    if mothra.requests.contains(id) =>
+                                {
+                                    mothra.requests.stream_end(id);
+                                }
+                                RPCErrorResponse::StreamTermination(method) => {
+                                    debug!(log, "RPC response stream ended"; "method" => &method);
+                                    mothra.handler.handle_event(MothraEvent::RpcStreamEnd {
+                                        method,
+                                        peer: peer_id.to_string(),
+                                        request_id: id,
+                                    });
+                                }
                             },
-                            RPCEvent::Error(_, _) => {
-                                warn!(log, "RPCEvent Error");
+                            RPCEvent::Error(_, error) => {
+                                // failures of our own requests are reported as `RPCFailed`
+                                warn!(log, "RPCEvent Error"; "peer_id" => format!("{:?}", peer_id), "error" => format!("{}", error));
                             }
                         }
                     }
+                    Libp2pEvent::RPCFailed {
+                        peer_id,
+                        request_id,
+                        method,
+                        error,
+                    } => mothra.request_failed(peer_id, request_id, method, error),
                     Libp2pEvent::PeerDialed(peer_id) => {
                         debug!(log, "Peer Dialed: {:?}", peer_id);
-                        (mothra.discovered_peer)(peer_id.to_string());
+                        if mothra.handshake.is_some() {
+                            mothra.send_status(peer_id);
+                        } else {
+                            mothra.peer_connected(peer_id);
+                        }
+                    }
+                    Libp2pEvent::PeerConnectedIncoming(peer_id) => {
+                        debug!(log, "Peer Connected: {:?}", peer_id);
+                        // with the handshake enabled, the dialer sends its status first
+                        if mothra.handshake.is_none() {
+                            mothra.peer_connected(peer_id);
+                        }
                     }
                     Libp2pEvent::PeerDisconnected(peer_id) => {
                         debug!(log, "Peer Disconnected: {:?}", peer_id);
+                        // peers that were never accepted were never reported as connected
+                        let accepted = match mothra.handshake.as_mut() {
+                            Some(handshake) => handshake.peer_disconnected(&peer_id),
+                            None => mothra.connected_peers.remove(&peer_id),
+                        };
+                        if accepted {
+                            mothra
+                                .handler
+                                .handle_event(MothraEvent::PeerDisconnected(peer_id.to_string()));
+                        }
                     }
                     Libp2pEvent::PubsubMessage {
                         id,
@@ -305,10 +945,34 @@ fn spawn_mothra(
                         message,
                     } => {
                         debug!(log, "Gossip message received from: {:?}", source);
-                        (mothra.receive_gossip)(topics[0].to_string(), message.clone());
+                        let topics = topics.iter().map(topic_name).collect::<Vec<_>>();
+                        if mothra.fault_injector.config().has_topic_faults() {
+                            mothra.gossip_topics.put(id.clone(), topics.clone());
+                        }
+                        mothra.handler.handle_event(MothraEvent::GossipReceived {
+                            message_id: id.to_string(),
+                            peer: source.to_string(),
+                            topics,
+                            data: message,
+                        });
                     }
                     Libp2pEvent::PeerSubscribed(peer_id, topic) => {
                         debug!(log, "Peer {:?} subscribed to topic: {:?}", peer_id, topic);
+                        mothra.handler.handle_event(MothraEvent::PeerSubscribed {
+                            peer: peer_id.to_string(),
+                            topic: topic_name(&topic),
+                        });
+                    }
+                    Libp2pEvent::PeerUnsubscribed(peer_id, topic) => {
+                        debug!(log, "Peer {:?} unsubscribed from topic: {:?}", peer_id, topic);
+                        mothra.handler.handle_event(MothraEvent::PeerUnsubscribed {
+                            peer: peer_id.to_string(),
+                            topic: topic_name(&topic),
+                        });
+                    }
+                    Libp2pEvent::LocalEnrUpdated(enr) => {
+                        info!(log, "Local ENR updated"; "enr" => enr.to_base64(), "seq" => enr.seq());
+                        mothra.handler.handle_event(MothraEvent::LocalEnrUpdated(enr));
                     }
                 },
                 Ok(Async::Ready(None)) => unreachable!("Stream never ends"),
@@ -325,15 +989,22 @@ fn spawn_mothra(
             );
         }
 
-        // if we have just forked, update inform the libp2p layer TODO
-        // if let Some(mut update_fork_delay) =  service.next_fork_update.take() {
-        //     if !update_fork_delay.is_elapsed() {
-        //         if let Ok(Async::Ready(_)) = update_fork_delay.poll() {
-        //                 service.libp2p.swarm.update_fork_version(service.beacon_chain.enr_fork_id());
-        //                 service.next_fork_update = next_fork_delay(&service.beacon_chain);
-        //         }
-        //     }
-        // }
+        // if we have just forked, inform the libp2p layer
+        if let Some(mut update_fork_delay) = mothra.next_fork_update.take() {
+            match update_fork_delay.poll() {
+                Ok(Async::Ready(_)) => {
+                    let enr_fork_id =
+                        EnrForkId::at_time(mothra.fork_digest, &mothra.fork_schedule, unix_time());
+                    info!(log, "Updating to the next fork"; "fork_digest" => hex::encode(enr_fork_id.fork_digest));
+                    mothra.libp2p.swarm.update_fork_version(enr_fork_id.clone());
+                    mothra.next_fork_update = next_fork_delay(&enr_fork_id);
+                }
+                Ok(Async::NotReady) => mothra.next_fork_update = Some(update_fork_delay),
+                Err(e) => {
+                    warn!(log, "Fork update timer failed"; "error" => format!("{:?}", e));
+                }
+            }
+        }
 
         Ok(Async::NotReady)
     })
@@ -343,6 +1014,31 @@ fn spawn_mothra(
     Ok(network_exit)
 }
 
+/// The current unix time in seconds.
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Returns a delay that expires when the next fork of `enr_fork_id` activates, if one is
+/// scheduled.
+fn next_fork_delay(enr_fork_id: &EnrForkId) -> Option<Delay> {
+    if enr_fork_id.next_fork_time == FAR_FUTURE_TIME {
+        return None;
+    }
+    let until_fork = enr_fork_id.next_fork_time.saturating_sub(unix_time());
+    Some(Delay::new(Instant::now() + Duration::from_secs(until_fork)))
+}
+
+/// The name of a gossipsub topic, without its fork digest prefix.
+fn topic_name(topic: &TopicHash) -> String {
+    GossipTopic::decode(topic.as_str())
+        .map(|topic| topic.topic().to_string())
+        .unwrap_or_else(|_| topic.to_string())
+}
+
 pub fn gossip(
     mut network_send: mpsc::UnboundedSender<NetworkMessage>,
     topic: String,
@@ -351,47 +1047,322 @@ pub fn gossip(
 ) {
     network_send
         .try_send(NetworkMessage::Publish {
-            topics: vec![GossipTopic::new(topic)],
+            topics: vec![topic],
             message: data,
         })
         .unwrap_or_else(|_| warn!(log, "Could not send gossip message."));
 }
 
+/// Subscribes to a gossipsub topic. The returned receiver resolves to `true` if the
+/// subscription succeeded, or `false` if we were already subscribed.
+pub fn subscribe(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    topic: String,
+    log: slog::Logger,
+) -> oneshot::Receiver<bool> {
+    let (result, result_rx) = oneshot::channel();
+    network_send
+        .try_send(NetworkMessage::Subscribe { topic, result })
+        .unwrap_or_else(|_| warn!(log, "Could not send subscription to the network service"));
+    result_rx
+}
+
+/// Unsubscribes from a gossipsub topic. The returned receiver resolves to `true` if the
+/// unsubscription succeeded, or `false` if we were not subscribed.
+pub fn unsubscribe(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    topic: String,
+    log: slog::Logger,
+) -> oneshot::Receiver<bool> {
+    let (result, result_rx) = oneshot::channel();
+    network_send
+        .try_send(NetworkMessage::Unsubscribe { topic, result })
+        .unwrap_or_else(|_| warn!(log, "Could not send unsubscription to the network service"));
+    result_rx
+}
+
+/// Parses a base58 encoded peer id, as passed to the handler.
+fn parse_peer_id(peer: &str) -> Result<PeerId, String> {
+    bs58::decode(peer)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| PeerId::from_bytes(bytes).ok())
+        .ok_or_else(|| format!("Invalid peer id: {}", peer))
+}
+
+/// Reports the validation result of a gossipsub message received by `receive_gossip`.
+///
+/// Accepted messages are propagated to our mesh peers. Rejected messages are dropped and the
+/// peer that sent them is penalised. Ignored messages are dropped. Returns an error if the peer
+/// id is invalid.
+pub fn validate_gossip(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    message_id: String,
+    peer: String,
+    validation_result: ValidationResult,
+    log: slog::Logger,
+) -> Result<(), String> {
+    let peer_id = parse_peer_id(&peer)?;
+    network_send
+        .try_send(NetworkMessage::Validate {
+            propagation_source: peer_id,
+            message_id: MessageId(message_id),
+            validation_result,
+        })
+        .unwrap_or_else(|_| warn!(log, "Could not send gossip validation result."));
+    Ok(())
+}
+
+/// Sends an RPC request to `peer` and returns the id of the outbound request. The same id is
+/// passed to `receive_rpc` when the matching response arrives, or to `rpc_failed` if the request
+/// fails. The request is sent on the newest version of `method` that the peer supports.
+///
+/// Requests of one-way methods are not answered, and their failures are not reported. Returns an
+/// error if the peer id is invalid.
 pub fn rpc_request(
     mut network_send: mpsc::UnboundedSender<NetworkMessage>,
     method: String,
     peer: String,
     data: Vec<u8>,
     log: slog::Logger,
-) {
-    // use 0 as the default request id, when an ID is not required.
-    let request_id: usize = 0;
-    let rpc_request: RPCRequest = RPCRequest::Message(data);
+) -> Result<RequestId, String> {
+    let peer_id = parse_peer_id(&peer)?;
+    let request_id = next_request_id();
+    let rpc_request: RPCRequest = RPCRequest::Message(method, String::new(), data);
     let rpc_event: RPCEvent = RPCEvent::Request(request_id, rpc_request);
-    let bytes = bs58::decode(peer.as_str()).into_vec().unwrap();
-    let peer_id = PeerId::from_bytes(bytes).map_err(|_| ()).unwrap();
     network_send
         .try_send(NetworkMessage::RPC(peer_id, rpc_event))
         .unwrap_or_else(|_| warn!(log, "Could not send RPC message to the network service"));
+    Ok(request_id)
+}
+
+/// Sends an RPC request to `peer` and returns a future that resolves to its response, one payload
+/// per chunk for streaming methods. The response is not passed to `receive_rpc`, and a failed
+/// request is not passed to `rpc_failed` or `receive_rpc_error`; the future resolves to the error
+/// instead. A request to a one-way method resolves without a response once it is sent. Returns an
+/// error if the peer id is invalid.
+pub fn request(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    method: String,
+    peer: String,
+    data: Vec<u8>,
+    log: slog::Logger,
+) -> Result<impl Future<Item = Vec<Vec<u8>>, Error = RequestError>, String> {
+    let (result, result_rx) = oneshot::channel();
+    let peer_id = parse_peer_id(&peer)?;
+    network_send
+        .try_send(NetworkMessage::Request {
+            peer_id,
+            method,
+            data,
+            result,
+        })
+        .unwrap_or_else(|_| warn!(log, "Could not send RPC request to the network service"));
+    // the network service has stopped if the result is dropped
+    Ok(result_rx.then(|result| result.unwrap_or(Err(RequestError::Shutdown))))
 }
 
+/// Responds to the inbound RPC request identified by `request_id`, as received by `receive_rpc`.
+/// The response is sent on the version of the method the request was received on. Returns an
+/// error if the peer id is invalid.
 pub fn rpc_response(
     mut network_send: mpsc::UnboundedSender<NetworkMessage>,
     method: String,
     peer: String,
+    request_id: RequestId,
     data: Vec<u8>,
     log: slog::Logger,
-) {
-    // use 0 as the default request id, when an ID is not required.
-    let request_id: usize = 0;
-    let rpc_response: RPCResponse = RPCResponse::Message(data);
+) -> Result<(), String> {
+    let rpc_response: RPCResponse = RPCResponse::Message(method, String::new(), data);
     let rpc_event: RPCEvent =
         RPCEvent::Response(request_id, RPCErrorResponse::Success(rpc_response));
-    let bytes = bs58::decode(peer.as_str()).into_vec().unwrap();
-    let peer_id = PeerId::from_bytes(bytes).map_err(|_| ()).unwrap();
+    let peer_id = parse_peer_id(&peer)?;
+    network_send
+        .try_send(NetworkMessage::RPC(peer_id, rpc_event))
+        .unwrap_or_else(|_| warn!(log, "Could not send RPC message to the network service"));
+    Ok(())
+}
+
+/// Ends the response stream of the inbound RPC request identified by `request_id`, after its
+/// chunks have been sent with `rpc_response`. Only applies to streaming methods; the stream of
+/// any other method is closed after its single response. Returns an error if the peer id is
+/// invalid.
+pub fn rpc_stream_end(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    method: String,
+    peer: String,
+    request_id: RequestId,
+    log: slog::Logger,
+) -> Result<(), String> {
+    let rpc_event: RPCEvent =
+        RPCEvent::Response(request_id, RPCErrorResponse::StreamTermination(method));
+    let peer_id = parse_peer_id(&peer)?;
+    network_send
+        .try_send(NetworkMessage::RPC(peer_id, rpc_event))
+        .unwrap_or_else(|_| warn!(log, "Could not send RPC message to the network service"));
+    Ok(())
+}
+
+/// Responds to the inbound RPC request identified by `request_id` with an error. The response
+/// `code` is 2 for an invalid request and 3 for a server error; any other code is sent as an
+/// unknown error (255). This ends the response stream of a streaming method. Returns an error if
+/// the peer id is invalid.
+pub fn rpc_error_response(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    peer: String,
+    request_id: RequestId,
+    code: u8,
+    message: String,
+    log: slog::Logger,
+) -> Result<(), String> {
+    let error_message = ErrorMessage {
+        error_message: message.into_bytes(),
+    };
+    let rpc_event: RPCEvent = RPCEvent::Response(
+        request_id,
+        RPCErrorResponse::from_error(code, error_message),
+    );
+    let peer_id = parse_peer_id(&peer)?;
     network_send
         .try_send(NetworkMessage::RPC(peer_id, rpc_event))
         .unwrap_or_else(|_| warn!(log, "Could not send RPC message to the network service"));
+    Ok(())
+}
+
+/// Sends a Goodbye request with the given reason to `peer`, then disconnects from it. The peer
+/// is banned for a short time, so that it does not reconnect immediately. Returns an error if the
+/// peer id is invalid.
+pub fn disconnect(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    peer: String,
+    reason: GoodbyeReason,
+    log: slog::Logger,
+) -> Result<(), String> {
+    let peer_id = parse_peer_id(&peer)?;
+    network_send
+        .try_send(NetworkMessage::Disconnect { peer_id, reason })
+        .unwrap_or_else(|_| warn!(log, "Could not send disconnect to the network service"));
+    Ok(())
+}
+
+/// Bans an IP address such as `10.0.0.1`, or a range in CIDR notation such as `10.0.0.0/8`, for
+/// `duration`, or forever if `duration` is `None`. Peers within it are disconnected, and are
+/// neither dialed nor accepted while the ban lasts. Bans are stored in the network directory, so
+/// they survive restarts. Returns an error if the range is invalid.
+pub fn ban_ip(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    range: String,
+    reason: String,
+    duration: Option<Duration>,
+    log: slog::Logger,
+) -> Result<(), String> {
+    let range = range.parse::<IpRange>()?;
+    network_send
+        .try_send(NetworkMessage::BanIp(IpBan::new(range, reason, duration)))
+        .unwrap_or_else(|_| warn!(log, "Could not send IP ban to the network service"));
+    Ok(())
+}
+
+/// Removes the ban of an IP address or range added with `ban_ip`. The returned receiver resolves
+/// to `true` if it was banned. Returns an error if the range is invalid.
+pub fn unban_ip(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    range: String,
+    log: slog::Logger,
+) -> Result<oneshot::Receiver<bool>, String> {
+    let range = range.parse::<IpRange>()?;
+    let (result, result_rx) = oneshot::channel();
+    network_send
+        .try_send(NetworkMessage::UnbanIp { range, result })
+        .unwrap_or_else(|_| warn!(log, "Could not send IP unban to the network service"));
+    Ok(result_rx)
+}
+
+/// The IP addresses and ranges that are currently banned.
+pub fn ip_bans(network_globals: &NetworkGlobals) -> Vec<IpBan> {
+    network_globals.ban_list.read().bans()
+}
+
+/// Supplies our current status, which is exchanged with every new peer when the status handshake
+/// is enabled. Handshakes wait until the first status is supplied, so this should be called as
+/// soon as the network has started, and again whenever the status changes.
+pub fn update_status(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    status: Vec<u8>,
+    log: slog::Logger,
+) {
+    network_send
+        .try_send(NetworkMessage::UpdateStatus(status))
+        .unwrap_or_else(|_| warn!(log, "Could not send status to the network service"));
+}
+
+/// Events produced by the network service. Peers are identified by their base58 encoded peer id.
+#[derive(Debug, Clone)]
+pub enum MothraEvent {
+    /// A connection to a new peer has been established. When the status handshake is enabled, the
+    /// peer's status has been accepted.
+    PeerConnected(String),
+    /// A peer has disconnected.
+    PeerDisconnected(String),
+    /// A peer has subscribed to a gossipsub topic.
+    PeerSubscribed { peer: String, topic: String },
+    /// A peer has unsubscribed from a gossipsub topic.
+    PeerUnsubscribed { peer: String, topic: String },
+    /// A gossipsub message has been received on one or more topics.
+    GossipReceived {
+        message_id: String,
+        /// The peer from which we received this message, not the peer that published it.
+        peer: String,
+        topics: Vec<String>,
+        data: Vec<u8>,
+    },
+    /// An RPC request has been received. Respond to it with `rpc_response`, and end the response
+    /// stream of a streaming method with `rpc_stream_end`.
+    RpcRequest {
+        method: String,
+        /// The version of the method negotiated with the peer.
+        version: String,
+        peer: String,
+        request_id: RequestId,
+        data: Vec<u8>,
+    },
+    /// A response to an RPC request sent with `rpc_request` has been received. Streaming methods
+    /// receive one event per chunk.
+    RpcResponse {
+        method: String,
+        /// The version of the method negotiated with the peer.
+        version: String,
+        peer: String,
+        request_id: RequestId,
+        data: Vec<u8>,
+    },
+    /// The response stream of an RPC request to a streaming method has ended.
+    RpcStreamEnd {
+        method: String,
+        peer: String,
+        request_id: RequestId,
+    },
+    /// A peer has responded to an RPC request sent with `rpc_request` with an error. See
+    /// `MothraHandler::receive_rpc_error` for the response codes.
+    RpcErrorResponse {
+        peer: String,
+        request_id: RequestId,
+        code: u8,
+        message: String,
+    },
+    /// An RPC request sent with `rpc_request` failed before it was answered.
+    RpcFailed {
+        method: String,
+        peer: String,
+        request_id: RequestId,
+        kind: RpcErrorKind,
+        /// A description of the error.
+        error: String,
+    },
+    /// A peer has sent a Goodbye request. It is disconnected afterwards.
+    Goodbye { peer: String, reason: GoodbyeReason },
+    /// The local ENR has been updated, for example after our external address changed.
+    LocalEnrUpdated(Enr),
 }
 
 /// Types of messages that the network service can receive.
@@ -399,9 +1370,10 @@ pub fn rpc_response(
 pub enum NetworkMessage {
     /// Send an RPC message to the libp2p service.
     RPC(PeerId, RPCEvent),
-    /// Publish a list of messages to the gossipsub protocol.
+    /// Publish a list of messages to the gossipsub protocol. Topics are given by name, and are
+    /// prefixed with the digest of the current fork.
     Publish {
-        topics: Vec<GossipTopic>,
+        topics: Vec<String>,
         message: Vec<u8>,
     },
     /// Propagate a received gossipsub message.
@@ -409,6 +1381,109 @@ pub enum NetworkMessage {
         propagation_source: PeerId,
         message_id: MessageId,
     },
-    /// Disconnect and bans a peer id.
-    Disconnect { peer_id: PeerId },
+    /// The application's verdict on a received gossipsub message.
+    Validate {
+        propagation_source: PeerId,
+        message_id: MessageId,
+        validation_result: ValidationResult,
+    },
+    /// Subscribe to a gossipsub topic. The result reports whether the subscription succeeded.
+    Subscribe {
+        topic: String,
+        result: oneshot::Sender<bool>,
+    },
+    /// Unsubscribe from a gossipsub topic. The result reports whether the unsubscription
+    /// succeeded.
+    Unsubscribe {
+        topic: String,
+        result: oneshot::Sender<bool>,
+    },
+    /// Sends a Goodbye request with the given reason to a peer, then disconnects and bans it.
+    Disconnect {
+        peer_id: PeerId,
+        reason: GoodbyeReason,
+    },
+    /// Replaces our status in the status handshake.
+    UpdateStatus(Vec<u8>),
+    /// Sends an RPC request whose responses resolve `result`.
+    Request {
+        peer_id: PeerId,
+        method: String,
+        data: Vec<u8>,
+        result: oneshot::Sender<RequestResult>,
+    },
+    /// Bans an IP address or range, and disconnects the connected peers within it.
+    BanIp(IpBan),
+    /// Removes the ban of an IP address or range. The result reports whether it was banned.
+    UnbanIp {
+        range: IpRange,
+        result: oneshot::Sender<bool>,
+    },
+}
+
+/// An outbound message that faults can be injected into.
+#[derive(Clone)]
+enum Outbound {
+    Publish {
+        topics: Vec<GossipTopic>,
+        message: Vec<u8>,
+    },
+    Propagate {
+        propagation_source: PeerId,
+        message_id: MessageId,
+    },
+    RpcRequest(PeerId, RequestId, RPCRequest),
+    RpcResponse(PeerId, RequestId, RPCErrorResponse),
+}
+
+/// The result of the application validating a received gossipsub message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValidationResult {
+    /// The message is valid and should be propagated.
+    Accept,
+    /// The message is invalid. It is dropped and the propagation source is penalised.
+    Reject,
+    /// The message is dropped without penalising the propagation source.
+    Ignore,
+}
+
+/// The reason an outbound RPC request failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RpcErrorKind {
+    /// No response was received in time.
+    Timeout = 0,
+    /// The method is not supported by the peer, or not configured locally.
+    UnsupportedProtocol = 1,
+    /// The peer is not connected, or disconnected before responding.
+    Disconnected = 2,
+    /// Reading from or writing to the substream failed.
+    Io = 3,
+    /// Any other failure, such as the peer closing the substream without responding.
+    Other = 4,
+}
+
+/// The reason a request sent with `request` was not answered with a response.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestError {
+    /// The peer responded with an error. See `MothraHandler::receive_rpc_error` for the response
+    /// codes.
+    ErrorResponse { code: u8, message: String },
+    /// The request failed before it was answered, for example because it timed out.
+    Failed { kind: RpcErrorKind, error: String },
+    /// The network service stopped before the request was answered.
+    Shutdown,
+}
+
+impl From<&RPCError> for RpcErrorKind {
+    fn from(error: &RPCError) -> Self {
+        match error {
+            RPCError::StreamTimeout => RpcErrorKind::Timeout,
+            RPCError::UnsupportedProtocol | RPCError::InvalidProtocol(_) => {
+                RpcErrorKind::UnsupportedProtocol
+            }
+            RPCError::Disconnected => RpcErrorKind::Disconnected,
+            RPCError::ReadError(_) | RPCError::IoError(_) => RpcErrorKind::Io,
+            RPCError::RateLimited(_) | RPCError::Custom(_) => RpcErrorKind::Other,
+        }
+    }
 }
@@ -0,0 +1,63 @@
+use network::{read_lines, write_atomically, Enr};
+use slog::warn;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The file in the network directory where the ENRs of the DHT are stored.
+const DHT_FILENAME: &str = "dht.dat";
+
+/// Loads the ENRs persisted by `persist_dht`. Returns an empty list if there is no stored DHT or
+/// it could not be read. ENRs that fail to decode are skipped.
+pub fn load_dht(dir: &Path, log: &slog::Logger) -> Vec<Enr> {
+    read_lines(dir, DHT_FILENAME, log)
+        .iter()
+        .filter_map(|line| match Enr::from_str(line) {
+            Ok(enr) => Some(enr),
+            Err(e) => {
+                warn!(log, "ENR from DHT file could not be decoded"; "error" => format!("{:?}", e));
+                None
+            }
+        })
+        .collect()
+}
+
+/// Stores the given ENRs in the network directory, one base64 encoded ENR per line. Any previously
+/// stored DHT is replaced.
+pub fn persist_dht(dir: &Path, enrs: &[Enr]) -> std::io::Result<()> {
+    let lines = enrs.iter().map(|enr| enr.to_base64()).collect::<Vec<_>>();
+    write_atomically(dir, DHT_FILENAME, &lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::o;
+    use std::fs;
+
+    /// The example record of EIP-778.
+    const ENR: &str = "enr:-IS4QHCYrYZbAKWCBRlAy5zzaDZXJBGkcnh4MHcBFZntXNFrdvJjX04jRzjzCBOonrkTfj499SZuOh8R33Ls8RRcy5wBgmlkgnY0gmlwhH8AAAGJc2VjcDI1NmsxoQPKY0yuDUmstAHYpMa2_oxVtw0RW_QAdpzBQA8yWM0xOIN1ZHCCdl8";
+
+    #[test]
+    fn test_dht_persist_load() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let dir = std::env::temp_dir().join(format!("mothra-dht-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        // a missing file loads as an empty DHT
+        assert!(load_dht(&dir, &log).is_empty());
+
+        let enr = Enr::from_str(ENR).unwrap();
+        persist_dht(&dir, &[enr.clone()]).unwrap();
+        let enrs = load_dht(&dir, &log);
+        assert_eq!(enrs.len(), 1);
+        assert_eq!(enrs[0].to_base64(), enr.to_base64());
+        assert!(!dir.join(format!("{}.tmp", DHT_FILENAME)).exists());
+
+        // blank lines and undecodable ENRs are skipped
+        let dht_string = format!("\n{}\n  \nnot an enr\n", enr.to_base64());
+        fs::write(dir.join(DHT_FILENAME), dht_string).unwrap();
+        assert_eq!(load_dht(&dir, &log).len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
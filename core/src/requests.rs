@@ -0,0 +1,192 @@
+//! Tracks the RPC requests sent with `request`, whose responses resolve a future instead of being
+//! passed to the handler. The responses of a streaming method are collected until the stream
+//! ends.
+use crate::mothra::RequestError;
+use fnv::{FnvHashMap, FnvHashSet};
+use network::{RPCMethod, RequestId};
+use tokio::sync::oneshot;
+
+/// The result a request sent with `request` resolves to: the response payloads, one per chunk
+/// for streaming methods.
+pub type RequestResult = Result<Vec<Vec<u8>>, RequestError>;
+
+/// A request awaiting its response.
+struct PendingRequest {
+    /// Resolves the future returned to the application.
+    result: oneshot::Sender<RequestResult>,
+    /// The responses received so far.
+    responses: Vec<Vec<u8>>,
+    /// Whether the method is not answered, so that the request resolves once it is sent.
+    one_way: bool,
+}
+
+/// The requests sent with `request` that are awaiting a response.
+pub struct Requests {
+    /// The pending requests, by request id.
+    pending: FnvHashMap<RequestId, PendingRequest>,
+    /// The name and version of the method versions that respond with a stream of chunks.
+    streaming_methods: FnvHashSet<(String, String)>,
+    /// The methods whose requests are not answered.
+    one_way_methods: FnvHashSet<String>,
+}
+
+impl Requests {
+    pub fn new(methods: &[RPCMethod]) -> Self {
+        Requests {
+            pending: FnvHashMap::default(),
+            streaming_methods: methods
+                .iter()
+                .filter(|m| m.streaming)
+                .map(|m| (m.name.clone(), m.version.clone()))
+                .collect(),
+            one_way_methods: methods
+                .iter()
+                .filter(|m| m.one_way)
+                .map(|m| m.name.clone())
+                .collect(),
+        }
+    }
+
+    /// Records a request on `method` that is about to be sent. It stays pending until it is
+    /// answered or fails, including when it is dropped before reaching the network.
+    pub fn register(
+        &mut self,
+        request_id: RequestId,
+        method: &str,
+        result: oneshot::Sender<RequestResult>,
+    ) {
+        self.pending.insert(
+            request_id,
+            PendingRequest {
+                result,
+                responses: Vec::new(),
+                one_way: self.one_way_methods.contains(method),
+            },
+        );
+    }
+
+    /// Notes that a request was handed to the network. Requests of one-way methods are not
+    /// answered, so they resolve now without a response.
+    pub fn request_sent(&mut self, request_id: RequestId) {
+        let one_way = self
+            .pending
+            .get(&request_id)
+            .map(|request| request.one_way)
+            .unwrap_or(false);
+        if one_way {
+            self.stream_end(request_id);
+        }
+    }
+
+    /// Returns `true` if `request_id` is one of our pending requests.
+    pub fn contains(&self, request_id: RequestId) -> bool {
+        self.pending.contains_key(&request_id)
+    }
+
+    /// Adds a response to a pending request, received on the negotiated `version` of `method`. A
+    /// request to a method version that does not stream is resolved by its response.
+    pub fn response(
+        &mut self,
+        request_id: RequestId,
+        method: String,
+        version: String,
+        data: Vec<u8>,
+    ) {
+        match self.pending.get_mut(&request_id) {
+            Some(request) => request.responses.push(data),
+            None => return,
+        }
+        if !self.streaming_methods.contains(&(method, version)) {
+            self.stream_end(request_id);
+        }
+    }
+
+    /// Resolves a pending request with the responses received so far.
+    pub fn stream_end(&mut self, request_id: RequestId) {
+        if let Some(request) = self.pending.remove(&request_id) {
+            let _ = request.result.send(Ok(request.responses));
+        }
+    }
+
+    /// Resolves a pending request with an error. Returns `false` if `request_id` is not one of
+    /// our pending requests.
+    pub fn fail(&mut self, request_id: RequestId, error: RequestError) -> bool {
+        match self.pending.remove(&request_id) {
+            Some(request) => {
+                let _ = request.result.send(Err(error));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::Future;
+    use network::parse_methods;
+
+    const METHODS: &str = "hello/1,blocks/2;streaming,blocks/1,notify;one_way";
+
+    #[test]
+    fn test_single_response() {
+        let mut requests = Requests::new(&parse_methods(METHODS).unwrap());
+        let (sender, result) = oneshot::channel();
+        requests.register(1, "hello", sender);
+        requests.request_sent(1);
+        assert!(requests.contains(1));
+        requests.response(1, "hello".into(), "1".into(), vec![1]);
+        assert!(!requests.contains(1));
+        assert_eq!(result.wait().unwrap(), Ok(vec![vec![1]]));
+    }
+
+    #[test]
+    fn test_streaming_responses() {
+        let mut requests = Requests::new(&parse_methods(METHODS).unwrap());
+        let (sender, result) = oneshot::channel();
+        requests.register(1, "blocks", sender);
+        requests.request_sent(1);
+        requests.response(1, "blocks".into(), "2".into(), vec![1]);
+        requests.response(1, "blocks".into(), "2".into(), vec![2]);
+        assert!(requests.contains(1));
+        requests.stream_end(1);
+        assert_eq!(result.wait().unwrap(), Ok(vec![vec![1], vec![2]]));
+
+        // the older version of the method does not stream
+        let (sender, result) = oneshot::channel();
+        requests.register(2, "blocks", sender);
+        requests.request_sent(2);
+        requests.response(2, "blocks".into(), "1".into(), vec![3]);
+        assert!(!requests.contains(2));
+        assert_eq!(result.wait().unwrap(), Ok(vec![vec![3]]));
+    }
+
+    #[test]
+    fn test_one_way_request() {
+        let mut requests = Requests::new(&parse_methods(METHODS).unwrap());
+        let (sender, result) = oneshot::channel();
+        requests.register(1, "notify", sender);
+        // a one-way request resolves once it is sent
+        assert!(requests.contains(1));
+        requests.request_sent(1);
+        assert!(!requests.contains(1));
+        assert_eq!(result.wait().unwrap(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_failed_request() {
+        let mut requests = Requests::new(&parse_methods(METHODS).unwrap());
+        let (sender, result) = oneshot::channel();
+        requests.register(1, "blocks", sender);
+        requests.request_sent(1);
+        requests.response(1, "blocks".into(), "2".into(), vec![1]);
+        assert!(requests.fail(1, RequestError::Shutdown));
+        assert!(!requests.fail(1, RequestError::Shutdown));
+        assert_eq!(result.wait().unwrap(), Err(RequestError::Shutdown));
+
+        // responses to unknown requests are ignored
+        requests.response(2, "hello".into(), "1".into(), vec![1]);
+        assert!(!requests.contains(2));
+    }
+}
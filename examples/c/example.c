@@ -20,19 +20,71 @@ void on_discovered_peer(const unsigned char* peer_utf8, int peer_length) {
     printf(",peer=%.*s\n", peer_length, peer_utf8);
 }
 
-void on_receive_gossip(const unsigned char* topic_utf8, int topic_length, unsigned char* data, int data_length) {
+void on_receive_gossip(const unsigned char* message_id_utf8, int message_id_length, const unsigned char* peer_utf8, int peer_length, const unsigned char* topic_utf8, int topic_length, unsigned char* data, int data_length) {
     printf("C: received gossip");
+    printf(",message_id=%.*s", message_id_length, message_id_utf8);
+    printf(",peer=%.*s", peer_length, peer_utf8);
     printf(",topic=%.*s", topic_length, topic_utf8);
     printf(",data=%.*s\n", data_length, data);
+    validate_gossip_message((unsigned char*)message_id_utf8, message_id_length, (unsigned char*)peer_utf8, peer_length, GOSSIP_ACCEPT);
 }
 
-void on_receive_rpc(const unsigned char* method_utf8, int method_length, int req_resp, const unsigned char* peer_utf8, int peer_length, unsigned char* data, int data_length) {
+void on_receive_rpc(const unsigned char* method_utf8, int method_length, const unsigned char* version_utf8, int version_length, int req_resp, const unsigned char* peer_utf8, int peer_length, long long request_id, unsigned char* data, int data_length) {
     printf("C: received rpc %i", req_resp);
     printf(",method=%.*s", method_length, method_utf8);
+    printf(",version=%.*s", version_length, version_utf8);
     printf(",peer=%.*s", peer_length, peer_utf8);
+    printf(",request_id=%lld", request_id);
     printf(",data=%.*s\n", data_length, data);
 }
 
+void on_peer_disconnected(const unsigned char* peer_utf8, int peer_length) {
+    printf("C: peer disconnected");
+    printf(",peer=%.*s\n", peer_length, peer_utf8);
+}
+
+void on_peer_subscribed(const unsigned char* peer_utf8, int peer_length, const unsigned char* topic_utf8, int topic_length) {
+    printf("C: peer subscribed");
+    printf(",peer=%.*s", peer_length, peer_utf8);
+    printf(",topic=%.*s\n", topic_length, topic_utf8);
+}
+
+void on_peer_unsubscribed(const unsigned char* peer_utf8, int peer_length, const unsigned char* topic_utf8, int topic_length) {
+    printf("C: peer unsubscribed");
+    printf(",peer=%.*s", peer_length, peer_utf8);
+    printf(",topic=%.*s\n", topic_length, topic_utf8);
+}
+
+void on_receive_rpc_error(const unsigned char* peer_utf8, int peer_length, long long request_id, int code, const unsigned char* message_utf8, int message_length) {
+    printf("C: received rpc error %i", code);
+    printf(",peer=%.*s", peer_length, peer_utf8);
+    printf(",request_id=%lld", request_id);
+    printf(",message=%.*s\n", message_length, message_utf8);
+}
+
+void on_rpc_failed(const unsigned char* method_utf8, int method_length, const unsigned char* peer_utf8, int peer_length, long long request_id, int kind, const unsigned char* error_utf8, int error_length) {
+    printf("C: rpc request failed %i", kind);
+    printf(",method=%.*s", method_length, method_utf8);
+    printf(",peer=%.*s", peer_length, peer_utf8);
+    printf(",request_id=%lld", request_id);
+    printf(",error=%.*s\n", error_length, error_utf8);
+}
+
+void on_receive_goodbye(const unsigned char* peer_utf8, int peer_length, long long reason) {
+    printf("C: received goodbye %lld", reason);
+    printf(",peer=%.*s\n", peer_length, peer_utf8);
+}
+
+// Peers are compatible if their status matches ours
+char* status = "mothra/1";
+
+bool on_check_status(const unsigned char* peer_utf8, int peer_length, unsigned char* status_data, int status_length) {
+    bool compatible = status_length == (int)(strlen(status)) && memcmp(status_data, status, status_length) == 0;
+    printf("C: checked status %.*s", status_length, status_data);
+    printf(",peer=%.*s,compatible=%d\n", peer_length, peer_utf8, compatible);
+    return compatible;
+}
+
 int main (int argc, char** argv) {
 
     char* client_constants[3] = {
@@ -45,7 +97,17 @@ int main (int argc, char** argv) {
         on_receive_gossip,
         on_receive_rpc
     );
+    register_peer_handlers(
+        on_peer_disconnected,
+        on_peer_subscribed,
+        on_peer_unsubscribed
+    );
+    register_rpc_error_handler(on_receive_rpc_error);
+    register_rpc_failed_handler(on_rpc_failed);
+    register_goodbye_handler(on_receive_goodbye);
+    register_status_handler(on_check_status);
     network_start((char**)client_constants,LEN(client_constants),argv,argc);
+    update_local_status((unsigned char*)status, (int)(strlen(status)));
     srand(time(NULL));
     while(1){
 #ifdef _WIN64
@@ -66,10 +66,22 @@ namespace Example
         private static GCHandle s_discoveredPeerHandle;
         private static GCHandle s_receiveGossipHandle;
         private static GCHandle s_receiveRpcHandle;
+        private static GCHandle s_peerDisconnectedHandle;
+        private static GCHandle s_peerSubscribedHandle;
+        private static GCHandle s_receiveRpcErrorHandle;
+        private static GCHandle s_rpcFailedHandle;
+        private static GCHandle s_receiveGoodbyeHandle;
+        private static GCHandle s_checkStatusHandle;
 
         private static Mothra.DiscoveredPeer? s_discoveredPeer;
         private static Mothra.ReceiveGossip? s_receiveGossip;
         private static Mothra.ReceiveRpc? s_receiveRpc;
+        private static Mothra.PeerDisconnected? s_peerDisconnected;
+        private static Mothra.PeerTopic? s_peerSubscribed;
+        private static Mothra.ReceiveRpcError? s_receiveRpcError;
+        private static Mothra.RpcFailed? s_rpcFailed;
+        private static Mothra.ReceiveGoodbye? s_receiveGoodbye;
+        private static Mothra.CheckStatus? s_checkStatus;
 
         private static GCHandle s_args;
         
@@ -136,12 +148,44 @@ namespace Example
 
             //MothraInterop.RegisterHandlers(discoveredPeer, receiveGossip, receiveRpc);
             Mothra.RegisterHandlers(s_discoveredPeer, s_receiveGossip, s_receiveRpc);
+
+            s_peerDisconnected = new Mothra.PeerDisconnected(s_handlers.OnPeerDisconnected);
+            s_peerSubscribed = new Mothra.PeerTopic(s_handlers.OnPeerSubscribed);
+            s_peerDisconnectedHandle = GCHandle.Alloc(s_peerDisconnected);
+            s_peerSubscribedHandle = GCHandle.Alloc(s_peerSubscribed);
+            Mothra.RegisterPeerHandlers(s_peerDisconnected, s_peerSubscribed, null);
+
+            s_receiveRpcError = new Mothra.ReceiveRpcError(s_handlers.OnReceiveRpcError);
+            s_receiveRpcErrorHandle = GCHandle.Alloc(s_receiveRpcError);
+            Mothra.RegisterRpcErrorHandler(s_receiveRpcError);
+
+            s_rpcFailed = new Mothra.RpcFailed(s_handlers.OnRpcFailed);
+            s_rpcFailedHandle = GCHandle.Alloc(s_rpcFailed);
+            Mothra.RegisterRpcFailedHandler(s_rpcFailed);
+
+            s_receiveGoodbye = new Mothra.ReceiveGoodbye(s_handlers.OnReceiveGoodbye);
+            s_receiveGoodbyeHandle = GCHandle.Alloc(s_receiveGoodbye);
+            Mothra.RegisterGoodbyeHandler(s_receiveGoodbye);
+
+            s_checkStatus = new Mothra.CheckStatus(s_handlers.OnCheckStatus);
+            s_checkStatusHandle = GCHandle.Alloc(s_checkStatus);
+            Mothra.RegisterStatusHandler(s_checkStatus);
             Thread.Sleep(1000);
             //MothraInterop.RegisterHandlers(discoveredPeerPtr, receiveGossipPtr, receiveRpcPtr);
             // MothraInterop.RegisterHandlers(s_discoveredPeerPtr, s_receiveGossipPtr, s_receiveRpcPtr);
 
             s_args = GCHandle.Alloc(args);
             Mothra.Start(clientConstants, clientConstants.Length, args, args.Length);
+            UpdateStatus(Handlers.Status);
+        }
+
+        public static unsafe void UpdateStatus(string status)
+        {
+            byte[] statusUtf8 = Encoding.UTF8.GetBytes(status);
+            fixed (byte* statusUtf8Ptr = statusUtf8)
+            {
+                Mothra.UpdateStatus(statusUtf8Ptr, statusUtf8.Length);
+            }
         }
 
         public static unsafe void SendGossip(string topic, ReadOnlySpan<byte> data)
@@ -157,6 +201,9 @@ namespace Example
 
     public class Handlers
     {
+        // Peers are compatible if their status matches ours
+        public const string Status = "mothra/1";
+
         public unsafe void OnDiscoveredPeer(byte* peerUtf8, int peerLength)
         {
             Console.Write("dotnet: peer");
@@ -164,16 +211,61 @@ namespace Example
             Console.WriteLine($" discovered {peer}");
         }
 
-        public unsafe void OnReceiveGossip(byte* topicUtf8, int topicLength, byte* data, int dataLength)
+        public unsafe void OnPeerDisconnected(byte* peerUtf8, int peerLength)
+        {
+            string peer = new String((sbyte*)peerUtf8, 0, peerLength, Encoding.UTF8);
+            Console.WriteLine($"dotnet: peer disconnected {peer}");
+        }
+
+        public unsafe void OnPeerSubscribed(byte* peerUtf8, int peerLength, byte* topicUtf8, int topicLength)
+        {
+            string peer = new String((sbyte*)peerUtf8, 0, peerLength, Encoding.UTF8);
+            string topic = new String((sbyte*)topicUtf8, 0, topicLength, Encoding.UTF8);
+            Console.WriteLine($"dotnet: peer {peer} subscribed to {topic}");
+        }
+
+        public unsafe void OnReceiveRpcError(byte* peerUtf8, int peerLength, long requestId, int code, byte* messageUtf8, int messageLength)
+        {
+            string peer = new String((sbyte*)peerUtf8, 0, peerLength, Encoding.UTF8);
+            string message = new String((sbyte*)messageUtf8, 0, messageLength, Encoding.UTF8);
+            Console.WriteLine($"dotnet: rpc request {requestId} to peer {peer} failed with error {code}: {message}");
+        }
+
+        public unsafe void OnRpcFailed(byte* methodUtf8, int methodLength, byte* peerUtf8, int peerLength, long requestId, int kind, byte* errorUtf8, int errorLength)
+        {
+            string method = new String((sbyte*)methodUtf8, 0, methodLength, Encoding.UTF8);
+            string peer = new String((sbyte*)peerUtf8, 0, peerLength, Encoding.UTF8);
+            string error = new String((sbyte*)errorUtf8, 0, errorLength, Encoding.UTF8);
+            Console.WriteLine($"dotnet: {method} request {requestId} to peer {peer} failed ({kind}): {error}");
+        }
+
+        public unsafe void OnReceiveGoodbye(byte* peerUtf8, int peerLength, long reason)
+        {
+            string peer = new String((sbyte*)peerUtf8, 0, peerLength, Encoding.UTF8);
+            Console.WriteLine($"dotnet: peer {peer} said goodbye, reason {reason}");
+        }
+
+        public unsafe bool OnCheckStatus(byte* peerUtf8, int peerLength, byte* status, int statusLength)
+        {
+            string peer = new String((sbyte*)peerUtf8, 0, peerLength, Encoding.UTF8);
+            string peerStatus = new String((sbyte*)status, 0, statusLength, Encoding.UTF8);
+            bool compatible = peerStatus == Status;
+            Console.WriteLine($"dotnet: peer {peer} has status {peerStatus}, compatible={compatible}");
+            return compatible;
+        }
+
+        public unsafe void OnReceiveGossip(byte* messageIdUtf8, int messageIdLength, byte* peerUtf8, int peerLength,
+            byte* topicUtf8, int topicLength, byte* data, int dataLength)
         {
             Console.Write("dotnet: receive");
             string topic = new String((sbyte*)topicUtf8, 0, topicLength, Encoding.UTF8);
             string dataString = new String((sbyte*)data, 0, dataLength, Encoding.UTF8);
             Console.WriteLine($" gossip={topic},data={dataString}");
+            Mothra.ValidateGossip(messageIdUtf8, messageIdLength, peerUtf8, peerLength, Mothra.GossipAccept);
         }
 
-        public unsafe void OnReceiveRpc(byte* methodUtf8, int methodLength, int requestResponseFlag, byte* peerUtf8,
-            int peerLength, byte* data, int dataLength)
+        public unsafe void OnReceiveRpc(byte* methodUtf8, int methodLength, byte* versionUtf8, int versionLength,
+            int requestResponseFlag, byte* peerUtf8, int peerLength, long requestId, byte* data, int dataLength)
         {
             // Nothing
         }
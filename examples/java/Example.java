@@ -7,6 +7,9 @@ import java.util.concurrent.Executors;
 import p2p.mothra.Mothra;
 
 public class Example {
+    // Peers are compatible if their status matches ours
+    private static final String STATUS = "mothra/1";
+
     public static void main(String[] args) throws InterruptedException {
         String[] clientConstants = {
             "java-example",
@@ -20,16 +23,32 @@ public class Example {
             Mothra.DiscoveryMessage = Example::printDiscoveryMessage;
             Mothra.ReceivedGossipMessage = Example::printGossipMessage;
             Mothra.ReceivedRPCMessage = Example::printRPCMessage;
+            Mothra.PeerDisconnectedMessage = Example::printPeerDisconnected;
+            Mothra.PeerSubscribedMessage = Example::printPeerSubscribed;
+            Mothra.PeerUnsubscribedMessage = Example::printPeerUnsubscribed;
+            Mothra.ReceivedRPCErrorMessage = Example::printRPCError;
+            Mothra.RPCFailedMessage = Example::printRPCFailed;
+            Mothra.ReceivedGoodbyeMessage = Example::printGoodbye;
+            Mothra.StatusCheck = Example::checkStatus;
             Mothra.Init();
             Mothra.Start(clientConstants, processed_args);
+            Mothra.UpdateStatus(STATUS.getBytes());
 
         };
         Executors.newSingleThreadExecutor().execute(run);
         Scanner scanner = new Scanner(System.in);
         while(true){
-            System.out.print("Select RPC or GOSSIP: \n");
+            System.out.print("Select RPC, GOSSIP, SUBSCRIBE or UNSUBSCRIBE: \n");
             String messageType = scanner.next();
-            if(messageType.equals("GOSSIP")){
+            if(messageType.equals("SUBSCRIBE")){
+                System.out.print("Enter a topic to SUBSCRIBE to: ");
+                String topic = scanner.next();
+                System.out.println("Java: subscribed=" + Mothra.Subscribe(topic.getBytes()));
+            } else if(messageType.equals("UNSUBSCRIBE")){
+                System.out.print("Enter a topic to UNSUBSCRIBE from: ");
+                String topic = scanner.next();
+                System.out.println("Java: unsubscribed=" + Mothra.Unsubscribe(topic.getBytes()));
+            } else if(messageType.equals("GOSSIP")){
                 System.out.print("Enter a message to GOSSIP: ");
                 String message = scanner.next();
                 Mothra.SendGossip("/mothra/topic1".getBytes(),message.getBytes());
@@ -38,9 +57,15 @@ public class Example {
                 int req_resp = Integer.parseInt(scanner.next());
                 System.out.print("Enter a Peer: ");
                 String peer = scanner.next();
+                long request_id = 0;
+                if(req_resp == 1){
+                    System.out.print("Enter the Request Id: ");
+                    request_id = Long.parseLong(scanner.next());
+                }
                 System.out.print("Enter a message: ");
                 String message = scanner.next();
-                Mothra.SendRPC("HELLO".getBytes(),req_resp,peer.getBytes(),message.getBytes());
+                request_id = Mothra.SendRPC("hello".getBytes(),req_resp,peer.getBytes(),request_id,message.getBytes());
+                System.out.println("Java: rpc sent with request id " + request_id);
             }
         }
 
@@ -51,13 +76,50 @@ public class Example {
         return true;
     }
 
-    public static Boolean printGossipMessage(String topic, byte[] message){
-        System.out.println("Java: received a gossip message. " + topic + ":" + new String(message));
+    public static Boolean printPeerDisconnected(String peer){
+        System.out.println("Java: peer disconnected " + peer);
+        return true;
+    }
+
+    public static Boolean printPeerSubscribed(String peer, String topic){
+        System.out.println("Java: peer " + peer + " subscribed to " + topic);
         return true;
     }
 
-    public static Boolean printRPCMessage(String method, Integer req_resp, String peer, byte[] message){
-        System.out.println("Java: rpc method  " + method + ":" + req_resp + " was invoked by peer: " + peer + " with message: " + new String(message));
+    public static Boolean printPeerUnsubscribed(String peer, String topic){
+        System.out.println("Java: peer " + peer + " unsubscribed from " + topic);
         return true;
     }
+
+    public static Boolean printGossipMessage(String message_id, String peer, String topic, byte[] message){
+        System.out.println("Java: received a gossip message " + message_id + " from peer " + peer + ". " + topic + ":" + new String(message));
+        Mothra.ValidateGossip(message_id.getBytes(), peer.getBytes(), Mothra.GOSSIP_ACCEPT);
+        return true;
+    }
+
+    public static Boolean printRPCMessage(String method, String version, Integer req_resp, String peer, Long request_id, byte[] message){
+        System.out.println("Java: rpc method  " + method + "/" + version + ":" + req_resp + " was invoked by peer: " + peer + " with request id: " + request_id + " and message: " + new String(message));
+        return true;
+    }
+
+    public static Boolean printRPCError(String peer, Long request_id, Integer code, String message){
+        System.out.println("Java: rpc request " + request_id + " to peer " + peer + " failed with error " + code + ": " + message);
+        return true;
+    }
+
+    public static Boolean printRPCFailed(String method, String peer, Long request_id, Integer kind, String error){
+        System.out.println("Java: " + method + " request " + request_id + " to peer " + peer + " failed (" + kind + "): " + error);
+        return true;
+    }
+
+    public static Boolean printGoodbye(String peer, Long reason){
+        System.out.println("Java: peer " + peer + " said goodbye, reason " + reason);
+        return true;
+    }
+
+    public static Boolean checkStatus(String peer, byte[] status){
+        boolean compatible = STATUS.equals(new String(status));
+        System.out.println("Java: peer " + peer + " has status " + new String(status) + ", compatible=" + compatible);
+        return compatible;
+    }
 }
\ No newline at end of file
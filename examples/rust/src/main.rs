@@ -1,10 +1,15 @@
 extern crate target_info;
 use clap::{App, AppSettings, Arg, ArgMatches};
-use mothra::{cli_app, gossip, Mothra};
+use mothra::{
+    cli_app, gossip, update_status, GoodbyeReason, Mothra, MothraHandler, RequestId, RpcErrorKind,
+};
 use slog::{debug, info, o, warn, Drain};
 use std::{thread, time};
 use tokio_compat::runtime::Runtime;
 
+/// Peers are compatible if their status matches ours.
+const STATUS: &[u8] = b"mothra/1";
+
 fn main() {
     let start = time::Instant::now();
     // Parse the CLI parameters.
@@ -37,14 +42,13 @@ fn main() {
         Some("rust-example/libp2p".into()),
         &matches.subcommand_matches("mothra").unwrap(),
     );
-    let (network_globals, network_send, network_exit, network_logger) = Mothra::new(
-        config,
-        &executor,
-        on_discovered_peer,
-        on_receive_gossip,
-        on_receive_rpc,
-    )
-    .unwrap();
+    let (network_globals, network_send, network_exit, network_logger) =
+        Mothra::new(config, &executor, Handler::default()).unwrap();
+    update_status(
+        network_send.clone(),
+        STATUS.to_vec(),
+        network_logger.clone(),
+    );
 
     let dur = time::Duration::from_secs(5);
     loop {
@@ -57,21 +61,96 @@ fn main() {
     }
 }
 
-fn on_discovered_peer(peer: String) {
-    println!("Rust: discovered peer");
-    println!("peer={:?}", peer);
+/// Prints the network events, keeping count of the peers and messages seen so far.
+#[derive(Default)]
+struct Handler {
+    peers: usize,
+    gossip_messages: usize,
+    rpc_messages: usize,
 }
 
-fn on_receive_gossip(topic: String, data: Vec<u8>) {
-    println!("Rust: received gossip");
-    println!("topic={:?}", topic);
-    println!("data={:?}", String::from_utf8_lossy(&data));
-}
+impl MothraHandler for Handler {
+    fn discovered_peer(&mut self, peer: String) {
+        self.peers += 1;
+        println!("Rust: discovered peer #{}", self.peers);
+        println!("peer={:?}", peer);
+    }
 
-fn on_receive_rpc(method: String, req_resp: u8, peer: String, data: Vec<u8>) {
-    println!("Rust: received rpc");
-    println!("method={:?}", method);
-    println!("req_resp={:?}", req_resp);
-    println!("peer={:?}", peer);
-    println!("data={:?}", String::from_utf8_lossy(&data));
+    fn peer_disconnected(&mut self, peer: String) {
+        self.peers = self.peers.saturating_sub(1);
+        println!("Rust: peer disconnected, {} remaining", self.peers);
+        println!("peer={:?}", peer);
+    }
+
+    fn receive_gossip(&mut self, message_id: String, peer: String, topic: String, data: Vec<u8>) {
+        self.gossip_messages += 1;
+        println!("Rust: received gossip #{}", self.gossip_messages);
+        println!("message_id={:?}", message_id);
+        println!("peer={:?}", peer);
+        println!("topic={:?}", topic);
+        println!("data={:?}", String::from_utf8_lossy(&data));
+    }
+
+    fn receive_rpc(
+        &mut self,
+        method: String,
+        version: String,
+        req_resp: u8,
+        peer: String,
+        request_id: RequestId,
+        data: Vec<u8>,
+    ) {
+        self.rpc_messages += 1;
+        println!("Rust: received rpc #{}", self.rpc_messages);
+        println!("method={:?}", method);
+        println!("version={:?}", version);
+        println!("req_resp={:?}", req_resp);
+        println!("peer={:?}", peer);
+        println!("request_id={:?}", request_id);
+        println!("data={:?}", String::from_utf8_lossy(&data));
+    }
+
+    fn receive_rpc_error(
+        &mut self,
+        peer: String,
+        request_id: RequestId,
+        code: u8,
+        message: String,
+    ) {
+        println!("Rust: received rpc error {}", code);
+        println!("peer={:?}", peer);
+        println!("request_id={:?}", request_id);
+        println!("message={:?}", message);
+    }
+
+    fn rpc_failed(
+        &mut self,
+        method: String,
+        peer: String,
+        request_id: RequestId,
+        kind: RpcErrorKind,
+        error: String,
+    ) {
+        println!("Rust: rpc request failed {:?}", kind);
+        println!("method={:?}", method);
+        println!("peer={:?}", peer);
+        println!("request_id={:?}", request_id);
+        println!("error={:?}", error);
+    }
+
+    fn receive_goodbye(&mut self, peer: String, reason: GoodbyeReason) {
+        println!("Rust: received goodbye {:?}", reason);
+        println!("peer={:?}", peer);
+    }
+
+    fn check_status(&mut self, peer: String, status: Vec<u8>) -> bool {
+        let compatible = status == STATUS;
+        println!(
+            "Rust: checked status {:?}",
+            String::from_utf8_lossy(&status)
+        );
+        println!("peer={:?}", peer);
+        println!("compatible={:?}", compatible);
+        compatible
+    }
 }